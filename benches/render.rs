@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dgg::chat::{features::Flairs, message::ChatMessage, state::State};
+use dgg::ui::{
+    emotes::EmoteList,
+    layout::LayoutNode,
+    line_cache::LineCache,
+    render,
+    suggester::Suggestor,
+    window::{WindowList, WindowType},
+};
+use tui::{backend::TestBackend, Terminal};
+
+const WORDS: [&str; 8] = [
+    "PEPE",
+    "hello",
+    "https://destiny.gg",
+    "OMEGALUL:wide",
+    ">greentext",
+    "a_rather_long_word_that_needs_wrapping",
+    "destiny",
+    "YEE",
+];
+
+fn message(i: usize) -> ChatMessage {
+    let text: Vec<&str> = (0..i % 30 + 1)
+        .map(|j| WORDS[(i + j) % WORDS.len()])
+        .collect();
+    let mut msg = ChatMessage::from_string(format!("chatter{}", i % 500), text.join(" "));
+    msg.parse("destiny", &[], &Flairs::default());
+    msg
+}
+
+// A busy chat: a new message arrives before every frame
+fn busy_chat(c: &mut Criterion) {
+    let mut state = State::new("destiny".to_string());
+    (0..state.messages.capacity()).for_each(|i| state.add_message(message(i)));
+
+    let emote_list = EmoteList::new();
+    let suggestor = Suggestor::new(&emote_list);
+    let mut windows = WindowList::new();
+    let mut cache = LineCache::new();
+    let mut terminal = Terminal::new(TestBackend::new(160, 50)).unwrap();
+
+    let mut i = 0;
+    let layout = LayoutNode::standard();
+    c.bench_function("draw busy chat", |b| {
+        b.iter(|| {
+            i += 1;
+            state.add_message(message(i));
+            terminal
+                .draw(|f| {
+                    let panes = layout.areas(f.size(), WindowType::Chat);
+                    render::draw(
+                        f,
+                        &panes,
+                        &state,
+                        &suggestor,
+                        None,
+                        &mut windows,
+                        &mut cache,
+                    )
+                    .unwrap()
+                })
+                .unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, busy_chat);
+criterion_main!(benches);
@@ -0,0 +1,2092 @@
+use std::{mem, time::Duration};
+
+use anyhow::Result;
+use crossterm::event::{Event as InputEvent, EventStream, KeyCode, KeyEvent, KeyModifiers};
+use futures::StreamExt;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::{interval, MissedTickBehavior},
+};
+use tui::{backend::Backend, Frame};
+
+use crate::{
+    chat::{
+        action::Action,
+        api::UserInfo,
+        chat_log::{self, ChatLog, LogEntry},
+        command::{self, parse_command_to_action, ModCommand},
+        features::FlairInfo,
+        filter::{self, Filter, FilterCommand},
+        message::ChatMessage,
+        search::SearchQuery,
+        state::State,
+    },
+    config::{Config, TaggedLinks, DEFAULT_PROFILE},
+    network::{parse_msg, Network, Session},
+    token::{Token, TokenStore},
+    ui::{
+        emotes::EmoteList,
+        keymap::{Feed, KeyAction, Keymap, Mode},
+        line_cache::LineCache,
+        render,
+        search::Search,
+        settings::Settings,
+        suggester::Suggestor,
+        theme::Theme,
+        window::{WindowList, WindowType},
+    },
+    ws_error::WsError,
+};
+
+// How much a pane grows or shrinks per key, in shares of its split
+const PANE_STEP: i16 = 5;
+// How many older matches a search shows from the chat log
+const LOG_MATCHES: usize = 20;
+
+/// Everything the reducer reacts to.
+/// The network, the input and the timer are separate tasks that share one channel.
+#[derive(Debug)]
+pub enum Event {
+    /// Applies to the active tab
+    Action(Action),
+    /// From the network of the tab with the id
+    Tab(usize, Action),
+    Input(InputEvent),
+    Tick,
+}
+
+pub type EventSender = UnboundedSender<Event>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// A chat connection of its own, opened with /tab.
+/// The active tab lives in the fields of the App, the others wait here until they are switched to.
+struct Tab {
+    id: usize,
+    state: State,
+    line_cache: LineCache,
+    windows: WindowList,
+    search: Option<Search>,
+    network: UnboundedSender<Action>,
+    profile: String,
+    token_store: Option<TokenStore>,
+    moderator: bool,
+    /// Messages that came in while the tab wasn't active
+    unread: usize,
+    mentions: usize,
+}
+
+impl Tab {
+    fn new(
+        id: usize,
+        state: State,
+        network: UnboundedSender<Action>,
+        profile: String,
+        token_store: Option<TokenStore>,
+    ) -> Tab {
+        Tab {
+            id,
+            state,
+            line_cache: LineCache::new(),
+            windows: WindowList::new(),
+            search: None,
+            network,
+            profile,
+            token_store,
+            moderator: false,
+            unread: 0,
+            mentions: 0,
+        }
+    }
+
+    // Holds the place of the active tab, whose fields are in the App
+    fn placeholder(id: usize) -> Tab {
+        Tab::new(
+            id,
+            State::new(String::new()),
+            unbounded_channel().0,
+            String::new(),
+            None,
+        )
+    }
+}
+
+/// The reducer, it owns the State and the UI and applies every Event to them.
+/// Requests for the network, e.g. sending a message, are passed on to the network task.
+pub struct App<'a> {
+    pub state: State,
+    suggestor: Suggestor<'a>,
+    windows: WindowList,
+    search: Option<Search>,
+    line_cache: LineCache,
+    network: UnboundedSender<Action>,
+    replaying: bool,
+    /// Where keys from the login screen are saved
+    pub token_store: Option<TokenStore>,
+    /// The profiles to switch between
+    pub config: Config,
+    /// The name of the current profile
+    pub profile: String,
+    settings: Option<Settings>,
+    /// Built from the keys of the config
+    pub keymap: Keymap,
+    show_bindings: bool,
+    /// The nick and features shown by /flairs
+    flairs: Option<(String, Vec<String>)>,
+    tabs: Vec<Tab>,
+    /// The index of the tab in the fields of the App
+    active: usize,
+    next_tab: usize,
+    /// Where the networks of new tabs send their events, without it tabs can't connect
+    pub events: Option<EventSender>,
+    /// The pane that gets resized from the keyboard
+    focus: WindowType,
+    /// Panes were toggled or resized, the layout is saved on quit
+    layout_changed: bool,
+}
+
+impl<'a> App<'a> {
+    pub fn new(
+        mut state: State,
+        emote_list: &'a EmoteList,
+        network: UnboundedSender<Action>,
+        replaying: bool,
+    ) -> App<'a> {
+        state.channel = DEFAULT_PROFILE.to_string();
+        // the replayed messages are in a log already
+        if replaying {
+            state.chat_log = None;
+        }
+        App {
+            state,
+            suggestor: Suggestor::new(emote_list),
+            windows: WindowList::new(),
+            search: None,
+            line_cache: LineCache::new(),
+            network,
+            replaying,
+            token_store: None,
+            config: Config::default(),
+            profile: DEFAULT_PROFILE.to_string(),
+            settings: None,
+            keymap: Keymap::default(),
+            show_bindings: false,
+            flairs: None,
+            tabs: vec![Tab::placeholder(0)],
+            active: 0,
+            next_tab: 1,
+            events: None,
+            focus: WindowType::Chat,
+            layout_changed: false,
+        }
+    }
+
+    pub fn update(&mut self, event: Event) -> Flow {
+        match event {
+            Event::Action(action) => self.handle_action(action),
+            Event::Tab(id, action) => match self.tabs.iter().position(|tab| tab.id == id) {
+                Some(index) if index == self.active => self.handle_action(action),
+                Some(index) => self.handle_in_background(index, action),
+                // the tab was closed
+                None => Flow::Continue,
+            },
+            Event::Input(InputEvent::Key(key)) => {
+                self.state.dirty = true;
+                self.handle_key(key)
+            }
+            // e.g. a resize
+            Event::Input(_) => {
+                self.state.dirty = true;
+                Flow::Continue
+            }
+            Event::Tick => Flow::Continue,
+        }
+    }
+
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) -> Result<()> {
+        let mut area = f.size();
+        if self.tabs.len() > 1 {
+            let titles = self.tab_titles();
+            render::draw_tabs(f, area, &titles, self.active, &self.state.theme);
+            area.y += 1;
+            area.height = area.height.saturating_sub(1);
+        }
+        let panes = self.config.layout.areas(area, self.focus);
+        render::draw(
+            f,
+            &panes,
+            &self.state,
+            &self.suggestor,
+            self.search.as_ref(),
+            &mut self.windows,
+            &mut self.line_cache,
+        )?;
+        render::draw_key_status(f, &self.keymap, &self.state.theme);
+        if self.show_bindings {
+            render::draw_bindings(f, &self.keymap, &self.state.theme);
+        }
+        if let Some((nick, features)) = &self.flairs {
+            render::draw_flairs(f, nick, features, &self.state.flairs, &self.state.theme);
+        }
+        if let Some(settings) = &self.settings {
+            render::draw_settings(f, settings, &self.state.theme);
+        }
+        self.state.dirty = false;
+        Ok(())
+    }
+
+    pub fn handle_action(&mut self, action: Action) -> Flow {
+        self.state.add_debug(action.to_string());
+        match action {
+            Action::RecvMsg(mut chat_msg) => {
+                chat_msg.parse(
+                    &self.state.username,
+                    &self.state.highlights,
+                    &self.state.flairs,
+                );
+                self.state.log(LogEntry::received(&chat_msg));
+                if !self.state.is_ignored(&chat_msg.name) {
+                    if chat_msg.mentioned && !chat_msg.own_message {
+                        self.state.bell |= self.config.notifications.bell;
+                    }
+                    self.state
+                        .add_chat_message(chat_msg, self.suggestor.emote_list)
+                }
+            }
+            Action::Whisper(msg) => {
+                if !self.state.is_ignored(&msg.name) {
+                    self.state.bell |= self.config.notifications.bell;
+                    self.state.add_whisper(msg)
+                }
+            }
+            Action::SendMsg(msg) => self.send_chat_message(msg),
+            Action::Moderate(command) => self.moderate(command),
+            Action::Stalk(..)
+            | Action::GetChatHistory
+            | Action::GetMe
+            | Action::GetFlairs(_)
+            | Action::GetEmbeds
+            | Action::Connect(_)
+            | Action::Login(_) => self.request(action),
+            Action::Profile(None) => self.list_profiles(),
+            Action::Profile(Some(name)) => self.switch_profile(name),
+            Action::Tab(None) => self.list_tabs(),
+            Action::Tab(Some(name)) => self.open_tab(name),
+            Action::CloseTab => self.close_tab(),
+            Action::LoginRequired(reason) => self.state.login = Some(reason),
+            Action::LoggedIn(token, nick) => self.logged_in(token, nick),
+            Action::QuitApp => {
+                self.request(Action::QuitApp);
+                for tab in &self.tabs {
+                    let _ = tab.network.send(Action::QuitApp);
+                }
+                // there is no chat left to show an error in
+                if self.layout_changed {
+                    let _ = self.config.save_to_config_file();
+                }
+                return Flow::Quit;
+            }
+            Action::Info(msg) => self.state.add_info(msg),
+            Action::Error(msg) => self.state.add_error(msg),
+            Action::Help(command) => command::help(command.as_deref(), self.state.is_moderator())
+                .into_iter()
+                .for_each(|line| self.state.add_info(line)),
+            Action::Search(query) => {
+                self.search = Some(Search::from_query(query));
+                self.jump_to_match();
+            }
+            Action::LogMatches(query, found) => self.show_log_matches(query, found),
+            Action::Settings => self.settings = Some(Settings::new(self.config.clone())),
+            Action::Flairs(nick) => self.show_flairs(&nick),
+            Action::Filter(command) => self.filter(command),
+            Action::TogglePane(window_type) => self.toggle_pane(window_type),
+            Action::SystemMessages(name, lines) => lines.into_iter().for_each(|line| {
+                self.state
+                    .add_message(ChatMessage::from_string(name.to_string(), line))
+            }),
+            Action::ChatHistory(chat_history) => self.add_chat_history(chat_history),
+            Action::FlairsLoaded(flairs) => self.update_flairs(flairs),
+            Action::Me(me) => self.set_me(me),
+            Action::UserJoin(mut user) => {
+                user.parse_flair(&self.state.flairs);
+                self.state.ul.add(user)
+            }
+            Action::UserQuit(user) => self.state.ul.remove(user),
+            Action::UsersInit(mut user_list) => {
+                for user in user_list.users.iter_mut() {
+                    user.parse_flair(&self.state.flairs);
+                }
+                self.state.ul.append(&mut user_list)
+            }
+            Action::Mute => (),
+            Action::Unmute => (),
+            Action::Ban => (),
+            Action::Unban => (),
+            Action::Subonly => (),
+            Action::Broadcast => (),
+            Action::PrivMsg => (),
+            Action::Ping => (),
+            Action::Pong => (),
+            Action::Refresh => (),
+            Action::Binary => (),
+            Action::Err(WsError::NeedLogin) if !self.state.anonymous => {
+                self.state.login = Some("destiny.gg wants a login for that.".to_string())
+            }
+            Action::Err(ws_err) => self.state.add_error(ws_err.to_string()),
+            Action::Unreachable(un_msg) => self
+                .state
+                .add_error(format!("NETWORK: Unreachable = {}", un_msg)),
+        }
+
+        // e.g. the user list changed
+        self.state.dirty = true;
+        Flow::Continue
+    }
+
+    // Passes the action on to the network task
+    fn request(&mut self, action: Action) {
+        if self.network.send(action).is_err() {
+            self.state
+                .add_error("The network is not running.".to_string());
+        }
+    }
+
+    fn logged_in(&mut self, token: Token, nick: String) {
+        // Keys from the login screen are kept for the next start
+        if self.state.login.take().is_some() {
+            match self.token_store.as_ref().map(|store| store.save(&token)) {
+                Some(Ok(source)) => self
+                    .state
+                    .add_info(format!("Saved the login key in {}.", source)),
+                Some(Err(err)) => self
+                    .state
+                    .add_error(format!("Could not save the login key: {}", err)),
+                None => (),
+            }
+        }
+
+        self.state.add_info(format!("Logged in as {}.", nick));
+    }
+
+    // The account decides the name to highlight and whether mod commands are available
+    fn set_me(&mut self, me: UserInfo) {
+        let name = &self.state.username;
+        if !name.is_empty() && !name.eq_ignore_ascii_case(&me.nick) {
+            self.state.add_error(format!(
+                "The name {} in the config doesn't match the account {} of the login key, using {}.",
+                name, me.nick, me.nick
+            ));
+        }
+
+        self.state.username = me.nick.to_string();
+        self.suggestor.moderator = me.is_moderator();
+        if me.is_moderator() {
+            self.state
+                .add_info("Moderator commands are available, see /help.".to_string());
+        }
+        self.state.me = Some(me);
+    }
+
+    fn list_profiles(&mut self) {
+        let lines: Vec<String> = self
+            .config
+            .profile_names()
+            .into_iter()
+            .map(|name| match name == self.profile {
+                true => format!("{} (current)", name),
+                false => name.to_string(),
+            })
+            .collect();
+        lines.into_iter().for_each(|line| self.state.add_info(line));
+    }
+
+    // Filters added or removed with /filter are saved to the config right away.
+    // Every tab filters with the rules of the config, in the same order.
+    fn filter(&mut self, command: FilterCommand) {
+        match command {
+            FilterCommand::Add(rule) => {
+                let filter = match Filter::new(rule.clone()) {
+                    Ok(filter) => filter,
+                    Err(err) => return self.state.add_error(err.to_string()),
+                };
+                for index in 0..self.tabs.len() {
+                    self.with_tab(index, |app| app.state.filters.push(filter.clone()));
+                }
+                self.config.filters.push(rule.clone());
+                let number = self.config.filters.len();
+                self.state
+                    .add_info(format!("Added filter {}: {}", number, rule));
+                self.save_filters();
+            }
+            FilterCommand::List if self.state.filters.is_empty() => self
+                .state
+                .add_info("There are no filters, add one with /filter add.".to_string()),
+            FilterCommand::List => {
+                let lines: Vec<String> = self
+                    .state
+                    .filters
+                    .iter()
+                    .enumerate()
+                    .map(|(i, filter)| {
+                        format!("{}. {} ({} hidden)", i + 1, filter.rule, filter.hidden)
+                    })
+                    .collect();
+                lines.into_iter().for_each(|line| self.state.add_info(line));
+            }
+            FilterCommand::Remove(number) => {
+                if number == 0 || number > self.config.filters.len() {
+                    return self
+                        .state
+                        .add_error(format!("There is no filter {}.", number));
+                }
+                let rule = self.config.filters.remove(number - 1);
+                for index in 0..self.tabs.len() {
+                    self.with_tab(index, |app| {
+                        let filters = &mut app.state.filters;
+                        if let Some(position) = filters.iter().position(|f| f.rule == rule) {
+                            filters.remove(position);
+                        }
+                    });
+                }
+                self.state
+                    .add_info(format!("Removed filter {}: {}", number, rule));
+                self.save_filters();
+            }
+        }
+    }
+
+    fn save_filters(&mut self) {
+        if let Err(err) = self.config.save_to_config_file() {
+            self.state
+                .add_error(format!("Could not save the filters: {}", err));
+        }
+    }
+
+    // Reconnects as the profile, overrides like DGG_TOKEN only count for the first one
+    fn switch_profile(&mut self, name: String) {
+        if self.replaying {
+            self.state
+                .add_error("Profiles can't be switched during a replay.".to_string());
+            return;
+        }
+        let profile = match self.config.profile(Some(&name)) {
+            Ok((_, profile)) => profile,
+            Err(err) => return self.state.add_error(err.to_string()),
+        };
+
+        self.token_store = self.config.token_file(&name).map(TokenStore::new);
+        let token = match (&self.token_store, profile.anonymous) {
+            (Some(store), false) => match store.load() {
+                Ok(token) => token.map(|(token, _)| token),
+                Err(err) => {
+                    self.state.add_error(err.to_string());
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        self.state.set_profile(&profile);
+        self.state.channel = name.to_string();
+        self.state.login = None;
+        self.suggestor.moderator = false;
+        self.state
+            .add_info(format!("Switching to the profile {}.", name));
+        self.profile = name;
+        self.request(Action::Connect(Session {
+            server: profile.server,
+            token,
+            anonymous: profile.anonymous,
+        }));
+    }
+
+    // The name of every tab with its unread messages and mentions
+    fn tab_titles(&self) -> Vec<(&str, usize, usize)> {
+        self.tabs
+            .iter()
+            .enumerate()
+            .map(|(i, tab)| match i == self.active {
+                true => (self.profile.as_str(), 0, 0),
+                false => (tab.profile.as_str(), tab.unread, tab.mentions),
+            })
+            .collect()
+    }
+
+    fn list_tabs(&mut self) {
+        let lines: Vec<String> = self
+            .tab_titles()
+            .into_iter()
+            .enumerate()
+            .map(
+                |(i, (name, unread, mentions))| match (i == self.active, mentions) {
+                    (true, _) => format!("{}. {} (current)", i + 1, name),
+                    (false, 0) => format!("{}. {} ({} unread)", i + 1, name, unread),
+                    (false, _) => format!(
+                        "{}. {} ({} unread, {} mentions)",
+                        i + 1,
+                        name,
+                        unread,
+                        mentions
+                    ),
+                },
+            )
+            .collect();
+        lines.into_iter().for_each(|line| self.state.add_info(line));
+    }
+
+    // Switches to the tab of the profile or connects with it in a new one
+    fn open_tab(&mut self, name: String) {
+        let open = self
+            .tab_titles()
+            .iter()
+            .position(|(tab, _, _)| *tab == name);
+        if let Some(index) = open {
+            return self.switch_tab(index);
+        }
+        if self.replaying {
+            return self
+                .state
+                .add_error("Tabs can't be opened during a replay.".to_string());
+        }
+        if let Err(err) = self.config.profile(Some(&name)) {
+            return self.state.add_error(err.to_string());
+        }
+
+        let id = self.next_tab;
+        self.next_tab += 1;
+        let state = self.tab_state(id, &name);
+        let network = self.start_network(id);
+        self.tabs
+            .push(Tab::new(id, state, network, String::new(), None));
+        self.switch_tab(self.tabs.len() - 1);
+        self.switch_profile(name);
+        self.request(Action::GetChatHistory);
+        self.request(Action::GetFlairs(None));
+    }
+
+    // A new tab starts with the settings and the flairs of the active one
+    fn tab_state(&self, id: usize, profile: &str) -> State {
+        let mut state = State::new(String::new());
+        state.tab = id;
+        state.set_scrollback(&self.config.scrollback);
+        state.set_tagged_links(self.config.links.tagged);
+        state.combos = self.config.chat.combos;
+        state.filters = filter::compile(&self.config.filters)
+            .expect("The filters are validated with the config");
+        state.theme = self.state.theme.clone();
+        state.flairs = self.state.flairs.clone();
+        match ChatLog::for_profile(&self.config, profile) {
+            Ok(chat_log) => state.chat_log = chat_log,
+            Err(err) => state.add_error(format!("Could not open the chat log: {}", err)),
+        }
+        state
+    }
+
+    // Every tab talks to a network task of its own
+    fn start_network(&self, id: usize) -> UnboundedSender<Action> {
+        let (requests, request_recv) = unbounded_channel();
+        if let Some(events) = &self.events {
+            tokio::spawn(Network::new(events.clone(), id).run(None, request_recv));
+        }
+        requests
+    }
+
+    // The connection of the tab ends with it
+    fn close_tab(&mut self) {
+        if self.tabs.len() == 1 {
+            return self.state.add_error(
+                "The last tab can't be closed, /profile switches its connection.".to_string(),
+            );
+        }
+        self.request(Action::QuitApp);
+        let closed = self.active;
+        self.switch_tab(if closed == 0 { 1 } else { closed - 1 });
+        let tab = self.tabs.remove(closed);
+        if self.active > closed {
+            self.active -= 1;
+        }
+        self.state.mentions.close_tab(tab.id);
+        self.state
+            .add_info(format!("Closed the tab {}.", tab.profile));
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active {
+            return;
+        }
+        self.swap_tab(self.active);
+        self.swap_tab(index);
+        self.active = index;
+        let tab = &mut self.tabs[index];
+        tab.unread = 0;
+        tab.mentions = 0;
+        self.suggestor.clear();
+        self.state.dirty = true;
+    }
+
+    // Trades the fields of the App with the ones kept in the tab
+    fn swap_tab(&mut self, index: usize) {
+        let tab = &mut self.tabs[index];
+        mem::swap(&mut self.state, &mut tab.state);
+        // the mentions are of the whole session and stay in the fields of the App
+        mem::swap(&mut self.state.mentions, &mut tab.state.mentions);
+        mem::swap(&mut self.line_cache, &mut tab.line_cache);
+        mem::swap(&mut self.windows, &mut tab.windows);
+        mem::swap(&mut self.search, &mut tab.search);
+        mem::swap(&mut self.network, &mut tab.network);
+        mem::swap(&mut self.profile, &mut tab.profile);
+        mem::swap(&mut self.token_store, &mut tab.token_store);
+        mem::swap(&mut self.suggestor.moderator, &mut tab.moderator);
+    }
+
+    // Runs f with the tab in the fields of the App, like it was the active one
+    fn with_tab<T>(&mut self, index: usize, f: impl FnOnce(&mut Self) -> T) -> T {
+        if index == self.active {
+            return f(self);
+        }
+        self.swap_tab(index);
+        let result = f(self);
+        self.swap_tab(index);
+        result
+    }
+
+    // Counts the new messages of a tab that isn't shown, its mentions ring the bell anyway
+    fn handle_in_background(&mut self, index: usize, action: Action) -> Flow {
+        let (flow, new, mentions, bell) = self.with_tab(index, |app| {
+            let messages = &app.state.messages;
+            let before = messages.evicted() + messages.len();
+            let flow = app.handle_action(action);
+            let messages = &app.state.messages;
+            let new = (messages.evicted() + messages.len()).saturating_sub(before);
+            let mentions = messages
+                .iter()
+                .rev()
+                .take(new)
+                .filter(|msg| msg.mentioned && !msg.own_message)
+                .count();
+            (flow, new, mentions, mem::take(&mut app.state.bell))
+        });
+
+        let tab = &mut self.tabs[index];
+        tab.unread += new;
+        tab.mentions += mentions;
+        self.state.bell |= bell;
+        self.state.dirty |= new > 0;
+        flow
+    }
+
+    // Users who left are still found by their last message
+    fn show_flairs(&mut self, nick: &str) {
+        let user = self
+            .state
+            .ul
+            .get(nick)
+            .map(|user| (user.name.to_string(), user.features.clone()));
+        let message = || {
+            self.state
+                .messages
+                .iter()
+                .rev()
+                .find(|msg| msg.name.eq_ignore_ascii_case(nick))
+                .map(|msg| (msg.name.to_string(), msg.features.to_vec()))
+        };
+        match user.or_else(message) {
+            Some(flairs) => self.flairs = Some(flairs),
+            None => self
+                .state
+                .add_error(format!("{} is not in the chat.", nick)),
+        }
+    }
+
+    // Flairs of messages and users that are already there get the new colours and badges too
+    fn update_flairs(&mut self, flairs: Vec<FlairInfo>) {
+        self.state.flairs.update(flairs);
+        let flairs = &self.state.flairs;
+        for msg in self.state.messages.iter_mut() {
+            msg.flairs = flairs.parse(&msg.features);
+        }
+        for user in self.state.ul.users.iter_mut() {
+            user.parse_flair(flairs);
+        }
+        self.line_cache = LineCache::new();
+    }
+
+    fn moderate(&mut self, command: ModCommand) {
+        if !self.state.is_moderator() {
+            self.state
+                .add_error("Only moderators can do that.".to_string());
+        } else if self.replaying {
+            self.state
+                .add_error("Moderation is not possible during a replay.".to_string());
+        } else {
+            self.request(Action::Moderate(command));
+        }
+    }
+
+    fn send_chat_message(&mut self, msg: String) {
+        if self.state.anonymous {
+            self.state.add_error(WsError::NeedLogin.to_string());
+            return;
+        }
+        if self.replaying {
+            self.state
+                .add_error("Messages can't be sent during a replay.".to_string());
+            return;
+        }
+
+        // logged once the server echoes it
+        self.request(Action::SendMsg(msg));
+    }
+
+    fn add_chat_history(&mut self, chat_history: Vec<String>) {
+        let start = chat_history.len().saturating_sub(50);
+        // History messages were logged in an earlier session already,
+        // so they skip the chat log
+        chat_history[start..]
+            .iter()
+            .for_each(|msg| match parse_msg(msg) {
+                Action::RecvMsg(mut chat_msg) => {
+                    chat_msg.parse(
+                        &self.state.username,
+                        &self.state.highlights,
+                        &self.state.flairs,
+                    );
+                    if !self.state.is_ignored(&chat_msg.name) {
+                        self.state
+                            .add_chat_message(chat_msg, self.suggestor.emote_list);
+                    }
+                }
+                action => {
+                    self.handle_action(action);
+                }
+            });
+        self.state.loaded = true;
+    }
+
+    // Looks for matches older than every message in the chat in the chat log.
+    // The files are read in the background, the matches come back as LogMatches.
+    fn search_log(&mut self, query: String) {
+        let (dir, events) = match (&self.state.chat_log, &self.events) {
+            (Some(chat_log), Some(events)) => (chat_log.dir().to_path_buf(), events.clone()),
+            _ => return,
+        };
+        // the log keeps whole seconds
+        let before = self
+            .state
+            .messages
+            .front()
+            .map_or(u64::MAX, |msg| msg.timestamp / 1000 * 1000);
+        let username = self.state.username.to_string();
+        let tab = self.state.tab;
+
+        tokio::task::spawn_blocking(move || {
+            let parsed = SearchQuery::parse(&query);
+            let found = chat_log::search(&dir, &parsed, &username, before, LOG_MATCHES);
+            let _ = events.send(Event::Tab(tab, Action::LogMatches(query, found)));
+        });
+    }
+
+    // Shows the matches in the search bar, unless the search changed in the meantime
+    fn show_log_matches(&mut self, query: String, found: Result<Vec<LogEntry>>) {
+        let search = match self.search.as_mut() {
+            Some(search) if search.input == query => search,
+            _ => return,
+        };
+        match found {
+            Ok(entries) => search.log_matches = Some(entries),
+            Err(err) => self
+                .state
+                .add_error(format!("Could not search the chat log: {}", err)),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Flow {
+        if self.state.login.is_some() {
+            return self.handle_login_key(key);
+        }
+
+        if self.settings.is_some() {
+            self.handle_settings_key(key);
+            return Flow::Continue;
+        }
+
+        if self.show_bindings || self.flairs.is_some() {
+            self.show_bindings = false;
+            self.flairs = None;
+            return Flow::Continue;
+        }
+
+        if self.search.is_some() {
+            if self.handle_search_key(key) {
+                return Flow::Continue;
+            }
+            if key.code == KeyCode::Esc || key.code == KeyCode::Enter {
+                self.search = None;
+                return Flow::Continue;
+            }
+        }
+
+        let typing = !self.state.chat_input.current_message.is_empty();
+        match self.keymap.feed(key, typing) {
+            Feed::Action(action) => self.handle_key_action(action),
+            Feed::Unbound if self.keymap.mode == Mode::Insert => {
+                self.edit_input(key);
+                Flow::Continue
+            }
+            Feed::Pending | Feed::Unbound => Flow::Continue,
+        }
+    }
+
+    // Typing isn't bound, every other key goes through the keymap
+    fn edit_input(&mut self, key: KeyEvent) {
+        let state = &mut self.state;
+        let has_modifier = key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char(_) if has_modifier => return,
+            // Only commands can be typed while reading anonymously
+            KeyCode::Char(c)
+                if state.anonymous && state.chat_input.current_message.is_empty() && c != '/' =>
+            {
+                return
+            }
+            KeyCode::Char(c) => state.chat_input.current_message.push(c),
+            KeyCode::Backspace => {
+                state.chat_input.current_message.pop();
+            }
+            _ => return,
+        }
+        self.suggestor
+            .update(&state.ul, &state.chat_input, &state.messages);
+    }
+
+    fn handle_key_action(&mut self, action: KeyAction) -> Flow {
+        let state = &mut self.state;
+        let suggestor = &mut self.suggestor;
+        let windows = &mut self.windows;
+
+        match action {
+            KeyAction::Quit => return self.handle_action(Action::QuitApp),
+            KeyAction::Send => return self.send_input(),
+            // Autocomplete: delete the current word and add the suggestion
+            KeyAction::Complete if !suggestor.suggestions.is_empty() => {
+                state.chat_input.delete_current_word();
+                state
+                    .chat_input
+                    .current_message
+                    .push_str(&suggestor.select_next())
+            }
+            KeyAction::CompletePrev if !suggestor.suggestions.is_empty() => {
+                state.chat_input.delete_current_word();
+                state
+                    .chat_input
+                    .current_message
+                    .push_str(&suggestor.select_prev())
+            }
+            KeyAction::Complete | KeyAction::CompletePrev => (),
+            KeyAction::DeleteWord => {
+                state.chat_input.delete_current_word();
+                suggestor.clear();
+            }
+            KeyAction::ClearInput => {
+                state.chat_input.current_message.clear();
+                suggestor.clear();
+            }
+            KeyAction::HistoryPrev => state.chat_input.next(),
+            KeyAction::HistoryNext => state.chat_input.prev(),
+            KeyAction::Search => {
+                self.search = Some(Search::new());
+                suggestor.clear();
+            }
+            // with the mentions pane focused they pick the mention the chat jumps to
+            KeyAction::ScrollUp if self.focus == WindowType::Mentions => self.select_mention(true),
+            KeyAction::ScrollDown if self.focus == WindowType::Mentions => {
+                self.select_mention(false)
+            }
+            KeyAction::ScrollUp => windows.get_mut(WindowType::Chat).scroll(-2),
+            KeyAction::ScrollDown => windows.get_mut(WindowType::Chat).scroll(2),
+            KeyAction::ScrollTop => windows.get_mut(WindowType::Chat).scroll_to(0),
+            KeyAction::ScrollBottom => {
+                state.mentions.clear_selection();
+                windows.get_mut(WindowType::Mentions).auto_scroll = true;
+                windows.get_mut(WindowType::Chat).auto_scroll = true
+            }
+            KeyAction::ToggleDebug => self.toggle_pane(WindowType::Debug),
+            KeyAction::ToggleUserList => self.toggle_pane(WindowType::UserList),
+            KeyAction::Settings => return self.handle_action(Action::Settings),
+            KeyAction::ShowBindings => self.show_bindings = true,
+            KeyAction::Reveal => self.reveal(),
+            KeyAction::NextTab => self.switch_tab((self.active + 1) % self.tabs.len()),
+            KeyAction::PrevTab => {
+                self.switch_tab((self.active + self.tabs.len() - 1) % self.tabs.len())
+            }
+            KeyAction::FocusNext => self.focus_next(),
+            KeyAction::GrowPane => self.resize_pane(PANE_STEP),
+            KeyAction::ShrinkPane => self.resize_pane(-PANE_STEP),
+            KeyAction::NormalMode => {
+                self.keymap.mode = Mode::Normal;
+                suggestor.clear();
+            }
+            KeyAction::InsertMode => self.keymap.mode = Mode::Insert,
+        }
+
+        Flow::Continue
+    }
+
+    // Shows or hides a pane of the layout, the focus goes back to the chat with a hidden pane
+    fn toggle_pane(&mut self, window_type: WindowType) {
+        if window_type == WindowType::Chat {
+            return self
+                .state
+                .add_error("The chat can't be hidden.".to_string());
+        }
+        if !self.config.layout.toggle(window_type) {
+            return self.state.add_error(format!(
+                "The layout has no {} pane, see layout in the config.",
+                window_type.name()
+            ));
+        }
+        self.layout_changed = true;
+        if !self.config.layout.panes(true).contains(&self.focus) {
+            self.focus = WindowType::Chat;
+        }
+    }
+
+    fn focus_next(&mut self) {
+        let panes = self.config.layout.panes(true);
+        let next = panes
+            .iter()
+            .position(|pane| *pane == self.focus)
+            .map_or(0, |index| (index + 1) % panes.len());
+        self.focus = panes.get(next).copied().unwrap_or(WindowType::Chat);
+    }
+
+    fn resize_pane(&mut self, delta: i16) {
+        if self.config.layout.resize(self.focus, delta) {
+            self.layout_changed = true;
+        }
+    }
+
+    // Selects the older or newer mention and jumps to it, in the tab it came in on
+    fn select_mention(&mut self, older: bool) {
+        let mentions = &mut self.state.mentions;
+        let mention = match older {
+            true => mentions.select_prev(),
+            false => mentions.select_next(),
+        };
+        let (id, channel, index) = match mention {
+            Some(mention) => (mention.tab, mention.channel.to_string(), mention.index),
+            None => return,
+        };
+
+        match id.and_then(|id| self.tabs.iter().position(|tab| tab.id == id)) {
+            Some(tab) => self.switch_tab(tab),
+            None => {
+                return self
+                    .state
+                    .add_error(format!("The tab of {} was closed.", channel))
+            }
+        }
+        if let Some(selected) = self.state.mentions.selected() {
+            self.windows
+                .get_mut(WindowType::Mentions)
+                .scroll_to(selected);
+        }
+        match self.state.messages.relative(index) {
+            Some(index) => self.windows.get_mut(WindowType::Chat).scroll_to(index),
+            None => self.state.add_info(
+                "The chat dropped that message already, it's only left in the mentions."
+                    .to_string(),
+            ),
+        }
+    }
+
+    // Reveals the newest masked message in view, once there are none left they get masked again
+    fn reveal(&mut self) {
+        if self.state.tagged_links == TaggedLinks::Hide {
+            return self.state.add_info(
+                "Spoilers and tagged links are always hidden, see links.tagged in the settings."
+                    .to_string(),
+            );
+        }
+
+        let window = self.windows.get(WindowType::Chat);
+        let messages = &mut self.state.messages;
+        let end = match window.auto_scroll {
+            true => messages.len(),
+            false => (window.scroll as usize + window.height).min(messages.len()),
+        };
+        let evicted = messages.evicted();
+        match messages.iter().take(end).rposition(ChatMessage::is_masked) {
+            Some(index) => {
+                if let Some(msg) = messages.get_mut(index) {
+                    msg.revealed = true;
+                }
+                self.line_cache.remove(evicted + index);
+            }
+            None => {
+                for (index, msg) in messages.iter_mut().take(end).enumerate() {
+                    if msg.revealed {
+                        msg.revealed = false;
+                        self.line_cache.remove(evicted + index);
+                    }
+                }
+            }
+        }
+        self.state.dirty = true;
+    }
+
+    // Sends the message or runs the command in the input
+    fn send_input(&mut self) -> Flow {
+        let state = &mut self.state;
+        self.suggestor.clear();
+        let msg = state.chat_input.current_message.to_string();
+        if msg.starts_with(":q") {
+            return self.handle_action(Action::QuitApp);
+        }
+
+        state.chat_input.add();
+        if msg.starts_with('/') {
+            match parse_command_to_action(&msg) {
+                Ok(action) => return self.handle_action(action),
+                Err(err) => state.add_error(err.to_string()),
+            }
+        } else {
+            return self.handle_action(Action::SendMsg(msg));
+        }
+
+        Flow::Continue
+    }
+
+    fn handle_login_key(&mut self, key: KeyEvent) -> Flow {
+        let input = &mut self.state.chat_input.current_message;
+        match key.code {
+            KeyCode::Esc => return self.handle_action(Action::QuitApp),
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                // The key never goes into the input history
+                let input = std::mem::take(input);
+                match Token::parse(&input) {
+                    Ok(token) => {
+                        self.state.login = Some("Checking the login key...".to_string());
+                        self.request(Action::Login(token));
+                    }
+                    Err(err) => self.state.login = Some(err.to_string()),
+                }
+            }
+            _ => (),
+        }
+
+        Flow::Continue
+    }
+
+    fn handle_settings_key(&mut self, key: KeyEvent) {
+        let settings = match self.settings.as_mut() {
+            Some(settings) => settings,
+            None => return,
+        };
+
+        if let Some(editing) = settings.editing.as_mut() {
+            match key.code {
+                KeyCode::Char(c) => editing.push(c),
+                KeyCode::Backspace => {
+                    editing.pop();
+                }
+                KeyCode::Enter => settings.finish_edit(),
+                KeyCode::Esc => {
+                    settings.editing = None;
+                    settings.error = None;
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => settings.select_prev(),
+            KeyCode::Down | KeyCode::Char('j') => settings.select_next(),
+            KeyCode::Enter | KeyCode::Char(' ') => settings.activate(),
+            KeyCode::Char('s') => self.save_settings(),
+            KeyCode::Esc => self.settings = None,
+            _ => (),
+        }
+    }
+
+    // Saves the edited config and applies what can change while running
+    fn save_settings(&mut self) {
+        let settings = match self.settings.take() {
+            Some(settings) => settings,
+            None => return,
+        };
+        if let Err(err) = settings.config.save_to_config_file() {
+            let mut settings = settings;
+            settings.error = Some(format!("Could not save: {}", err));
+            self.settings = Some(settings);
+            return;
+        }
+
+        let config = settings.config;
+        let theme = match Theme::load(&config.theme, config.dir()) {
+            Ok(theme) => Some(theme),
+            Err(err) => {
+                self.state
+                    .add_error(format!("Could not load the theme: {}", err));
+                None
+            }
+        };
+        for index in 0..self.tabs.len() {
+            let first = self.tabs[index].id == 0;
+            self.with_tab(index, |app| {
+                app.apply_settings(&config, theme.as_ref(), first)
+            });
+        }
+        self.keymap = Keymap::new(&config.keys).expect("The keys are validated with the config");
+        self.config = config;
+        self.state.add_info("Saved the settings.".to_string());
+    }
+
+    // Applies the settings to the tab in the fields of the App
+    fn apply_settings(&mut self, config: &Config, theme: Option<&Theme>, first: bool) {
+        self.state.set_scrollback(&config.scrollback);
+        self.state.set_tagged_links(config.links.tagged);
+        self.state.combos = config.chat.combos;
+        if let Some(theme) = theme {
+            self.state.theme = theme.clone();
+        }
+        // the colours are baked into the cached lines
+        self.line_cache = LineCache::new();
+        if self.replaying {
+            return;
+        }
+        // tabs opened with /tab log to a directory of their own
+        let chat_log = match first {
+            true => ChatLog::from_config(config),
+            false => ChatLog::for_profile(config, &self.profile),
+        };
+        match chat_log {
+            Ok(chat_log) => self.state.chat_log = chat_log,
+            Err(err) => self
+                .state
+                .add_error(format!("Could not open the chat log: {}", err)),
+        }
+    }
+
+    // Handles the keys of an open search, returns false if the key wasn't used
+    fn handle_search_key(&mut self, key: KeyEvent) -> bool {
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return false,
+        };
+
+        if search.typing {
+            match key.code {
+                KeyCode::Char(c) => search.push(c),
+                KeyCode::Backspace => search.pop(),
+                KeyCode::Enter => {
+                    search.confirm();
+                    self.jump_to_match();
+                }
+                _ => return false,
+            }
+            return true;
+        }
+
+        let jumped = match key.code {
+            KeyCode::Char('n') => search.older(&self.state.messages),
+            KeyCode::Char('N') => search.newer(&self.state.messages),
+            _ => return false,
+        };
+        if let Some(index) = jumped {
+            scroll_to_match(index, &self.state, &mut self.windows);
+        }
+        true
+    }
+
+    // Jumps to the newest match and looks for older ones in the chat log
+    fn jump_to_match(&mut self) {
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return,
+        };
+
+        if let Some(index) = search.older(&self.state.messages) {
+            scroll_to_match(index, &self.state, &mut self.windows);
+        }
+        let query = search.input.to_string();
+        self.search_log(query);
+    }
+}
+
+fn scroll_to_match(index: usize, state: &State, windows: &mut WindowList) {
+    if let Some(index) = state.messages.relative(index) {
+        windows.get_mut(WindowType::Chat).scroll_to(index);
+    }
+}
+
+/// Sends the terminal input to the reducer.
+pub async fn read_input(events: EventSender) {
+    let mut input = EventStream::new();
+    while let Some(Ok(event)) = input.next().await {
+        if events.send(Event::Input(event)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends a tick every rate, the reducer draws on ticks when something changed.
+pub async fn tick(events: EventSender, rate: Duration) {
+    let mut timer = interval(rate);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        timer.tick().await;
+        if events.send(Event::Tick).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use tui::{backend::TestBackend, Terminal};
+
+    use super::*;
+    use crate::{
+        chat::{features::Flairs, mentions::MentionKind, user::UserList},
+        config::{KeyConfig, LogConfig, Profile},
+        replay::{self, Frame},
+        test_util::temp_dir,
+        ui::keymap::Preset,
+    };
+
+    fn app(emote_list: &EmoteList, replaying: bool) -> (App<'_>, UnboundedReceiver<Action>) {
+        let (network, requests) = unbounded_channel();
+        let state = State::new("destiny".to_string());
+        (App::new(state, emote_list, network, replaying), requests)
+    }
+
+    fn key(code: KeyCode) -> Event {
+        Event::Input(InputEvent::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+    }
+
+    // Every line the chat log in dir has
+    fn logged(dir: &std::path::Path) -> Vec<String> {
+        chat_log::log_files(dir)
+            .unwrap()
+            .iter()
+            .flat_map(|file| {
+                let content = std::fs::read_to_string(file).unwrap();
+                content.lines().map(str::to_string).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn log_to(app: &mut App, dir: &std::path::Path) {
+        let config = LogConfig {
+            enabled: true,
+            ..LogConfig::default()
+        };
+        app.state.chat_log = Some(ChatLog::new(&config, dir.to_path_buf()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn replays_are_not_logged() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("replay-log");
+        log_to(&mut app, &dir);
+        let (network, _requests) = unbounded_channel();
+        let mut app = App::new(app.state, &emote_list, network, true);
+
+        let frames = vec![Frame::from_line("[2022-10-26 21:06:29] bob: hi destiny").unwrap()];
+        let (events, mut replayed) = unbounded_channel();
+        replay::play(frames, 0.0, events).await;
+        while let Ok(event) = replayed.try_recv() {
+            app.update(event);
+        }
+        app.handle_action(Action::Error("oops".to_string()));
+        assert!(app
+            .state
+            .messages
+            .iter()
+            .any(|msg| msg.message == "hi destiny"));
+        assert!(logged(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn log_matches_stay_out_of_the_chat() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("search-log");
+        log_to(&mut app, &dir);
+        let (events, mut found) = unbounded_channel();
+        app.events = Some(events);
+
+        let mut old = ChatMessage::from_string("bob".to_string(), "pepe then".to_string());
+        old.timestamp -= 60_000;
+        app.state.log(LogEntry::received(&old));
+        let msg = ChatMessage::from_string("bob".to_string(), "pepe now".to_string());
+        app.handle_action(Action::RecvMsg(msg));
+        let lines = app.state.messages.len();
+
+        app.handle_action(Action::Search("pepe".to_string()));
+        app.update(found.recv().await.unwrap());
+        let matches = app.search.as_ref().unwrap().log_matches.as_ref().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].data, "pepe then");
+        assert_eq!(app.state.messages.len(), lines);
+        assert_eq!(logged(&dir).len(), 2);
+
+        // they are listed above the search bar
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|f| app.draw(f).unwrap()).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("bob: pepe then"));
+        assert!(screen.contains("1 older in the chat log"));
+
+        // matches of an older search are dropped
+        app.handle_action(Action::LogMatches("nope".to_string(), Ok(Vec::new())));
+        assert_eq!(
+            app.search
+                .as_ref()
+                .unwrap()
+                .log_matches
+                .as_ref()
+                .unwrap()
+                .len(),
+            1
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn own_messages_are_logged_once() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("own-messages");
+        log_to(&mut app, &dir);
+
+        app.handle_action(Action::SendMsg("hello".to_string()));
+        let echo = ChatMessage::from_string("destiny".to_string(), "hello".to_string());
+        app.handle_action(Action::RecvMsg(echo));
+        let lines = logged(&dir);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("] destiny: hello"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn received_messages_are_added() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        app.state.dirty = false;
+        let msg = ChatMessage::from_string("bob".to_string(), "hi destiny".to_string());
+        app.update(Event::Action(Action::RecvMsg(msg)));
+        assert_eq!(app.state.messages.len(), 1);
+        assert!(app.state.messages.back().unwrap().mentioned);
+        assert!(app.state.dirty);
+    }
+
+    #[test]
+    fn requests_go_to_the_network() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        "hello".chars().for_each(|c| {
+            app.update(key(KeyCode::Char(c)));
+        });
+        app.update(key(KeyCode::Enter));
+        match requests.try_recv() {
+            Ok(Action::SendMsg(msg)) => assert_eq!(msg, "hello"),
+            action => panic!("expected SendMsg, got {:?}", action),
+        }
+        assert!(app.state.chat_input.current_message.is_empty());
+    }
+
+    #[test]
+    fn no_sending_while_replaying() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, true);
+        app.update(Event::Action(Action::SendMsg("hello".to_string())));
+        assert!(requests.try_recv().is_err());
+        assert_eq!(&*app.state.messages.back().unwrap().name, "ERROR");
+    }
+
+    #[test]
+    fn quit_stops_the_network() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        assert_eq!(app.update(key(KeyCode::Esc)), Flow::Quit);
+        assert!(matches!(requests.try_recv(), Ok(Action::QuitApp)));
+    }
+
+    #[test]
+    fn login_screen() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        app.update(Event::Action(Action::LoginRequired("No key".to_string())));
+
+        "short".chars().for_each(|c| {
+            app.update(key(KeyCode::Char(c)));
+        });
+        app.update(key(KeyCode::Enter));
+        assert!(requests.try_recv().is_err());
+        assert!(app.state.login.is_some());
+
+        "a".repeat(64).chars().for_each(|c| {
+            app.update(key(KeyCode::Char(c)));
+        });
+        app.update(key(KeyCode::Enter));
+        assert!(matches!(requests.try_recv(), Ok(Action::Login(_))));
+        // the key is never kept in the history
+        assert!(app.state.chat_input.history.is_empty());
+
+        let token = Token::parse(&"a".repeat(64)).unwrap();
+        app.update(Event::Action(Action::LoggedIn(
+            token,
+            "destiny".to_string(),
+        )));
+        assert_eq!(app.state.login, None);
+    }
+
+    #[test]
+    fn account_from_chat_me() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        app.state.username = "typo".to_string();
+        let mute = || Action::Moderate(ModCommand::Unmute("bob".to_string()));
+
+        app.update(Event::Action(mute()));
+        assert!(requests.try_recv().is_err());
+
+        let me = UserInfo {
+            nick: "Destiny".to_string(),
+            roles: vec!["USER".to_string(), "MODERATOR".to_string()],
+            ..UserInfo::default()
+        };
+        app.update(Event::Action(Action::Me(me)));
+        assert_eq!(app.state.username, "Destiny");
+        assert!(app
+            .state
+            .messages
+            .iter()
+            .any(|msg| &*msg.name == "ERROR" && msg.message.contains("typo")));
+
+        app.update(Event::Action(mute()));
+        assert!(matches!(requests.try_recv(), Ok(Action::Moderate(_))));
+    }
+
+    #[test]
+    fn anonymous_is_read_only() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        app.state.anonymous = true;
+
+        app.update(key(KeyCode::Char('h')));
+        assert!(app.state.chat_input.current_message.is_empty());
+
+        app.handle_action(Action::SendMsg("hello".to_string()));
+        assert!(requests.try_recv().is_err());
+
+        app.handle_action(Action::Err(WsError::NeedLogin));
+        assert_eq!(app.state.login, None);
+
+        "/help".chars().for_each(|c| {
+            app.update(key(KeyCode::Char(c)));
+        });
+        assert_eq!(app.state.chat_input.current_message, "/help");
+    }
+
+    #[test]
+    fn switch_profiles() {
+        let emote_list = EmoteList::new();
+        let (mut app, mut requests) = app(&emote_list, false);
+        let work = Profile {
+            name: "bob".to_string(),
+            anonymous: true,
+            ignores: vec!["Spammer".to_string()],
+            highlights: vec!["rust".to_string()],
+            ..Profile::default()
+        };
+        app.config.profiles.insert("work".to_string(), work);
+
+        app.handle_action(Action::Profile(Some("nope".to_string())));
+        assert!(requests.try_recv().is_err());
+
+        app.handle_action(Action::Profile(Some("work".to_string())));
+        assert!(matches!(
+            requests.try_recv(),
+            Ok(Action::Connect(Session {
+                anonymous: true,
+                ..
+            }))
+        ));
+        assert_eq!(app.profile, "work");
+        assert_eq!(app.state.username, "bob");
+
+        let spam = ChatMessage::from_string("spammer".to_string(), "buy".to_string());
+        app.handle_action(Action::RecvMsg(spam));
+        let msg = ChatMessage::from_string("alice".to_string(), "Rust is fun".to_string());
+        app.handle_action(Action::RecvMsg(msg));
+        let last = app.state.messages.back().unwrap();
+        assert_eq!(&*last.name, "alice");
+        assert!(last.mentioned);
+        assert!(!app.state.messages.iter().any(|msg| &*msg.name == "spammer"));
+    }
+
+    #[test]
+    fn settings_screen() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("settings-screen");
+        let file = dir.join("config.json");
+        app.config.path = Some(file.to_path_buf());
+
+        app.handle_action(Action::Settings);
+        // scrollback.messages is the first row
+        app.update(key(KeyCode::Enter));
+        app.update(key(KeyCode::Backspace));
+        app.update(key(KeyCode::Backspace));
+        app.update(key(KeyCode::Enter));
+        assert_eq!(app.config.scrollback.messages, 200);
+
+        app.update(key(KeyCode::Char('s')));
+        assert_eq!(app.config.scrollback.messages, 2);
+        assert_eq!(app.state.messages.capacity(), 2);
+        assert!(app.settings.is_none());
+
+        let mut saved = Config {
+            path: Some(file),
+            ..Config::default()
+        };
+        saved.read_user_data_from_file().unwrap();
+        assert_eq!(saved.scrollback.messages, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bindings_overlay() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        app.update(key(KeyCode::Char('?')));
+        assert!(app.show_bindings);
+        assert_eq!(app.state.chat_input.current_message, "");
+
+        // the key that closes the overlay does nothing else
+        assert_eq!(app.update(key(KeyCode::Esc)), Flow::Continue);
+        assert!(!app.show_bindings);
+
+        for c in "why?".chars() {
+            app.update(key(KeyCode::Char(c)));
+        }
+        assert!(!app.show_bindings);
+        assert_eq!(app.state.chat_input.current_message, "why?");
+    }
+
+    #[test]
+    fn vim_keys() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let config = KeyConfig {
+            preset: Preset::Vim,
+            ..KeyConfig::default()
+        };
+        app.keymap = Keymap::new(&config).unwrap();
+
+        app.update(key(KeyCode::Char('j')));
+        assert_eq!(app.update(key(KeyCode::Esc)), Flow::Continue);
+        assert_eq!(app.keymap.mode, Mode::Normal);
+
+        // normal mode keys are never typed
+        app.update(key(KeyCode::Char('x')));
+        app.update(key(KeyCode::Char('G')));
+        assert!(app.windows.get(WindowType::Chat).auto_scroll);
+        app.update(key(KeyCode::Char('k')));
+        assert!(!app.windows.get(WindowType::Chat).auto_scroll);
+        assert_eq!(app.state.chat_input.current_message, "j");
+
+        app.update(key(KeyCode::Char('i')));
+        app.update(key(KeyCode::Char('k')));
+        assert_eq!(app.state.chat_input.current_message, "jk");
+
+        app.update(key(KeyCode::Esc));
+        assert_eq!(app.update(key(KeyCode::Char('Z'))), Flow::Continue);
+        assert_eq!(app.keymap.pending(), "Z");
+        assert_eq!(app.update(key(KeyCode::Char('Z'))), Flow::Quit);
+    }
+
+    #[test]
+    fn flairs_popup() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let users = UserList::from_json(
+            r#"{"connectioncount":1,"users":[{"nick":"Bob","features":["flair99","flair3","moderator"]}]}"#,
+        )
+        .unwrap();
+        app.update(Event::Action(Action::UsersInit(users)));
+        let flairs = |app: &App| -> Vec<String> {
+            let user = app.state.ul.get("bob").unwrap();
+            user.flairs
+                .iter()
+                .map(|flair| flair.name.to_string())
+                .collect()
+        };
+        assert_eq!(flairs(&app), vec!["moderator", "flair3"]);
+
+        // a newer flairs.json knows flair99
+        let json = r#"[{"name":"flair99","label":"New","priority":0}]"#;
+        app.update(Event::Action(Action::FlairsLoaded(
+            Flairs::from_json(json).unwrap(),
+        )));
+        assert_eq!(flairs(&app), vec!["flair99", "moderator", "flair3"]);
+
+        app.handle_action(parse_command_to_action("/flairs bob").unwrap());
+        let (nick, features) = app.flairs.as_ref().unwrap();
+        assert_eq!(nick, "Bob");
+        assert_eq!(features.len(), 3);
+        app.update(key(KeyCode::Char('x')));
+        assert!(app.flairs.is_none());
+        assert_eq!(app.state.chat_input.current_message, "");
+
+        app.handle_action(Action::Flairs("alice".to_string()));
+        assert!(app.flairs.is_none());
+        assert!(app.state.messages.back().unwrap().name.contains("ERROR"));
+    }
+
+    #[test]
+    fn tagged_links_are_revealed_one_by_one() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        for text in ["nsfw https://a.io", "nsfl https://b.io", "weeb stuff"] {
+            let msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+            app.update(Event::Action(Action::RecvMsg(msg)));
+        }
+        let masked = |app: &App| -> Vec<bool> {
+            app.state
+                .messages
+                .iter()
+                .map(ChatMessage::is_masked)
+                .collect()
+        };
+        assert_eq!(masked(&app), vec![true, true, false]);
+
+        app.update(key(KeyCode::F(4)));
+        assert_eq!(masked(&app), vec![true, false, false]);
+        app.update(key(KeyCode::F(4)));
+        assert_eq!(masked(&app), vec![false, false, false]);
+        app.update(key(KeyCode::F(4)));
+        assert_eq!(masked(&app), vec![true, true, false]);
+
+        app.state.set_tagged_links(TaggedLinks::Reveal);
+        assert_eq!(masked(&app), vec![false, false, false]);
+        app.state.set_tagged_links(TaggedLinks::Hide);
+        app.update(key(KeyCode::F(4)));
+        assert_eq!(masked(&app)[..3], [true, true, false]);
+        assert_eq!(&*app.state.messages.back().unwrap().name, "INFO");
+    }
+
+    #[test]
+    fn emotes_in_a_row_become_a_combo() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let recv = |app: &mut App, nick: &str, text: &str| {
+            let msg = ChatMessage::from_string(nick.to_string(), text.to_string());
+            app.update(Event::Action(Action::RecvMsg(msg)));
+        };
+        let combos =
+            |app: &App| -> Vec<usize> { app.state.messages.iter().map(|msg| msg.combo).collect() };
+
+        recv(&mut app, "bob", "PEPE");
+        assert_eq!(combos(&app), vec![0]);
+        recv(&mut app, "alice", "PEPE");
+        recv(&mut app, "carol", " PEPE ");
+        assert_eq!(combos(&app), vec![3]);
+
+        // other emotes, words and emotes with text start over
+        recv(&mut app, "bob", "YEE");
+        recv(&mut app, "bob", "PEPE hi");
+        recv(&mut app, "bob", "PEPE");
+        recv(&mut app, "bob", "hi");
+        recv(&mut app, "bob", "hi");
+        assert_eq!(combos(&app), vec![3, 0, 0, 0, 0, 0]);
+
+        app.state.combos = false;
+        recv(&mut app, "bob", "YEE");
+        recv(&mut app, "bob", "YEE");
+        assert_eq!(app.state.messages.len(), 8);
+    }
+
+    #[test]
+    fn filters_hide_dim_and_collapse() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("filters");
+        app.config.path = Some(dir.join("config.json"));
+        app.state.username = "me".to_string();
+        let recv = |app: &mut App, nick: &str, text: &str| {
+            let msg = ChatMessage::from_string(nick.to_string(), text.to_string());
+            app.update(Event::Action(Action::RecvMsg(msg)));
+        };
+        let filter = |app: &mut App, command: &str| {
+            let action = parse_command_to_action(command).unwrap();
+            app.update(Event::Action(action));
+        };
+
+        filter(&mut app, "/filter add hide regex ^!");
+        filter(&mut app, "/filter add dim longer_than 10");
+        filter(&mut app, "/filter add collapse duplicates");
+        assert_eq!(app.config.filters.len(), 3);
+        let start = app.state.messages.len();
+
+        recv(&mut app, "bob", "!nsfw");
+        recv(&mut app, "bob", "!nsfw me");
+        recv(&mut app, "bob", "a long message");
+        recv(&mut app, "bob", "buy gold");
+        recv(&mut app, "alice", "buy gold");
+        recv(&mut app, "carol", "buy gold");
+        recv(&mut app, "carol", "hi");
+        assert_eq!(app.state.hidden(), 1);
+        let lines: Vec<(bool, usize)> = app
+            .state
+            .messages
+            .iter()
+            .skip(start)
+            .map(|msg| (msg.dimmed, msg.collapsed))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(false, 0), (true, 0), (false, 0), (false, 2), (false, 0)]
+        );
+        assert!(app.state.messages.get(start).unwrap().mentioned);
+
+        filter(&mut app, "/filter remove 1");
+        recv(&mut app, "bob", "!nsfw");
+        assert_eq!(app.config.filters.len(), 2);
+        assert_eq!(&*app.state.messages.back().unwrap().message, "!nsfw");
+
+        filter(&mut app, "/filter remove 3");
+        assert!(app.state.messages.back().unwrap().name.contains("ERROR"));
+        filter(&mut app, "/filter list");
+        assert_eq!(
+            &*app.state.messages.back().unwrap().message,
+            "2. collapse duplicates (0 hidden)"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filters_are_shared_by_tabs() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("tab-filters");
+        app.config.path = Some(dir.join("config.json"));
+        let local = Profile {
+            name: "me".to_string(),
+            anonymous: true,
+            ..Profile::default()
+        };
+        app.config.profiles.insert("local".to_string(), local);
+        let filter = |app: &mut App, command: &str| {
+            let action = parse_command_to_action(command).unwrap();
+            app.update(Event::Action(action));
+        };
+        let last = |app: &App| app.state.messages.back().unwrap().message.to_string();
+        let rules = |filters: &[Filter]| -> Vec<String> {
+            filters
+                .iter()
+                .map(|filter| filter.rule.to_string())
+                .collect()
+        };
+
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        filter(&mut app, "/filter add hide bots");
+        app.update(Event::Input(InputEvent::Key(KeyEvent::new(
+            KeyCode::Left,
+            KeyModifiers::ALT,
+        ))));
+        filter(&mut app, "/filter add dim longer_than 5");
+        assert_eq!(last(&app), "Added filter 2: dim longer_than 5");
+        filter(&mut app, "/filter list");
+        assert_eq!(last(&app), "2. dim longer_than 5 (0 hidden)");
+
+        filter(&mut app, "/filter remove 1");
+        assert_eq!(last(&app), "Removed filter 1: hide bots");
+        assert_eq!(app.config.filters.len(), 1);
+        assert_eq!(rules(&app.state.filters), vec!["dim longer_than 5"]);
+        assert_eq!(rules(&app.tabs[1].state.filters), vec!["dim longer_than 5"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tabs_keep_their_own_chat() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let local = Profile {
+            name: "me".to_string(),
+            anonymous: true,
+            ..Profile::default()
+        };
+        app.config.profiles.insert("local".to_string(), local);
+        let recv = |app: &mut App, tab: usize, text: &str| {
+            let msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+            app.update(Event::Tab(tab, Action::RecvMsg(msg)));
+        };
+        let last = |app: &App| app.state.messages.back().unwrap().message.to_string();
+
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        recv(&mut app, 0, "hi destiny");
+        recv(&mut app, 0, "hello");
+        recv(&mut app, 1, "hi me");
+        assert_eq!(app.tab_titles(), vec![("default", 2, 1), ("local", 0, 0)]);
+        assert_eq!(last(&app), "hi me");
+        assert!(app.state.messages.back().unwrap().mentioned);
+
+        app.update(Event::Input(InputEvent::Key(KeyEvent::new(
+            KeyCode::Left,
+            KeyModifiers::ALT,
+        ))));
+        assert_eq!(app.tab_titles(), vec![("default", 0, 0), ("local", 0, 0)]);
+        assert_eq!(app.state.username, "destiny");
+        assert_eq!(last(&app), "hello");
+
+        // the tab of a profile is only opened once
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        assert_eq!(app.tabs.len(), 2);
+        assert_eq!(app.active, 1);
+
+        app.update(Event::Action(Action::CloseTab));
+        assert_eq!(app.tab_titles(), vec![("default", 0, 0)]);
+        recv(&mut app, 1, "too late");
+        assert!(last(&app).starts_with("Closed the tab local"));
+        app.update(Event::Action(Action::CloseTab));
+        assert!(app.state.messages.back().unwrap().name.contains("ERROR"));
+    }
+
+    #[test]
+    fn mentions_outlive_the_chat() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let local = Profile {
+            name: "me".to_string(),
+            anonymous: true,
+            ..Profile::default()
+        };
+        app.config.profiles.insert("local".to_string(), local);
+        app.state.highlights = vec!["rust".to_string()];
+        let recv = |app: &mut App, tab: usize, text: &str| {
+            let msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+            app.update(Event::Tab(tab, Action::RecvMsg(msg)));
+        };
+        let pageup = |app: &mut App| app.update(key(KeyCode::PageUp));
+
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        recv(&mut app, 0, "hi destiny");
+        recv(&mut app, 0, "rust rocks");
+        recv(&mut app, 1, "hi me");
+        app.tabs[0].state.messages.set_capacity(3);
+        recv(&mut app, 0, "a");
+        recv(&mut app, 0, "b");
+        let whisper = ChatMessage::from_whisper_json(r#"{"nick":"bob","data":"psst"}"#).unwrap();
+        app.update(Event::Tab(0, Action::Whisper(whisper)));
+
+        let mentions: Vec<(MentionKind, &str, &str)> = app
+            .state
+            .mentions
+            .iter()
+            .map(|m| (m.kind, m.channel.as_str(), m.message.as_str()))
+            .collect();
+        assert_eq!(
+            mentions,
+            vec![
+                (MentionKind::Mention, "default", "hi destiny"),
+                (MentionKind::Highlight, "default", "rust rocks"),
+                (MentionKind::Mention, "local", "hi me"),
+                (MentionKind::Whisper, "default", "psst"),
+            ]
+        );
+
+        // with the mentions pane focused the scroll keys jump to the mentions
+        app.handle_action(Action::TogglePane(WindowType::Mentions));
+        app.update(Event::Input(InputEvent::Key(KeyEvent::new(
+            KeyCode::Char('o'),
+            KeyModifiers::ALT,
+        ))));
+        pageup(&mut app);
+        assert_eq!(app.active, 0);
+        assert!(!app.windows.get(WindowType::Chat).auto_scroll);
+        assert_eq!(&*app.state.messages.back().unwrap().name, "WHISPER");
+
+        pageup(&mut app);
+        assert_eq!(app.active, 1);
+        assert_eq!(app.state.mentions.selected(), Some(2));
+
+        // dropped by the chat, but not by the mentions
+        pageup(&mut app);
+        assert_eq!(app.active, 0);
+        assert!(app
+            .state
+            .messages
+            .back()
+            .unwrap()
+            .message
+            .contains("only left in the mentions"));
+        assert_eq!(app.state.mentions.len(), 4);
+
+        app.update(key(KeyCode::F(3)));
+        assert_eq!(app.state.mentions.selected(), None);
+        assert!(app.windows.get(WindowType::Chat).auto_scroll);
+    }
+
+    #[test]
+    fn mentions_follow_their_tab() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        for (name, nick) in [("local", "me"), ("other", "you")] {
+            let profile = Profile {
+                name: nick.to_string(),
+                anonymous: true,
+                ..Profile::default()
+            };
+            app.config.profiles.insert(name.to_string(), profile);
+        }
+        let recv = |app: &mut App, tab: usize, text: &str| {
+            let msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+            app.update(Event::Tab(tab, Action::RecvMsg(msg)));
+        };
+        let alt = |app: &mut App, code| {
+            app.update(Event::Input(InputEvent::Key(KeyEvent::new(
+                code,
+                KeyModifiers::ALT,
+            ))))
+        };
+        let last = |app: &App| app.state.messages.back().unwrap().message.to_string();
+
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        recv(&mut app, 1, "hi me");
+        // the tab is found after switching its profile
+        app.handle_action(Action::Profile(Some("other".to_string())));
+        alt(&mut app, KeyCode::Left);
+        app.handle_action(Action::TogglePane(WindowType::Mentions));
+        alt(&mut app, KeyCode::Char('o'));
+        app.update(key(KeyCode::PageUp));
+        assert_eq!(app.active, 1);
+        assert_eq!(app.state.mentions.iter().next().unwrap().tab, Some(1));
+
+        // a new tab of the same profile doesn't take over the mentions of the closed one
+        app.update(Event::Action(Action::CloseTab));
+        app.update(Event::Action(Action::Tab(Some("local".to_string()))));
+        recv(&mut app, 2, "a");
+        recv(&mut app, 2, "b");
+        alt(&mut app, KeyCode::Left);
+        app.update(key(KeyCode::F(3)));
+        app.update(key(KeyCode::PageUp));
+        assert_eq!(app.active, 0);
+        assert_eq!(last(&app), "The tab of local was closed.");
+        assert_eq!(app.state.mentions.iter().next().unwrap().tab, None);
+    }
+
+    #[test]
+    fn draws_the_smallest_layout() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        app.state.add_info("hello".to_string());
+        for pane in [
+            WindowType::Debug,
+            WindowType::UserList,
+            WindowType::Mentions,
+        ] {
+            app.config.layout.toggle(pane);
+            app.config.layout.resize(pane, -100);
+        }
+        for (width, height) in [(80, 24), (12, 8), (3, 3)] {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| app.draw(f).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn panes_are_toggled_resized_and_saved() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let dir = temp_dir("layout");
+        let file = dir.join("config.json");
+        app.config.path = Some(file.to_path_buf());
+        let alt = |c| {
+            Event::Input(InputEvent::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::ALT,
+            )))
+        };
+
+        app.handle_action(Action::TogglePane(WindowType::Whispers));
+        assert!(app.state.messages.back().unwrap().name.contains("ERROR"));
+        app.handle_action(Action::TogglePane(WindowType::UserList));
+        assert_eq!(
+            app.config.layout.panes(true),
+            vec![WindowType::Chat, WindowType::UserList]
+        );
+
+        app.update(alt('o'));
+        assert_eq!(app.focus, WindowType::UserList);
+        app.update(alt('='));
+        assert_eq!(app.config.layout.children[1].children[1].size, 25);
+        app.update(alt('o'));
+        assert_eq!(app.focus, WindowType::Chat);
+        app.update(alt('o'));
+
+        // hiding the focused pane gives the focus back to the chat
+        app.handle_action(Action::TogglePane(WindowType::UserList));
+        assert_eq!(app.focus, WindowType::Chat);
+
+        assert_eq!(app.handle_action(Action::QuitApp), Flow::Quit);
+        let mut saved = Config {
+            path: Some(file),
+            ..Config::default()
+        };
+        saved.read_user_data_from_file().unwrap();
+        assert_eq!(saved.layout, app.config.layout);
+        assert_eq!(saved.layout.children[1].children[1].size, 25);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn whispers_ring_the_bell() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        app.config.notifications.bell = true;
+        let json = r#"{"nick":"bob","data":"psst","timestamp":1}"#;
+        let msg = ChatMessage::from_whisper_json(json).unwrap();
+        app.handle_action(Action::Whisper(msg));
+        assert!(app.state.bell);
+        assert_eq!(app.state.whispers.len(), 1);
+        assert_eq!(&*app.state.messages.back().unwrap().name, "WHISPER");
+    }
+
+    #[test]
+    fn chat_history_is_added() {
+        let emote_list = EmoteList::new();
+        let (mut app, _requests) = app(&emote_list, false);
+        let history = vec![
+            r#"MSG {"nick":"bob","features":[],"timestamp":1,"data":"hi"}"#.to_string(),
+            r#"JOIN {"nick":"bob","features":[],"timestamp":1}"#.to_string(),
+        ];
+        app.update(Event::Action(Action::ChatHistory(history)));
+        assert_eq!(app.state.messages.len(), 1);
+        assert_eq!(app.state.ul.users.len(), 1);
+        assert!(app.state.loaded);
+    }
+}
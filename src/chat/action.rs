@@ -1,9 +1,15 @@
 use super::{
+    api::UserInfo,
+    chat_log::LogEntry,
+    command::ModCommand,
+    features::FlairInfo,
+    filter::FilterCommand,
     message::ChatMessage,
     user::{User, UserList},
 };
-use crate::ws_error::WsError;
-use std::fmt::Display;
+use crate::{network::Session, token::Token, ui::window::WindowType, ws_error::WsError};
+use anyhow::Result;
+use std::{fmt::Display, path::PathBuf};
 
 #[derive(Debug)]
 pub enum Action {
@@ -11,9 +17,47 @@ pub enum Action {
     QuitApp,
     GetChatHistory,
     GetMe,
+    /// Fetches flairs.json and saves it to the cache file
+    GetFlairs(Option<PathBuf>),
+    /// Flairs from flairs.json, they replace the known ones
+    FlairsLoaded(Vec<FlairInfo>),
+    /// Connects as a profile, dropping the current connection
+    Connect(Session),
+    /// Lists the profiles or switches to the one with the name
+    Profile(Option<String>),
+    /// Lists the tabs or opens the profile with the name in one
+    Tab(Option<String>),
+    /// Closes the current tab and its connection
+    CloseTab,
+    /// Checks the login key and connects with it
+    Login(Token),
+    /// Shows the login screen with the reason
+    LoginRequired(String),
+    /// The login key works, with the nick it belongs to
+    LoggedIn(Token, String),
     GetEmbeds,
+    Help(Option<String>),
+    Info(String),
+    Error(String),
+    /// Messages from an API call, shown with the name e.g. STALK
+    SystemMessages(String, Vec<String>),
+    ChatHistory(Vec<String>),
+    Me(UserInfo),
+    Search(String),
+    /// Matches of the search in the chat log, older than the chat, for the query they were searched with
+    LogMatches(String, Result<Vec<LogEntry>>),
+    /// Opens the settings screen
+    Settings,
+    /// Shows or hides a pane of the layout
+    TogglePane(WindowType),
+    /// Shows every flair of the nick
+    Flairs(String),
+    Filter(FilterCommand),
     RecvMsg(ChatMessage),
-    SendMsg,
+    /// A private message to the account
+    Whisper(ChatMessage),
+    SendMsg(String),
+    Moderate(ModCommand),
     UserJoin(User),
     UserQuit(User),
     UsersInit(UserList),
@@ -33,35 +77,34 @@ pub enum Action {
 }
 
 impl Action {
-    pub fn from_prefix_and_json(prefix: &str, json: &str) -> Action {
+    /// Fails if the json doesn't fit the prefix, unknown prefixes are Unreachable.
+    pub fn from_prefix_and_json(prefix: &str, json: &str) -> Result<Action> {
         // These actions come from the websocket only!
-        match prefix {
-            "MSG" => Action::RecvMsg(ChatMessage::from_json(json)),
-            "JOIN" => Action::UserJoin(User::from_json(json)),
-            "QUIT" => Action::UserQuit(User::from_json(json)),
-            "NAMES" => Action::UsersInit(UserList::from_json(json)),
+        Ok(match prefix {
+            "MSG" => Action::RecvMsg(ChatMessage::from_json(json)?),
+            "JOIN" => Action::UserJoin(User::from_json(json)?),
+            "QUIT" => Action::UserQuit(User::from_json(json)?),
+            "NAMES" => Action::UsersInit(UserList::from_json(json)?),
             "MUTE" => Action::Mute,
             "UNMUTE" => Action::Unmute,
             "BAN" => Action::Ban,
             "UNBAN" => Action::Unban,
             "SUBONLY" => Action::Subonly,
             "BROADCAST" => Action::Broadcast,
-            "PRIVMSG" => Action::PrivMsg,
+            "PRIVMSG" => Action::Whisper(ChatMessage::from_whisper_json(json)?),
             "PRIVMSGSENT" => Action::PrivMsg,
             "PING" => Action::Ping,
             "PONG" => Action::Pong,
             "REFRESH" => Action::Refresh,
             "Binary" => Action::Binary,
-            "ERR" => Action::Err(WsError::from_json(json)),
+            "ERR" => Action::Err(WsError::from_json(json)?),
             _ => Action::Unreachable(json.to_string()),
-        }
+        })
     }
 }
 
 impl Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            _ => write!(f, "{:?}", self),
-        }
+        write!(f, "{:?}", self)
     }
 }
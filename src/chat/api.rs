@@ -1,23 +1,27 @@
 use anyhow::{bail, Result};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{fmt::Display, time::Duration};
 use tokio::time::Instant;
 
-pub struct ApiCaller<'a> {
+pub struct ApiCaller {
     client: Client,
     timer: Instant,
-    token: &'a str,
+    token: String,
+    /// The address of the chat API, e.g. https://www.destiny.gg
+    api: String,
 }
 
-impl<'a> ApiCaller<'a> {
-    pub fn new(token: &'a str) -> ApiCaller<'a> {
+impl ApiCaller {
+    pub fn new(token: &str, api: &str) -> ApiCaller {
         let client = reqwest::Client::default();
         let timer = Instant::now() - Duration::from_secs(10);
         ApiCaller {
             client,
             timer,
-            token,
+            token: token.to_string(),
+            api: api.trim_end_matches('/').to_string(),
         }
     }
 
@@ -65,7 +69,7 @@ impl<'a> ApiCaller<'a> {
     pub async fn get_chat_history(&self) -> Result<Vec<String>> {
         let res = self
             .client
-            .get("https://www.destiny.gg/api/chat/history")
+            .get(format!("{}/api/chat/history", self.api))
             .send()
             .await?
             .text()
@@ -75,41 +79,68 @@ impl<'a> ApiCaller<'a> {
         Ok(messages)
     }
 
-    pub async fn get_me(&self) -> Result<UserInfo> {
-        // WARNING
-        // res is not valid json i think
-        todo!();
+    /// The flairs.json of the CDN, e.g. https://cdn.destiny.gg, as it was sent.
+    pub async fn get_flairs(&self, cdn: &str) -> Result<String> {
         let res = self
             .client
-            .get(format!(
-                "https://www.destiny.gg/api/api/userinfo?token={}",
-                self.token
-            ))
+            .get(format!("{}/flairs/flairs.json", cdn.trim_end_matches('/')))
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            bail!("{} answered with {}.", cdn, res.status());
+        }
+        Ok(res.text().await?)
+    }
+
+    /// Who the login key belongs to, with roles and features.
+    /// Returns None if destiny.gg rejected the key.
+    pub async fn get_me(&self) -> Result<Option<UserInfo>> {
+        let res = self
+            .client
+            .get(format!("{}/api/chat/me", self.api))
+            .header("cookie", format!("authtoken={}", self.token))
             .send()
-            .await?
-            .text()
             .await?;
 
-        let user: UserInfo = serde_json::from_str(&res)?;
-        Ok(user)
+        match res.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Ok(None),
+            status if !status.is_success() => bail!("destiny.gg answered with {}.", status),
+            _ => (),
+        }
+
+        let me: UserInfo = serde_json::from_str(&res.text().await?)?;
+        Ok(Some(me))
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The answer of /api/chat/me, only nick is required because the other fields vary between accounts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserInfo {
     pub nick: String,
+    #[serde(default)]
     pub username: String,
-    #[serde(rename(deserialize = "userId"))]
-    pub user_id: String,
-    #[serde(rename(deserialize = "status"))]
+    #[serde(default, rename(deserialize = "userId"))]
+    pub user_id: Value,
+    #[serde(default, rename(deserialize = "status"))]
     pub user_status: String,
-    #[serde(rename(deserialize = "createdDate"))]
+    #[serde(default, rename(deserialize = "createdDate"))]
     pub created_date: String,
+    #[serde(default)]
     pub roles: Vec<String>,
+    #[serde(default)]
     pub features: Vec<String>,
+    #[serde(default)]
     pub team: String,
 }
 
+impl UserInfo {
+    pub fn is_moderator(&self) -> bool {
+        self.roles
+            .iter()
+            .any(|role| role == "ADMIN" || role == "MODERATOR")
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatHistory {
     nick: String,
@@ -168,3 +199,27 @@ impl Display for Embed {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_info_from_chat_me() {
+        let json = r#"{"nick":"Bob","username":"bob","userId":"12","status":"Active",
+            "roles":["USER","MODERATOR"],"features":["subscriber"],"settings":[]}"#;
+        let me: UserInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(me.nick, "Bob");
+        assert!(me.is_moderator());
+        assert_eq!(me.team, "");
+
+        let me: UserInfo = serde_json::from_str(r#"{"nick":"Bob","userId":12}"#).unwrap();
+        assert!(!me.is_moderator());
+    }
+
+    #[test]
+    fn user_info_needs_a_nick() {
+        assert!(serde_json::from_str::<UserInfo>("{}").is_err());
+        assert!(serde_json::from_str::<UserInfo>(r#"{"username":"bob"}"#).is_err());
+    }
+}
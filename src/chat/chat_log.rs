@@ -0,0 +1,441 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, LogConfig, LogFormat};
+
+use super::{
+    message::ChatMessage,
+    search::SearchQuery,
+    time::{format_date, format_datetime, parse_datetime},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogKind {
+    Received,
+    /// From older logs, own messages are logged once the server echoes them
+    Sent,
+    System,
+}
+
+/// A single line of the chat log.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: u64,
+    pub kind: LogKind,
+    pub nick: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    pub data: String,
+}
+
+impl LogEntry {
+    pub fn received(msg: &ChatMessage) -> LogEntry {
+        LogEntry {
+            timestamp: msg.timestamp,
+            kind: LogKind::Received,
+            nick: msg.name.to_string(),
+            features: msg.features.to_vec(),
+            data: msg.message.to_string(),
+        }
+    }
+
+    /// Errors, infos and other messages the client generates itself
+    pub fn system(msg: &ChatMessage) -> LogEntry {
+        LogEntry {
+            kind: LogKind::System,
+            ..LogEntry::received(msg)
+        }
+    }
+
+    /// Parses a line written by to_line in either format.
+    pub fn from_line(line: &str) -> Option<LogEntry> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            return serde_json::from_str(line).ok();
+        }
+
+        // [2022-10-26 21:06:29] (sent) nick: message
+        let (datetime, rest) = line.strip_prefix('[')?.split_once("] ")?;
+        let (kind, rest) = if let Some(rest) = rest.strip_prefix("(sent) ") {
+            (LogKind::Sent, rest)
+        } else if let Some(rest) = rest.strip_prefix("* ") {
+            (LogKind::System, rest)
+        } else {
+            (LogKind::Received, rest)
+        };
+        let (nick, data) = rest.split_once(": ")?;
+
+        Some(LogEntry {
+            timestamp: parse_datetime(datetime)?,
+            kind,
+            nick: nick.to_string(),
+            features: Vec::new(),
+            data: data.to_string(),
+        })
+    }
+
+    pub fn to_line(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Json => serde_json::to_string(self).unwrap(),
+            LogFormat::Text => {
+                let marker = match self.kind {
+                    LogKind::Received => "",
+                    LogKind::Sent => "(sent) ",
+                    LogKind::System => "* ",
+                };
+                format!(
+                    "[{}] {}{}: {}",
+                    format_datetime(self.timestamp),
+                    marker,
+                    self.nick,
+                    self.data
+                )
+            }
+        }
+    }
+}
+
+/// Writes log entries to one file per day (UTC), e.g. 2022-10-26.log.
+/// Files that grow too big are continued in 2022-10-26.1.log and so on.
+pub struct ChatLog {
+    dir: PathBuf,
+    format: LogFormat,
+    max_file_size: u64,
+    max_age: Option<Duration>,
+    day: String,
+    index: usize,
+    size: u64,
+    file: Option<File>,
+}
+
+impl ChatLog {
+    pub fn new(config: &LogConfig, dir: PathBuf) -> Result<ChatLog> {
+        fs::create_dir_all(&dir)?;
+
+        let max_age = match config.max_age_days {
+            0 => None,
+            days => Some(Duration::from_secs(days * 24 * 60 * 60)),
+        };
+
+        let chat_log = ChatLog {
+            dir,
+            format: config.format,
+            max_file_size: config.max_file_size,
+            max_age,
+            day: String::new(),
+            index: 0,
+            size: 0,
+            file: None,
+        };
+        chat_log.prune()?;
+
+        Ok(chat_log)
+    }
+
+    /// Returns None if logging is disabled in the config.
+    pub fn from_config(config: &Config) -> Result<Option<ChatLog>> {
+        if !config.logging.enabled {
+            return Ok(None);
+        }
+
+        match config.log_dir() {
+            Some(dir) => Ok(Some(ChatLog::new(&config.logging, dir)?)),
+            None => anyhow::bail!("No directory for the chat log found."),
+        }
+    }
+
+    /// Like from_config, in a directory named after the profile of a tab opened with /tab.
+    pub fn for_profile(config: &Config, profile: &str) -> Result<Option<ChatLog>> {
+        match ChatLog::from_config(config)? {
+            Some(chat_log) => Ok(Some(ChatLog::new(
+                &config.logging,
+                chat_log.dir.join(profile),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn write(&mut self, entry: &LogEntry) -> Result<()> {
+        let day = format_date(entry.timestamp);
+        if self.file.is_none() || day != self.day {
+            self.open(day)?;
+            self.prune()?;
+        }
+
+        let line = format!("{}\n", entry.to_line(self.format));
+        if self.size > 0 && self.size + line.len() as u64 > self.max_file_size {
+            self.index += 1;
+            self.open_current()?;
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            self.size += line.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    // Continues the newest file of the day
+    fn open(&mut self, day: String) -> Result<()> {
+        self.day = day;
+        self.index = 0;
+        while self.path(self.index + 1).exists() {
+            self.index += 1;
+        }
+        self.open_current()
+    }
+
+    fn open_current(&mut self) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(self.index))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        let extension = extension(self.format);
+        match index {
+            0 => self.dir.join(format!("{}.{}", self.day, extension)),
+            _ => self
+                .dir
+                .join(format!("{}.{}.{}", self.day, index, extension)),
+        }
+    }
+
+    /// Deletes log files that are older than max_age_days.
+    pub fn prune(&self) -> Result<()> {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return Ok(()),
+        };
+
+        for path in log_files(&self.dir)? {
+            let modified = fs::metadata(&path)?.modified()?;
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            if age > max_age {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn extension(format: LogFormat) -> &'static str {
+    match format {
+        LogFormat::Text => "log",
+        LogFormat::Json => "jsonl",
+    }
+}
+
+/// Every log file in dir, oldest first.
+pub fn log_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "log" || ext == "jsonl")
+        })
+        .collect();
+
+    // 2022-10-26.log < 2022-10-26.1.log < 2022-10-26.2.log < 2022-10-27.log
+    files.sort_by_key(|path| {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let (day, index) = match name.split_once('.') {
+            Some((day, index)) => (day.to_string(), index.parse::<usize>().unwrap_or(0)),
+            None => (name.to_string(), 0),
+        };
+        (day, index)
+    });
+    Ok(files)
+}
+
+/// The newest chat messages in the log files of dir that match the query and are older than before.
+/// Returns at most limit entries, oldest first.
+pub fn search(
+    dir: &Path,
+    query: &SearchQuery,
+    username: &str,
+    before: u64,
+    limit: usize,
+) -> Result<Vec<LogEntry>> {
+    let mut found = Vec::new();
+
+    for path in log_files(dir)?.iter().rev() {
+        let content = fs::read_to_string(path)?;
+        let mut matches: Vec<LogEntry> = content
+            .lines()
+            .filter_map(LogEntry::from_line)
+            .filter(|entry| entry.kind != LogKind::System && entry.timestamp < before)
+            .filter(|entry| {
+                let mentioned = entry.data.split_whitespace().any(|word| word == username);
+                query.matches_parts(&entry.nick, &entry.data, mentioned)
+            })
+            .collect();
+
+        let start = matches.len().saturating_sub(limit - found.len());
+        matches.drain(..start);
+        matches.append(&mut found);
+        found = matches;
+
+        if found.len() >= limit {
+            break;
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    // 2022-10-26 21:06:29
+    const TIMESTAMP: u64 = 1666818389374;
+    const DAY: u64 = 24 * 60 * 60 * 1000;
+
+    fn entry(timestamp: u64, data: &str) -> LogEntry {
+        LogEntry {
+            timestamp,
+            kind: LogKind::Received,
+            nick: "destiny".to_string(),
+            features: vec!["subscriber".to_string()],
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn text_line() {
+        let line = entry(TIMESTAMP, "hello").to_line(LogFormat::Text);
+        assert_eq!(line, "[2022-10-26 21:06:29] destiny: hello");
+
+        let mut sent = entry(TIMESTAMP, "hello");
+        sent.kind = LogKind::Sent;
+        assert_eq!(
+            sent.to_line(LogFormat::Text),
+            "[2022-10-26 21:06:29] (sent) destiny: hello"
+        );
+    }
+
+    #[test]
+    fn json_line_roundtrip() {
+        let line = entry(TIMESTAMP, "hello").to_line(LogFormat::Json);
+        assert_eq!(LogEntry::from_line(&line), Some(entry(TIMESTAMP, "hello")));
+    }
+
+    #[test]
+    fn text_line_roundtrip() {
+        let mut sent = entry(TIMESTAMP - 374, "hello: world");
+        sent.kind = LogKind::Sent;
+        sent.features.clear();
+        let line = sent.to_line(LogFormat::Text);
+        assert_eq!(LogEntry::from_line(&line), Some(sent));
+        assert_eq!(LogEntry::from_line("not a log line"), None);
+    }
+
+    #[test]
+    fn search_log_files() {
+        let dir = temp_dir("search");
+        let mut chat_log = ChatLog::new(&LogConfig::default(), dir.clone()).unwrap();
+        chat_log.write(&entry(TIMESTAMP, "first match")).unwrap();
+        chat_log
+            .write(&entry(TIMESTAMP + DAY, "second match"))
+            .unwrap();
+        chat_log.write(&entry(TIMESTAMP + DAY, "nothing")).unwrap();
+        chat_log
+            .write(&entry(TIMESTAMP + 2 * DAY, "third match"))
+            .unwrap();
+
+        let query = SearchQuery::parse("match");
+        let found = search(&dir, &query, "bob", TIMESTAMP + 2 * DAY - 1000, 10).unwrap();
+        let data: Vec<&str> = found.iter().map(|entry| entry.data.as_str()).collect();
+        assert_eq!(data, vec!["first match", "second match"]);
+
+        let found = search(&dir, &query, "bob", u64::MAX, 1).unwrap();
+        assert_eq!(found[0].data, "third match");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn one_file_per_day() {
+        let dir = temp_dir("days");
+        let mut chat_log = ChatLog::new(&LogConfig::default(), dir.clone()).unwrap();
+        chat_log.write(&entry(TIMESTAMP, "first")).unwrap();
+        chat_log.write(&entry(TIMESTAMP + 1000, "second")).unwrap();
+        chat_log.write(&entry(TIMESTAMP + DAY, "third")).unwrap();
+
+        let files = log_files(&dir).unwrap();
+        assert_eq!(
+            files,
+            vec![dir.join("2022-10-26.log"), dir.join("2022-10-27.log")]
+        );
+        let content = fs::read_to_string(&files[0]).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_on_size() {
+        let dir = temp_dir("size");
+        let config = LogConfig {
+            format: LogFormat::Json,
+            max_file_size: 150,
+            ..Default::default()
+        };
+        let mut chat_log = ChatLog::new(&config, dir.clone()).unwrap();
+        for i in 0..3 {
+            chat_log
+                .write(&entry(TIMESTAMP + i, "a message with some length"))
+                .unwrap();
+        }
+
+        let files = log_files(&dir).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("2022-10-26.jsonl"),
+                dir.join("2022-10-26.1.jsonl"),
+                dir.join("2022-10-26.2.jsonl")
+            ]
+        );
+
+        // reopening continues the newest file
+        let mut chat_log = ChatLog::new(&config, dir.clone()).unwrap();
+        chat_log.write(&entry(TIMESTAMP, "x")).unwrap();
+        assert_eq!(log_files(&dir).unwrap().len(), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prune_old_files() {
+        let dir = temp_dir("prune");
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("2000-01-01.log");
+        let file = File::create(&old).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(60 * 24 * 60 * 60))
+            .unwrap();
+
+        ChatLog::new(&LogConfig::default(), dir.clone()).unwrap();
+        assert!(!old.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
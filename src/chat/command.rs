@@ -1,49 +1,623 @@
 use anyhow::bail;
 use anyhow::Result;
+use serde_json::json;
 use std::fmt::Display;
 
-use super::action::Action;
+use super::{
+    action::Action,
+    filter::{FilterCommand, FilterRule},
+};
+use crate::ui::window::WindowType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Nick,
+    Number,
+    Command,
+    Text,
+}
+
+#[derive(Debug)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
 
 #[derive(Debug)]
-pub enum Command {
-    Stalk(String, u8),
-    Embeds,
-}
-
-pub fn parse_command_to_action(s: &String) -> Result<Action> {
-    let whitespaces: Vec<&str> = s.split_whitespace().collect();
-    let command = &whitespaces[0][1..];
-    match command {
-        "stalk" => match whitespaces.len() {
-            2 => Ok(Action::Stalk(whitespaces[1].to_string(), 15)),
-            3 => Ok(Action::Stalk(
-                whitespaces[1].to_string(),
-                whitespaces[2].parse::<u8>()?,
-            )),
-            _ => bail!("Invalid stalk usage! /stalk [name] [number]."),
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    /// Only shown to and usable by moderators
+    pub mod_only: bool,
+    build: fn(&[&str]) -> Result<Action>,
+}
+
+// Every command the client understands, new commands only need an entry here
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "stalk",
+        aliases: &["s"],
+        args: &[
+            Arg {
+                name: "nick",
+                kind: ArgKind::Nick,
+                required: true,
+            },
+            Arg {
+                name: "number",
+                kind: ArgKind::Number,
+                required: false,
+            },
+        ],
+        help: "Shows the last messages of a user.",
+        mod_only: false,
+        build: |args| {
+            let num = match args.get(1) {
+                Some(num) => parse_number(num)?,
+                None => 15,
+            };
+            Ok(Action::Stalk(args[0].to_string(), num))
+        },
+    },
+    Command {
+        name: "embeds",
+        aliases: &[],
+        args: &[],
+        help: "Shows the most linked embeds of the last 30 minutes.",
+        mod_only: false,
+        build: |_| Ok(Action::GetEmbeds),
+    },
+    Command {
+        name: "search",
+        aliases: &["find"],
+        args: &[Arg {
+            name: "query",
+            kind: ArgKind::Text,
+            required: true,
+        }],
+        help: "Searches the chat, filter with from:nick, has:link and is:mention.",
+        mod_only: false,
+        build: |args| Ok(Action::Search(args.join(" "))),
+    },
+    Command {
+        name: "flairs",
+        aliases: &[],
+        args: &[Arg {
+            name: "nick",
+            kind: ArgKind::Nick,
+            required: true,
+        }],
+        help: "Shows every flair of a user.",
+        mod_only: false,
+        build: |args| Ok(Action::Flairs(args[0].to_string())),
+    },
+    Command {
+        name: "filter",
+        aliases: &[],
+        args: &[Arg {
+            name: "add|list|remove",
+            kind: ArgKind::Text,
+            required: true,
+        }],
+        help: "Adds, lists or removes filters, e.g. /filter add dim bots or /filter remove 1.",
+        mod_only: false,
+        build: |args| match args {
+            ["add", rule @ ..] => Ok(Action::Filter(FilterCommand::Add(FilterRule::parse(rule)?))),
+            ["list"] => Ok(Action::Filter(FilterCommand::List)),
+            ["remove", number] => match number.parse() {
+                Ok(number) => Ok(Action::Filter(FilterCommand::Remove(number))),
+                Err(_) => bail!("{} is not the number of a filter.", number),
+            },
+            _ => bail!("Filters are added, listed or removed."),
+        },
+    },
+    Command {
+        name: "profile",
+        aliases: &[],
+        args: &[Arg {
+            name: "name",
+            kind: ArgKind::Text,
+            required: false,
+        }],
+        help: "Lists the profiles or reconnects with the given one.",
+        mod_only: false,
+        build: |args| Ok(Action::Profile(args.first().map(|name| name.to_string()))),
+    },
+    Command {
+        name: "tab",
+        aliases: &[],
+        args: &[Arg {
+            name: "profile",
+            kind: ArgKind::Text,
+            required: false,
+        }],
+        help: "Lists the tabs or opens the profile in a tab of its own.",
+        mod_only: false,
+        build: |args| Ok(Action::Tab(args.first().map(|name| name.to_string()))),
+    },
+    Command {
+        name: "close",
+        aliases: &[],
+        args: &[],
+        help: "Closes the current tab and its connection.",
+        mod_only: false,
+        build: |_| Ok(Action::CloseTab),
+    },
+    Command {
+        name: "pane",
+        aliases: &[],
+        args: &[Arg {
+            name: "name",
+            kind: ArgKind::Text,
+            required: true,
+        }],
+        help: "Shows or hides a pane of the layout, e.g. /pane mentions.",
+        mod_only: false,
+        build: |args| Ok(Action::TogglePane(WindowType::parse(args[0])?)),
+    },
+    Command {
+        name: "settings",
+        aliases: &["config"],
+        args: &[],
+        help: "Opens the settings, s saves them to the config file.",
+        mod_only: false,
+        build: |_| Ok(Action::Settings),
+    },
+    Command {
+        name: "help",
+        aliases: &["h"],
+        args: &[Arg {
+            name: "command",
+            kind: ArgKind::Command,
+            required: false,
+        }],
+        help: "Lists all commands or explains a single one.",
+        mod_only: false,
+        build: |args| match args.first() {
+            Some(name) => match Command::find(name) {
+                Some(command) => Ok(Action::Help(Some(command.name.to_string()))),
+                None => bail!("Command /{} not found.", name),
+            },
+            None => Ok(Action::Help(None)),
+        },
+    },
+    Command {
+        name: "mute",
+        aliases: &[],
+        args: &[
+            Arg {
+                name: "nick",
+                kind: ArgKind::Nick,
+                required: true,
+            },
+            Arg {
+                name: "minutes",
+                kind: ArgKind::Number,
+                required: false,
+            },
+        ],
+        help: "Mutes a user, for 10 minutes if no time is given.",
+        mod_only: true,
+        build: |args| {
+            let minutes = match args.get(1) {
+                Some(minutes) => parse_minutes(minutes)?,
+                None => 10,
+            };
+            Ok(Action::Moderate(ModCommand::Mute(
+                args[0].to_string(),
+                minutes,
+            )))
+        },
+    },
+    Command {
+        name: "unmute",
+        aliases: &[],
+        args: &[Arg {
+            name: "nick",
+            kind: ArgKind::Nick,
+            required: true,
+        }],
+        help: "Unmutes a user.",
+        mod_only: true,
+        build: |args| Ok(Action::Moderate(ModCommand::Unmute(args[0].to_string()))),
+    },
+    Command {
+        name: "ban",
+        aliases: &[],
+        args: &[
+            Arg {
+                name: "nick",
+                kind: ArgKind::Nick,
+                required: true,
+            },
+            Arg {
+                name: "minutes",
+                kind: ArgKind::Number,
+                required: true,
+            },
+            Arg {
+                name: "reason",
+                kind: ArgKind::Text,
+                required: true,
+            },
+        ],
+        help: "Bans a user, 0 minutes ban permanently.",
+        mod_only: true,
+        build: |args| {
+            Ok(Action::Moderate(ModCommand::Ban {
+                nick: args[0].to_string(),
+                minutes: parse_minutes(args[1])?,
+                reason: args[2..].join(" "),
+            }))
+        },
+    },
+    Command {
+        name: "unban",
+        aliases: &[],
+        args: &[Arg {
+            name: "nick",
+            kind: ArgKind::Nick,
+            required: true,
+        }],
+        help: "Unbans a user.",
+        mod_only: true,
+        build: |args| Ok(Action::Moderate(ModCommand::Unban(args[0].to_string()))),
+    },
+    Command {
+        name: "subonly",
+        aliases: &[],
+        args: &[Arg {
+            name: "on|off",
+            kind: ArgKind::Text,
+            required: true,
+        }],
+        help: "Turns the subscriber only mode on or off.",
+        mod_only: true,
+        build: |args| match args {
+            ["on"] => Ok(Action::Moderate(ModCommand::SubOnly(true))),
+            ["off"] => Ok(Action::Moderate(ModCommand::SubOnly(false))),
+            _ => bail!("Subonly is either on or off."),
         },
-        "embeds" => Ok(Action::GetEmbeds),
-        _ => bail!("Command not found."),
+    },
+];
+
+/// Moderation requests, sent to the chat as websocket commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModCommand {
+    Mute(String, u32),
+    Unmute(String),
+    Ban {
+        nick: String,
+        minutes: u32,
+        reason: String,
+    },
+    Unban(String),
+    SubOnly(bool),
+}
+
+impl ModCommand {
+    /// The websocket frame, durations are in nanoseconds.
+    pub fn frame(&self) -> String {
+        let nanos = |minutes: &u32| u64::from(*minutes) * 60 * 1_000_000_000;
+        match self {
+            ModCommand::Mute(nick, minutes) => format!(
+                "MUTE {}",
+                json!({ "data": nick, "duration": nanos(minutes) })
+            ),
+            ModCommand::Unmute(nick) => format!("UNMUTE {}", json!({ "data": nick })),
+            ModCommand::Ban {
+                nick,
+                minutes,
+                reason,
+            } => format!(
+                "BAN {}",
+                json!({
+                    "nick": nick,
+                    "reason": reason,
+                    "duration": nanos(minutes),
+                    "ispermanent": *minutes == 0,
+                    "banip": false,
+                })
+            ),
+            ModCommand::Unban(nick) => format!("UNBAN {}", json!({ "data": nick })),
+            ModCommand::SubOnly(on) => format!(
+                "SUBONLY {}",
+                json!({ "data": if *on { "on" } else { "off" } })
+            ),
+        }
+    }
+}
+
+pub fn parse_command_to_action(s: &str) -> Result<Action> {
+    let whitespaces: Vec<&str> = s.trim_start_matches('/').split_whitespace().collect();
+    let (name, args) = match whitespaces.split_first() {
+        Some((name, args)) => (*name, args),
+        None => bail!("No command given. Type /help to list all commands."),
+    };
+
+    let command = match Command::find(name) {
+        Some(command) => command,
+        None => bail!(
+            "Command /{} not found. Type /help to list all commands.",
+            name
+        ),
+    };
+
+    let required = command.args.iter().filter(|arg| arg.required).count();
+    let takes_text = command.args.last().map(|arg| arg.kind) == Some(ArgKind::Text);
+    if args.len() < required || (!takes_text && args.len() > command.args.len()) {
+        bail!("Invalid usage! {}", command.usage());
+    }
+
+    (command.build)(args).map_err(|err| anyhow::anyhow!("{} {}", err, command.usage()))
+}
+
+fn parse_number(s: &str) -> Result<u8> {
+    match s.parse::<u8>() {
+        Ok(num) => Ok(num),
+        Err(_) => bail!("{} is not a number between 0 and 255.", s),
+    }
+}
+
+fn parse_minutes(s: &str) -> Result<u32> {
+    match s.parse::<u32>() {
+        Ok(minutes) => Ok(minutes),
+        Err(_) => bail!("{} is not a number of minutes.", s),
     }
 }
 
 impl Command {
-    pub fn vec() -> Vec<String> {
-        vec!["/stalk".to_string(), "/embeds".to_string()]
+    /// Finds a command by its name or one of its aliases, with or without the leading slash.
+    pub fn find(name: &str) -> Option<&'static Command> {
+        let name = name.trim_start_matches('/').to_lowercase();
+        COMMANDS
+            .iter()
+            .find(|command| command.name == name || command.aliases.contains(&name.as_str()))
+    }
+
+    /// The commands the user may use, mod only commands are left out for everyone else.
+    pub fn available(moderator: bool) -> impl Iterator<Item = &'static Command> {
+        COMMANDS
+            .iter()
+            .filter(move |command| moderator || !command.mod_only)
+    }
+
+    pub fn vec(moderator: bool) -> Vec<String> {
+        Command::available(moderator)
+            .map(|command| format!("/{}", command.name))
+            .collect()
+    }
+
+    pub fn usage(&self) -> String {
+        let args: String = self
+            .args
+            .iter()
+            .map(|arg| match arg.required {
+                true => format!(" <{}>", arg.name),
+                false => format!(" [{}]", arg.name),
+            })
+            .collect();
+        format!("Usage: /{}{}", self.name, args)
+    }
+
+    /// The kind of the argument at index, text arguments swallow everything after them.
+    pub fn arg_kind(&self, index: usize) -> Option<ArgKind> {
+        match self.args.get(index) {
+            Some(arg) => Some(arg.kind),
+            None => self
+                .args
+                .last()
+                .map(|arg| arg.kind)
+                .filter(|kind| *kind == ArgKind::Text),
+        }
+    }
+}
+
+/// The lines /help prints, either for every command or only for the given one.
+pub fn help(command: Option<&str>, moderator: bool) -> Vec<String> {
+    match command.and_then(Command::find) {
+        Some(command) => {
+            let mut lines = vec![command.usage(), command.help.to_string()];
+            if !command.aliases.is_empty() {
+                let aliases: Vec<String> = command
+                    .aliases
+                    .iter()
+                    .map(|alias| format!("/{}", alias))
+                    .collect();
+                lines.push(format!("Aliases: {}", aliases.join(", ")));
+            }
+            lines
+        }
+        None => Command::available(moderator)
+            .map(|command| format!("/{} - {}", command.name, command.help))
+            .collect(),
     }
 }
 
 impl Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            _ => write!(f, "{:?}", self),
-        }
+        write!(f, "/{}", self.name)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
 
     #[test]
-    fn command_strings_equals() {}
+    fn command_strings_equals() {
+        assert_eq!(
+            Command::vec(false),
+            vec![
+                "/stalk",
+                "/embeds",
+                "/search",
+                "/flairs",
+                "/filter",
+                "/profile",
+                "/tab",
+                "/close",
+                "/pane",
+                "/settings",
+                "/help"
+            ]
+        );
+        assert_eq!(Command::vec(true).len(), COMMANDS.len());
+    }
+
+    #[test]
+    fn command_names_are_unique() {
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .flat_map(|command| {
+                std::iter::once(command.name).chain(command.aliases.iter().copied())
+            })
+            .collect();
+        let len = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), len);
+    }
+
+    #[test]
+    fn stalk() {
+        let action = parse_command_to_action("/stalk destiny").unwrap();
+        assert!(matches!(action, Action::Stalk(name, 15) if name == "destiny"));
+
+        let action = parse_command_to_action("/stalk destiny 3").unwrap();
+        assert!(matches!(action, Action::Stalk(name, 3) if name == "destiny"));
+
+        let action = parse_command_to_action("/s destiny").unwrap();
+        assert!(matches!(action, Action::Stalk(name, 15) if name == "destiny"));
+    }
+
+    #[test]
+    fn stalk_invalid() {
+        let err = parse_command_to_action("/stalk").unwrap_err();
+        assert!(err.to_string().contains("Usage: /stalk <nick> [number]"));
+        assert!(parse_command_to_action("/stalk destiny many").is_err());
+        assert!(parse_command_to_action("/stalk destiny 3 4").is_err());
+    }
+
+    #[test]
+    fn embeds() {
+        let action = parse_command_to_action("/embeds").unwrap();
+        assert!(matches!(action, Action::GetEmbeds));
+        assert!(parse_command_to_action("/embeds now").is_err());
+    }
+
+    #[test]
+    fn search() {
+        let action = parse_command_to_action("/search from:destiny hello world").unwrap();
+        assert!(matches!(action, Action::Search(query) if query == "from:destiny hello world"));
+        assert!(parse_command_to_action("/find x").is_ok());
+        assert!(parse_command_to_action("/search").is_err());
+    }
+
+    #[test]
+    fn help_command() {
+        assert!(matches!(
+            parse_command_to_action("/help").unwrap(),
+            Action::Help(None)
+        ));
+        assert!(matches!(
+            parse_command_to_action("/help /s").unwrap(),
+            Action::Help(Some(name)) if name == "stalk"
+        ));
+        assert!(parse_command_to_action("/help nope").is_err());
+        assert_eq!(help(None, true).len(), COMMANDS.len());
+        assert_eq!(help(None, false).len(), 11);
+        assert_eq!(
+            help(Some("stalk"), false)[0],
+            "Usage: /stalk <nick> [number]"
+        );
+    }
+
+    #[test]
+    fn empty_command_does_not_panic() {
+        assert!(parse_command_to_action("/").is_err());
+        assert!(parse_command_to_action("/ ").is_err());
+        assert!(parse_command_to_action("/notacommand").is_err());
+    }
+
+    #[test]
+    fn flairs() {
+        let action = parse_command_to_action("/flairs destiny").unwrap();
+        assert!(matches!(action, Action::Flairs(nick) if nick == "destiny"));
+        assert!(parse_command_to_action("/flairs").is_err());
+    }
+
+    #[test]
+    fn filter() {
+        let action = parse_command_to_action("/filter add hide regex ^! \\w+").unwrap();
+        assert!(matches!(action, Action::Filter(FilterCommand::Add(rule))
+            if rule.to_string() == "hide regex ^! \\w+"));
+        let action = parse_command_to_action("/filter list").unwrap();
+        assert!(matches!(action, Action::Filter(FilterCommand::List)));
+        let action = parse_command_to_action("/filter remove 2").unwrap();
+        assert!(matches!(action, Action::Filter(FilterCommand::Remove(2))));
+
+        let err = parse_command_to_action("/filter add hide cats").unwrap_err();
+        assert!(err.to_string().contains("Usage: /filter <add|list|remove>"));
+        assert!(parse_command_to_action("/filter remove last").is_err());
+        assert!(parse_command_to_action("/filter clear").is_err());
+    }
+
+    #[test]
+    fn pane() {
+        assert!(matches!(
+            parse_command_to_action("/pane mentions").unwrap(),
+            Action::TogglePane(WindowType::Mentions)
+        ));
+        assert!(matches!(
+            parse_command_to_action("/pane users").unwrap(),
+            Action::TogglePane(WindowType::UserList)
+        ));
+        assert!(parse_command_to_action("/pane sidebar").is_err());
+    }
+
+    #[test]
+    fn profile() {
+        assert!(matches!(
+            parse_command_to_action("/profile").unwrap(),
+            Action::Profile(None)
+        ));
+        assert!(matches!(
+            parse_command_to_action("/profile work").unwrap(),
+            Action::Profile(Some(name)) if name == "work"
+        ));
+    }
+
+    #[test]
+    fn moderation() {
+        let action = parse_command_to_action("/mute bob").unwrap();
+        assert!(matches!(action, Action::Moderate(ModCommand::Mute(nick, 10)) if nick == "bob"));
+
+        let action = parse_command_to_action("/ban bob 0 being rude").unwrap();
+        let Action::Moderate(ban) = action else {
+            panic!("not a ban");
+        };
+        assert_eq!(
+            ban.frame(),
+            r#"BAN {"banip":false,"duration":0,"ispermanent":true,"nick":"bob","reason":"being rude"}"#
+        );
+        assert!(parse_command_to_action("/ban bob 10").is_err());
+
+        assert_eq!(
+            ModCommand::Mute("bob".to_string(), 1).frame(),
+            r#"MUTE {"data":"bob","duration":60000000000}"#
+        );
+        assert!(parse_command_to_action("/subonly maybe").is_err());
+    }
+
+    #[test]
+    fn arg_kinds() {
+        let stalk = Command::find("stalk").unwrap();
+        assert_eq!(stalk.arg_kind(0), Some(ArgKind::Nick));
+        assert_eq!(stalk.arg_kind(1), Some(ArgKind::Number));
+        assert_eq!(stalk.arg_kind(2), None);
+    }
 }
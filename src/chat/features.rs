@@ -1,120 +1,305 @@
-use anyhow::{bail, Result};
-use serde::{Deserialize, Serialize};
-use tui::style::Color;
-
-#[derive(Debug, Deserialize, Serialize)]
-pub enum Feature {
-    White,
-    Sub,
-    Tier1,
-    Tier2,
-    Tier3,
-    Tier4,
-    Bot,
-    Bot2,
-    Protected,
-    Vip,
-    Mod,
-    Notable,
-    Trusted,
-    Contributor,
-    Music,
-    Broadcaster,
-    Birthday,
-    Admin,
-    Sc2,
-    Dnd,
-    Lawyer,
-    EmoteContributor,
-    YoutubeContributor,
-    Twitch,
-    Eve,
-    Gym,
-    League,
-    Nfl,
-    MinecraftVIP,
-    Micro,
-    EmoteMaster,
-    DggShirtDesigner,
-    Verified,
-    YoutubeEditor,
-    DndGold,
-    DndScoria,
-    DndKnight,
-    TikTokEditor,
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path, sync::Arc};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+/// A flair as the flairs.json of destiny.gg describes it, other keys of it are ignored
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FlairInfo {
+    /// The name the server uses in features, e.g. "flair13"
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+    /// "#rrggbb", empty if the nick keeps its colour
+    #[serde(default)]
+    pub color: String,
+    /// Lower ones are shown first and pick the nick colour
+    #[serde(default = "lowest_priority")]
+    pub priority: u32,
+    /// Only colours the nick, there is no badge for it
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+fn lowest_priority() -> u32 {
+    u32::MAX
+}
+
+impl FlairInfo {
+    /// Short text for the badge strip, flairs without one only show up in /flairs
+    pub fn badge(&self) -> Option<&'static str> {
+        if self.hidden {
+            return None;
+        }
+        BADGES
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, badge)| *badge)
+    }
+}
+
+pub type Flair = Arc<FlairInfo>;
+
+// flairs.json has no short names, so badges only exist for the roles and tiers
+const BADGES: &[(&str, &str)] = &[
+    ("admin", "A"),
+    ("flair12", "B"),
+    ("moderator", "M"),
+    ("vip", "V"),
+    ("bot", "bot"),
+    ("flair11", "bot"),
+    ("flair8", "T4"),
+    ("flair3", "T3"),
+    ("flair1", "T2"),
+    ("flair13", "T1"),
+];
+
+// The subscriber tiers, from tier 1 to tier 4
+const TIERS: &[&str] = &["flair13", "flair1", "flair3", "flair8"];
+
+const BOTS: &[&str] = &["bot", "flair11"];
+
+/// The highest subscriber tier of the flairs, 0 without a subscription
+pub fn tier(flairs: &[Flair]) -> u8 {
+    flairs
+        .iter()
+        .filter_map(|flair| TIERS.iter().position(|tier| *tier == flair.name))
+        .map(|index| index as u8 + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn is_bot(flairs: &[Flair]) -> bool {
+    flairs
+        .iter()
+        .any(|flair| BOTS.contains(&flair.name.as_str()))
+}
+
+/// Short names for the config, e.g. "tier1" instead of "flair13"
+pub const ALIASES: &[(&str, &str)] = &[
+    ("tier1", "flair13"),
+    ("tier2", "flair1"),
+    ("tier3", "flair3"),
+    ("tier4", "flair8"),
+    ("sub", "subscriber"),
+    ("mod", "moderator"),
+    ("broadcaster", "flair12"),
+    ("notable", "flair2"),
+    ("micro", "flair17"),
+];
+
+/// The server name of a flair or its alias
+pub fn resolve_alias(name: &str) -> &str {
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, flair)| flair)
+}
+
+// Used until flairs.json was loaded once: name, label, colour and priority.
+// The roles come before the subscriber tiers, the tiers before everything else.
+const BUILTIN: &[(&str, &str, &str, u32)] = &[
+    ("admin", "Administrator", "red", 1),
+    ("flair12", "Broadcaster", "#e69014", 2),
+    ("moderator", "Moderator", "yellow", 3),
+    ("vip", "VIP", "#db4c1c", 4),
+    ("bot", "Bot", "", 5),
+    ("flair11", "Bot", "", 6),
+    ("flair8", "Subscriber Tier 4", "magenta", 10),
+    ("flair3", "Subscriber Tier 3", "lightgreen", 11),
+    ("flair1", "Subscriber Tier 2", "lightcyan", 12),
+    ("flair13", "Subscriber Tier 1", "cyan", 13),
+    ("subscriber", "Subscriber", "", 14),
+    ("flair17", "Micro", "yellow", 15),
+    ("flair2", "Notable", "#e69014", 20),
+    ("flair4", "Trusted", "", 21),
+    ("flair5", "Contributor", "", 22),
+    ("protected", "Protected", "", 23),
+    ("flair9", "Twitch Subscriber", "", 30),
+    ("flair6", "Music", "", 31),
+    ("flair7", "NFL", "", 32),
+    ("flair10", "StarCraft 2", "", 33),
+    ("flair14", "Minecraft VIP", "", 34),
+    ("flair15", "Birthday", "", 35),
+    ("flair16", "Emote Contributor", "", 36),
+    ("flair18", "Emote Master", "", 37),
+    ("flair19", "DGG Shirt Designer", "", 38),
+    ("flair20", "Verified", "", 39),
+    ("flair21", "YouTube Editor", "", 40),
+    ("flair22", "D&D Gold", "", 41),
+    ("flair24", "D&D Scoria", "", 42),
+    ("flair25", "YouTube Contributor", "", 43),
+    ("flair26", "D&D Knight", "", 44),
+    ("flair27", "TikTok Editor", "", 45),
+    ("flair28", "Lawyer", "", 46),
+    ("flair29", "Gym", "", 47),
+    ("flair30", "League", "", 48),
+];
+
+/// Every flair the client knows, by the name the server uses
+#[derive(Debug, Clone)]
+pub struct Flairs {
+    flairs: HashMap<String, Flair>,
+}
+
+impl Default for Flairs {
+    fn default() -> Self {
+        let builtin = BUILTIN
+            .iter()
+            .map(|(name, label, color, priority)| FlairInfo {
+                name: name.to_string(),
+                label: label.to_string(),
+                color: color.to_string(),
+                priority: *priority,
+                hidden: false,
+            })
+            .collect();
+        let mut flairs = Flairs {
+            flairs: HashMap::new(),
+        };
+        flairs.update(builtin);
+        flairs
+    }
 }
 
-impl Feature {
-    pub fn to_color(&self) -> Color {
-        match self {
-            Feature::Tier1 => Color::Cyan,
-            Feature::Tier2 => Color::LightCyan,
-            Feature::Tier3 => Color::LightGreen,
-            Feature::Tier4 => Color::Magenta,
-            Feature::Vip => Color::Rgb(219, 76, 28),
-            Feature::Micro => Color::Yellow,
-            Feature::Mod => Color::Yellow,
-            Feature::Broadcaster => Color::Rgb(230, 144, 20),
-            Feature::Notable => Color::Rgb(230, 144, 20),
-            Feature::Admin => Color::Red,
-            _ => Color::White,
+impl Flairs {
+    /// Reads the flairs.json of destiny.gg.
+    pub fn from_json(json: &str) -> Result<Vec<FlairInfo>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Flairs from the server replace the ones with the same name.
+    pub fn update(&mut self, flairs: Vec<FlairInfo>) {
+        for flair in flairs {
+            self.flairs.insert(flair.name.to_string(), Arc::new(flair));
         }
     }
 
-    pub fn parse_flair(flairs: &Vec<String>) -> Feature {
-        match flairs.len() {
-            4 => Feature::from_str(&flairs[2]).unwrap(),
-            2 | 3 => Feature::from_str(&flairs[1]).unwrap(),
-            1 => Feature::from_str(&flairs[0]).unwrap(),
-            _ => Feature::White,
+    pub fn get(&self, name: &str) -> Option<&Flair> {
+        self.flairs.get(name)
+    }
+
+    /// The known flairs of the features, most important first. Unknown ones are skipped.
+    pub fn parse(&self, features: &[String]) -> Vec<Flair> {
+        let mut flairs: Vec<Flair> = features
+            .iter()
+            .filter_map(|name| self.get(name).cloned())
+            .collect();
+        flairs.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.name.cmp(&b.name)));
+        flairs.dedup_by(|a, b| a.name == b.name);
+        flairs
+    }
+
+    /// The features the client doesn't know, e.g. flairs newer than the cache.
+    pub fn unknown<'a>(&self, features: &'a [String]) -> Vec<&'a str> {
+        features
+            .iter()
+            .filter(|name| self.get(name).is_none())
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    /// The flairs.json saved by the last start, None if there is none.
+    pub fn read_cache(file: &Path) -> Result<Option<Vec<FlairInfo>>> {
+        match fs::read_to_string(file) {
+            Ok(json) => Ok(Some(Flairs::from_json(&json)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
         }
     }
 
-    pub fn from_str(s: &str) -> Result<Self> {
-        match s {
-            "subscriber" => Ok(Feature::Sub),
-            "bot" => Ok(Feature::Bot),
-            "protected" => Ok(Feature::Protected),
-            "vip" => Ok(Feature::Vip),
-            "moderator" => Ok(Feature::Mod),
-            "admin" => Ok(Feature::Admin),
-            "flair1" => Ok(Feature::Tier2),
-            "flair2" => Ok(Feature::Notable),
-            "flair3" => Ok(Feature::Tier3),
-            "flair4" => Ok(Feature::Trusted),
-            "flair5" => Ok(Feature::Contributor),
-            "flair6" => Ok(Feature::Music),
-            "flair7" => Ok(Feature::Nfl),
-            "flair8" => Ok(Feature::Tier4),
-            "flair9" => Ok(Feature::Twitch),
-            "flair10" => Ok(Feature::Sc2),
-            "flair11" => Ok(Feature::Bot2),
-            "flair12" => Ok(Feature::Broadcaster),
-            "flair13" => Ok(Feature::Tier1),
-            "flair14" => Ok(Feature::MinecraftVIP),
-            "flair15" => Ok(Feature::Birthday),
-            "flair16" => Ok(Feature::EmoteContributor),
-            "flair17" => Ok(Feature::Micro),
-            "flair18" => Ok(Feature::EmoteMaster),
-            "flair19" => Ok(Feature::DggShirtDesigner),
-            "flair20" => Ok(Feature::Verified),
-            "flair21" => Ok(Feature::YoutubeEditor),
-            "flair22" => Ok(Feature::DndGold),
-            "flair23" => Ok(Feature::White),
-            "flair24" => Ok(Feature::DndScoria),
-            "flair25" => Ok(Feature::YoutubeContributor),
-            "flair26" => Ok(Feature::DndKnight),
-            "flair27" => Ok(Feature::TikTokEditor),
-            "flair28" => Ok(Feature::Lawyer),
-            "flair29" => Ok(Feature::Gym),
-            "flair30" => Ok(Feature::League),
-            _ => bail!("Could not find flair {}", s),
+    pub fn write_cache(file: &Path, json: &str) -> Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
         }
+        fs::write(file, json)?;
+        Ok(())
     }
 }
 
-impl Default for Feature {
-    fn default() -> Self {
-        Feature::White
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn parse(flairs: &Flairs, features: &[&str]) -> Vec<String> {
+        flairs
+            .parse(&names(features))
+            .iter()
+            .map(|flair| flair.name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sorted_by_priority() {
+        let flairs = Flairs::default();
+        assert_eq!(
+            parse(&flairs, &["flair2", "subscriber", "flair3", "moderator"]),
+            names(&["moderator", "flair3", "subscriber", "flair2"])
+        );
+        assert!(flairs.parse(&[]).is_empty());
+    }
+
+    #[test]
+    fn unknown_flairs_are_skipped() {
+        let flairs = Flairs::default();
+        let features = names(&["flair99", "flair13", "subscriber"]);
+        assert_eq!(
+            parse(&flairs, &["flair99", "flair13", "subscriber"]),
+            names(&["flair13", "subscriber"])
+        );
+        assert_eq!(flairs.unknown(&features), vec!["flair99"]);
+    }
+
+    #[test]
+    fn server_flairs_replace_the_builtin_ones() {
+        let mut flairs = Flairs::default();
+        let json = r##"[
+            {"label":"Subscriber Tier 1","name":"flair13","description":"","hidden":false,
+             "priority":50,"color":"#488ce7","rainbowColor":false,"image":[]},
+            {"label":"Star","name":"flair99","hidden":true,"priority":1,"color":"#ffffff"}
+        ]"##;
+        flairs.update(Flairs::from_json(json).unwrap());
+
+        let tier1 = flairs.get("flair13").unwrap();
+        assert_eq!(tier1.color, "#488ce7");
+        assert_eq!(tier1.badge(), Some("T1"));
+        assert_eq!(
+            parse(&flairs, &["flair13", "flair99", "flair2"]),
+            names(&["flair99", "flair2", "flair13"])
+        );
+        assert_eq!(flairs.get("flair99").unwrap().badge(), None);
+    }
+
+    #[test]
+    fn cache() {
+        let dir = temp_dir("flairs-cache");
+        let file = dir.join("flairs.json");
+        assert_eq!(Flairs::read_cache(&file).unwrap(), None);
+
+        Flairs::write_cache(&file, r#"[{"name":"flair99","label":"New"}]"#).unwrap();
+        let cached = Flairs::read_cache(&file).unwrap().unwrap();
+        assert_eq!(cached[0].label, "New");
+        assert_eq!(cached[0].priority, u32::MAX);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tiers_and_bots() {
+        let flairs = Flairs::default();
+        assert_eq!(tier(&flairs.parse(&names(&["subscriber", "flair3"]))), 3);
+        assert_eq!(tier(&flairs.parse(&names(&["subscriber"]))), 0);
+        assert!(is_bot(&flairs.parse(&names(&["flair11"]))));
+        assert!(!is_bot(&flairs.parse(&names(&["moderator"]))));
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(resolve_alias("tier1"), "flair13");
+        assert_eq!(resolve_alias("flair42"), "flair42");
     }
 }
@@ -0,0 +1,256 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    features::{is_bot, tier},
+    message::ChatMessage,
+};
+use crate::ui::{emotes::EmoteList, parser::parse_emote};
+
+/// What happens to a message that matches a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Dropped, only counted in the chat title
+    Hide,
+    /// Shown in the hint style
+    Dim,
+    /// Matches in a row become one line, e.g. "4 messages collapsed"
+    Collapse,
+}
+
+/// Which messages a rule matches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Matcher {
+    Regex(String),
+    /// Chatters below this subscriber tier
+    BelowTier(u8),
+    /// Messages with nothing but emotes
+    EmotesOnly,
+    Bots,
+    /// More characters than this
+    LongerThan(usize),
+    /// The same text as one of the last messages
+    Duplicates,
+}
+
+/// A filter of the config, written like the arguments of /filter add, e.g. "hide regex ^!"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    #[serde(rename = "match")]
+    pub matcher: Matcher,
+}
+
+// How many of the last messages count for duplicates
+const DUPLICATE_WINDOW: usize = 20;
+
+/// What /filter does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterCommand {
+    Add(FilterRule),
+    List,
+    /// Starts at 1 like /filter list
+    Remove(usize),
+}
+
+impl FilterRule {
+    /// Reads the arguments of /filter add.
+    pub fn parse(args: &[&str]) -> Result<FilterRule> {
+        let action = match args.first() {
+            Some(&"hide") => FilterAction::Hide,
+            Some(&"dim") => FilterAction::Dim,
+            Some(&"collapse") => FilterAction::Collapse,
+            Some(action) => bail!("{} is not one of hide, dim and collapse.", action),
+            None => bail!("A filter needs an action."),
+        };
+        let value = args.get(2..).unwrap_or_default().join(" ");
+        let number = |what: &str| -> Result<usize> {
+            value
+                .parse()
+                .map_err(|_| anyhow!("{} needs a number of {}.", args[1], what))
+        };
+        let matcher = match args.get(1) {
+            Some(&"regex") if !value.is_empty() => Matcher::Regex(value.to_string()),
+            Some(&"below_tier") => match number("the tier")? {
+                tier @ 1..=4 => Matcher::BelowTier(tier as u8),
+                _ => bail!("Tiers go from 1 to 4."),
+            },
+            Some(&"emotes_only") => Matcher::EmotesOnly,
+            Some(&"bots") => Matcher::Bots,
+            Some(&"longer_than") => Matcher::LongerThan(number("characters")?),
+            Some(&"duplicates") => Matcher::Duplicates,
+            Some(&"regex") => bail!("regex needs a pattern."),
+            Some(matcher) => bail!("{} is not a kind of filter.", matcher),
+            None => bail!("A filter needs something to match."),
+        };
+        let rule = FilterRule { action, matcher };
+        Filter::new(rule.clone())?;
+        Ok(rule)
+    }
+}
+
+impl Display for FilterRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.action {
+            FilterAction::Hide => "hide",
+            FilterAction::Dim => "dim",
+            FilterAction::Collapse => "collapse",
+        };
+        match &self.matcher {
+            Matcher::Regex(pattern) => write!(f, "{} regex {}", action, pattern),
+            Matcher::BelowTier(tier) => write!(f, "{} below_tier {}", action, tier),
+            Matcher::EmotesOnly => write!(f, "{} emotes_only", action),
+            Matcher::Bots => write!(f, "{} bots", action),
+            Matcher::LongerThan(chars) => write!(f, "{} longer_than {}", action, chars),
+            Matcher::Duplicates => write!(f, "{} duplicates", action),
+        }
+    }
+}
+
+/// A rule ready to match, with the number of messages it hid
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub rule: FilterRule,
+    regex: Option<Regex>,
+    pub hidden: usize,
+}
+
+impl Filter {
+    pub fn new(rule: FilterRule) -> Result<Filter> {
+        let regex = match &rule.matcher {
+            Matcher::Regex(pattern) => Some(Regex::new(pattern)?),
+            _ => None,
+        };
+        Ok(Filter {
+            rule,
+            regex,
+            hidden: 0,
+        })
+    }
+
+    /// Whether the rule applies to the message, recent are the messages before it, newest last.
+    pub fn matches<'a>(
+        &self,
+        msg: &ChatMessage,
+        emotes: &EmoteList,
+        recent: impl DoubleEndedIterator<Item = &'a ChatMessage>,
+    ) -> bool {
+        match &self.rule.matcher {
+            Matcher::Regex(_) => self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(&msg.message)),
+            Matcher::BelowTier(min) => tier(&msg.flairs) < *min,
+            Matcher::EmotesOnly => {
+                let mut words = msg.message.split_whitespace().peekable();
+                words.peek().is_some() && words.all(|word| parse_emote(word, emotes).is_some())
+            }
+            Matcher::Bots => is_bot(&msg.flairs),
+            Matcher::LongerThan(chars) => msg.message.chars().count() > *chars,
+            Matcher::Duplicates => {
+                let text = msg.message.trim();
+                recent
+                    .rev()
+                    .take(DUPLICATE_WINDOW)
+                    .any(|other| other.collapsed == 0 && other.message.trim() == text)
+            }
+        }
+    }
+}
+
+/// The filters of the config, a broken regex names its index, e.g. "filters.2: ..."
+pub fn compile(rules: &[FilterRule]) -> Result<Vec<Filter>> {
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| Filter::new(rule.clone()).map_err(|err| anyhow!("filters.{}: {}", i, err)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::features::Flairs;
+
+    fn rule(args: &str) -> Result<FilterRule> {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        FilterRule::parse(&args)
+    }
+
+    fn msg(features: &[&str], text: &str) -> ChatMessage {
+        let mut msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+        let features: Vec<String> = features.iter().map(|name| name.to_string()).collect();
+        msg.flairs = Flairs::default().parse(&features);
+        msg
+    }
+
+    fn matches(args: &str, msg: &ChatMessage, recent: &[ChatMessage]) -> bool {
+        let filter = Filter::new(rule(args).unwrap()).unwrap();
+        filter.matches(msg, &EmoteList::new(), recent.iter())
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for args in [
+            "hide regex ^!\\w+",
+            "dim below_tier 2",
+            "collapse emotes_only",
+            "hide bots",
+            "dim longer_than 300",
+            "collapse duplicates",
+        ] {
+            assert_eq!(rule(args).unwrap().to_string(), args);
+        }
+        assert!(rule("explode bots").is_err());
+        assert!(rule("hide cats").is_err());
+        assert!(rule("hide regex").is_err());
+        assert!(rule("hide regex (").is_err());
+        assert!(rule("hide below_tier 5").is_err());
+        assert!(rule("hide longer_than many").is_err());
+    }
+
+    #[test]
+    fn matchers() {
+        assert!(matches("hide regex ^!", &msg(&[], "!nsfw"), &[]));
+        assert!(!matches("hide regex ^!", &msg(&[], "hi !"), &[]));
+
+        assert!(matches("hide below_tier 2", &msg(&["flair13"], "hi"), &[]));
+        assert!(!matches("hide below_tier 2", &msg(&["flair3"], "hi"), &[]));
+
+        assert!(matches("hide emotes_only", &msg(&[], "PEPE YEE"), &[]));
+        assert!(!matches("hide emotes_only", &msg(&[], "PEPE hi"), &[]));
+        assert!(!matches("hide emotes_only", &msg(&[], " "), &[]));
+
+        assert!(matches("hide bots", &msg(&["bot"], "hi"), &[]));
+        assert!(!matches("hide bots", &msg(&["moderator"], "hi"), &[]));
+
+        assert!(matches("hide longer_than 3", &msg(&[], "hello"), &[]));
+        assert!(!matches("hide longer_than 5", &msg(&[], "hello"), &[]));
+
+        let recent = [msg(&[], "buy gold"), msg(&[], "hi")];
+        assert!(matches("hide duplicates", &msg(&[], "buy gold "), &recent));
+        assert!(!matches(
+            "hide duplicates",
+            &msg(&[], "buy silver"),
+            &recent
+        ));
+    }
+
+    #[test]
+    fn broken_rules_name_the_index() {
+        let rules = vec![
+            rule("hide bots").unwrap(),
+            FilterRule {
+                action: FilterAction::Hide,
+                matcher: Matcher::Regex("(".to_string()),
+            },
+        ];
+        let err = compile(&rules).unwrap_err();
+        assert!(err.to_string().starts_with("filters.1: "));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{vec_deque, VecDeque},
+    sync::Arc,
+};
+
+use super::{features::Flair, message::ChatMessage};
+
+// The oldest mentions are dropped after this many, the chat forgets them much sooner
+const MAX_MENTIONS: usize = 500;
+
+/// Why a message is in the mentions pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MentionKind {
+    /// Has the username
+    Mention,
+    /// Has one of the highlights of the profile
+    Highlight,
+    Whisper,
+}
+
+/// A copy of a message for the mentions pane, kept after the chat dropped it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mention {
+    pub kind: MentionKind,
+    /// The id of the tab it came in on, None once that tab was closed
+    pub tab: Option<usize>,
+    /// The profile of that tab when it came in
+    pub channel: String,
+    pub timestamp: u64,
+    pub name: Arc<str>,
+    pub flairs: Vec<Flair>,
+    pub message: String,
+    /// The absolute index of its line in the chat of the tab
+    pub index: usize,
+}
+
+impl Mention {
+    pub fn new(
+        kind: MentionKind,
+        tab: usize,
+        channel: &str,
+        msg: &ChatMessage,
+        index: usize,
+    ) -> Mention {
+        Mention {
+            kind,
+            tab: Some(tab),
+            channel: channel.to_string(),
+            timestamp: msg.timestamp,
+            name: msg.name.clone(),
+            flairs: msg.flairs.clone(),
+            message: msg.message.to_string(),
+            index,
+        }
+    }
+}
+
+/// The mentions, highlights and whispers of every tab, oldest first.
+/// One entry can be selected to jump the chat to it.
+#[derive(Debug, Default)]
+pub struct Mentions {
+    entries: VecDeque<Mention>,
+    selected: Option<usize>,
+}
+
+impl Mentions {
+    pub fn push(&mut self, mention: Mention) {
+        if self.entries.len() == MAX_MENTIONS {
+            self.entries.pop_front();
+            // the selection stays on its entry, or goes when that one was dropped
+            self.selected = self.selected.and_then(|index| index.checked_sub(1));
+        }
+        self.entries.push_back(mention);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, Mention> {
+        self.entries.iter()
+    }
+
+    /// The index of the selected entry, None until one is selected
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Selects the entry before the selected one, the newest one if none is selected.
+    pub fn select_prev(&mut self) -> Option<&Mention> {
+        self.selected = match self.selected {
+            Some(index) => Some(index.saturating_sub(1)),
+            None => self.entries.len().checked_sub(1),
+        };
+        self.selected.and_then(|index| self.entries.get(index))
+    }
+
+    /// Selects the entry after the selected one, stays on the newest one.
+    pub fn select_next(&mut self) -> Option<&Mention> {
+        self.selected = match self.selected {
+            Some(index) => Some((index + 1).min(self.entries.len() - 1)),
+            None => self.entries.len().checked_sub(1),
+        };
+        self.selected.and_then(|index| self.entries.get(index))
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected = None;
+    }
+
+    /// The mentions of the closed tab stay, but can't be jumped to anymore
+    pub fn close_tab(&mut self, id: usize) {
+        self.entries
+            .iter_mut()
+            .filter(|mention| mention.tab == Some(id))
+            .for_each(|mention| mention.tab = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(text: &str) -> Mention {
+        let msg = ChatMessage::from_string("bob".to_string(), text.to_string());
+        Mention::new(MentionKind::Mention, 0, "default", &msg, 0)
+    }
+
+    fn selected(mentions: &Mentions) -> Option<&str> {
+        let index = mentions.selected()?;
+        mentions
+            .iter()
+            .nth(index)
+            .map(|mention| mention.message.as_str())
+    }
+
+    #[test]
+    fn selection_starts_at_the_newest() {
+        let mut mentions = Mentions::default();
+        assert_eq!(mentions.select_prev(), None);
+
+        for text in ["a", "b", "c"] {
+            mentions.push(mention(text));
+        }
+        assert_eq!(mentions.select_prev().unwrap().message, "c");
+        assert_eq!(mentions.select_prev().unwrap().message, "b");
+        mentions.select_prev();
+        assert_eq!(mentions.select_prev().unwrap().message, "a");
+        mentions.select_next();
+        mentions.select_next();
+        assert_eq!(mentions.select_next().unwrap().message, "c");
+
+        mentions.clear_selection();
+        assert_eq!(mentions.select_next().unwrap().message, "c");
+    }
+
+    #[test]
+    fn oldest_are_dropped() {
+        let mut mentions = Mentions::default();
+        for i in 0..MAX_MENTIONS {
+            mentions.push(mention(&i.to_string()));
+        }
+        mentions.select_prev();
+        mentions.push(mention("new"));
+        assert_eq!(mentions.len(), MAX_MENTIONS);
+        assert_eq!(mentions.iter().next().unwrap().message, "1");
+        assert_eq!(
+            selected(&mentions),
+            Some((MAX_MENTIONS - 1).to_string().as_str())
+        );
+    }
+}
@@ -1,84 +1,181 @@
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
-use super::features::Feature;
+use super::{
+    features::{Flair, Flairs},
+    search::is_link,
+    time::now_millis,
+};
+use crate::ui::parser::has_spoiler;
+
+/// Words that tag every link of a message, like on destiny.gg
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkTag {
+    Nsfw,
+    Nsfl,
+    Loud,
+    Weeb,
+}
+
+impl LinkTag {
+    pub fn parse(word: &str) -> Option<LinkTag> {
+        match word.to_lowercase().as_str() {
+            "nsfw" => Some(LinkTag::Nsfw),
+            "nsfl" => Some(LinkTag::Nsfl),
+            "loud" => Some(LinkTag::Loud),
+            "weeb" => Some(LinkTag::Weeb),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkTag::Nsfw => "nsfw",
+            LinkTag::Nsfl => "nsfl",
+            LinkTag::Loud => "loud",
+            LinkTag::Weeb => "weeb",
+        }
+    }
+}
+
+// A PRIVMSG as destiny.gg sends it
+#[derive(Deserialize)]
+struct Whisper {
+    nick: String,
+    data: String,
+    timestamp: Option<u64>,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ChatMessage {
     #[serde(rename(deserialize = "data"))]
     pub message: String,
-    pub features: Vec<String>,
+    pub features: Arc<[String]>,
     #[serde(rename(deserialize = "nick"))]
-    pub name: String,
-    #[serde(skip_deserializing)]
-    pub timestamp: String,
-    #[serde(skip_deserializing)]
-    pub flair: Feature,
+    pub name: Arc<str>,
+    #[serde(default)]
+    pub timestamp: u64,
+    /// The known flairs of features, most important first
+    #[serde(skip)]
+    pub flairs: Vec<Flair>,
     #[serde(skip_deserializing)]
     pub greentext: bool,
     #[serde(skip_deserializing)]
     pub own_message: bool,
     #[serde(skip_deserializing)]
     pub mentioned: bool,
-    #[serde(skip_deserializing)]
-    pub nsfw: bool,
-    #[serde(skip_deserializing)]
-    pub nsfl: bool,
+    /// Mentioned by one of the highlights of the profile, not the username
+    #[serde(skip)]
+    pub highlighted: bool,
+    /// The tags of the links, empty if the message has no link
+    #[serde(skip)]
+    pub link_tags: Vec<LinkTag>,
+    /// How many messages of the same emote in a row this line stands for, 0 if it's no combo
+    #[serde(skip)]
+    pub combo: usize,
+    /// How many filtered messages this line stands for, 0 if it's a normal message
+    #[serde(skip)]
+    pub collapsed: usize,
+    /// A filter wants it less visible
+    #[serde(skip)]
+    pub dimmed: bool,
+    /// Has a `||spoiler||`
+    #[serde(skip)]
+    pub spoiler: bool,
+    /// Spoilers and tagged links are masked until revealed
+    #[serde(skip)]
+    pub revealed: bool,
 }
 
 impl ChatMessage {
-    pub fn from_json(json: &str) -> ChatMessage {
-        serde_json::from_str(json).unwrap()
+    pub fn from_json(json: &str) -> Result<ChatMessage> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// A PRIVMSG, whispers come without features
+    pub fn from_whisper_json(json: &str) -> Result<ChatMessage> {
+        let whisper: Whisper = serde_json::from_str(json)?;
+        let mut msg = ChatMessage::from_string(whisper.nick, whisper.data);
+        if let Some(timestamp) = whisper.timestamp {
+            msg.timestamp = timestamp;
+        }
+        Ok(msg)
     }
 
     pub fn from_string(name: String, message: String) -> ChatMessage {
         ChatMessage {
-            name,
-            features: Vec::new(),
-            timestamp: String::from("default_timestamp"),
+            name: name.into(),
+            features: Arc::new([]),
+            timestamp: now_millis(),
             message,
-            flair: Feature::White,
+            flairs: Vec::new(),
             greentext: false,
             own_message: false,
             mentioned: false,
-            nsfw: false,
-            nsfl: false,
+            highlighted: false,
+            link_tags: Vec::new(),
+            combo: 0,
+            collapsed: 0,
+            dimmed: false,
+            spoiler: false,
+            revealed: false,
         }
     }
 
-    pub fn parse(&mut self, username: &str) {
-        // parse flair
-        self.flair = Feature::parse_flair(&self.features);
+    /// Highlights count like mentions of the username.
+    pub fn parse(&mut self, username: &str, highlights: &[String], flairs: &Flairs) {
+        self.flairs = flairs.parse(&self.features);
 
         // parse message
-        self.parse_message(username);
+        self.parse_message(username, highlights);
     }
 
-    fn parse_message(&mut self, username: &str) {
-        self.message
-            .split_whitespace()
-            .into_iter()
-            .for_each(|word| {
-                if word.starts_with(">") {
-                    self.greentext = true;
-                }
+    fn parse_message(&mut self, username: &str, highlights: &[String]) {
+        // like on destiny.gg, a > later in the message is just text
+        self.greentext = self.message.trim_start().starts_with('>');
+        self.spoiler = has_spoiler(&self.message);
 
-                if word.len() == 4 {
-                    match word {
-                        "nsfw" => self.nsfw = true,
-                        "nsfl" => self.nsfl = true,
-                        _ => (),
-                    }
+        let mut link_tags = Vec::new();
+        let mut has_link = false;
+        let mut named = false;
+        self.message.split_whitespace().for_each(|word| {
+            if let Some(tag) = LinkTag::parse(word) {
+                if !link_tags.contains(&tag) {
+                    link_tags.push(tag);
                 }
+            }
+            has_link |= is_link(word);
 
-                if word.len() == username.len() && word.eq(username) {
-                    self.mentioned = true;
-                }
+            if word.len() == username.len() && word.eq(username) {
+                self.mentioned = true;
+                named = true;
+            }
 
-                if self.name.len() == username.len() && self.name.eq(username) {
-                    self.own_message = true;
-                }
-            });
+            if highlights
+                .iter()
+                .any(|highlight| highlight.eq_ignore_ascii_case(word))
+            {
+                self.mentioned = true;
+                self.highlighted = true;
+            }
+
+            if self.name.len() == username.len() && &*self.name == username {
+                self.own_message = true;
+            }
+        });
+
+        self.highlighted &= !named;
+
+        // the tags only mean something for links
+        if has_link {
+            self.link_tags = link_tags;
+        }
+    }
+
+    /// Spoilers and tagged links are masked, e.g. "[nsfw link]".
+    pub fn is_masked(&self) -> bool {
+        (self.spoiler || !self.link_tags.is_empty()) && !self.revealed
     }
 }
 
@@ -93,3 +190,63 @@ impl PartialEq for ChatMessage {
         self.name == other.name && self.message == other.message
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(message: &str) -> ChatMessage {
+        let mut msg = ChatMessage::from_string("bob".to_string(), message.to_string());
+        msg.parse("alice", &[], &Flairs::default());
+        msg
+    }
+
+    #[test]
+    fn tags_need_a_link() {
+        assert!(parse("that was nsfw").link_tags.is_empty());
+        assert!(parse("https://a.io nice").link_tags.is_empty());
+
+        let msg = parse("NSFW loud https://a.io nsfw");
+        assert_eq!(msg.link_tags, vec![LinkTag::Nsfw, LinkTag::Loud]);
+        assert!(msg.is_masked());
+    }
+
+    #[test]
+    fn greentext_starts_the_message() {
+        assert!(parse(">be me").greentext);
+        assert!(parse("  > implying").greentext);
+        assert!(!parse("1 > 0").greentext);
+        assert!(!parse("see >this").greentext);
+    }
+
+    #[test]
+    fn spoilers_are_masked() {
+        assert!(parse("it ||dies|| in the end").is_masked());
+        assert!(!parse("a || b").is_masked());
+    }
+
+    #[test]
+    fn highlights_are_told_apart_from_mentions() {
+        let highlight = |message: &str| {
+            let mut msg = ChatMessage::from_string("bob".to_string(), message.to_string());
+            msg.parse("alice", &["rust".to_string()], &Flairs::default());
+            (msg.mentioned, msg.highlighted)
+        };
+        assert_eq!(highlight("Rust is fun"), (true, true));
+        assert_eq!(highlight("alice likes rust"), (true, false));
+        assert_eq!(highlight("hi alice"), (true, false));
+        assert_eq!(highlight("hi bob"), (false, false));
+    }
+
+    #[test]
+    fn whispers_need_a_nick_and_text() {
+        let json = r#"{"nick":"bob","data":"psst","timestamp":1}"#;
+        let msg = ChatMessage::from_whisper_json(json).unwrap();
+        assert_eq!(
+            (&*msg.name, msg.message.as_str(), msg.timestamp),
+            ("bob", "psst", 1)
+        );
+        assert!(ChatMessage::from_whisper_json("{}").is_err());
+        assert!(ChatMessage::from_whisper_json("{bad").is_err());
+    }
+}
@@ -1,7 +1,13 @@
 pub mod action;
 pub mod api;
+pub mod chat_log;
 pub mod command;
 pub mod features;
+pub mod filter;
+pub mod mentions;
 pub mod message;
+pub mod search;
 pub mod state;
+pub mod store;
+pub mod time;
 pub mod user;
@@ -0,0 +1,126 @@
+use super::message::ChatMessage;
+
+/// A parsed search like `from:destiny has:link is:mention some words`.
+/// Every filter and every word has to match.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub words: Vec<String>,
+    pub from: Option<String>,
+    pub has_link: bool,
+    pub is_mention: bool,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> SearchQuery {
+        let mut parsed = SearchQuery::default();
+
+        query
+            .split_whitespace()
+            .for_each(|token| match token.to_lowercase().as_str() {
+                "has:link" => parsed.has_link = true,
+                "is:mention" => parsed.is_mention = true,
+                token => match token.strip_prefix("from:") {
+                    Some(nick) if !nick.is_empty() => parsed.from = Some(nick.to_string()),
+                    _ => parsed.words.push(token.to_string()),
+                },
+            });
+
+        parsed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &SearchQuery::default()
+    }
+
+    pub fn matches(&self, msg: &ChatMessage) -> bool {
+        self.matches_parts(&msg.name, &msg.message, msg.mentioned)
+    }
+
+    pub fn matches_parts(&self, nick: &str, message: &str, mentioned: bool) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        if let Some(from) = &self.from {
+            if !nick.eq_ignore_ascii_case(from) {
+                return false;
+            }
+        }
+
+        if self.has_link && !message.split_whitespace().any(is_link) {
+            return false;
+        }
+
+        if self.is_mention && !mentioned {
+            return false;
+        }
+
+        let message = message.to_lowercase();
+        self.words.iter().all(|word| message.contains(word))
+    }
+
+    /// Whether a single word of a message should be highlighted.
+    pub fn highlights(&self, word: &str) -> bool {
+        let lowercase = word.to_lowercase();
+        self.words.iter().any(|w| lowercase.contains(w)) || (self.has_link && is_link(word))
+    }
+}
+
+pub fn is_link(word: &str) -> bool {
+    word.starts_with("https://") || word.starts_with("http://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(name: &str, message: &str) -> ChatMessage {
+        ChatMessage::from_string(name.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn parse_filters() {
+        let query = SearchQuery::parse("from:Destiny has:link IS:MENTION Hello world");
+        assert_eq!(query.from, Some("destiny".to_string()));
+        assert!(query.has_link);
+        assert!(query.is_mention);
+        assert_eq!(query.words, vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn match_words_case_insensitive() {
+        let query = SearchQuery::parse("HELLO");
+        assert!(query.matches(&msg("destiny", "well hello there")));
+        assert!(!query.matches(&msg("destiny", "goodbye")));
+    }
+
+    #[test]
+    fn match_from_and_link() {
+        let query = SearchQuery::parse("from:destiny has:link");
+        assert!(query.matches(&msg("Destiny", "look https://destiny.gg")));
+        assert!(!query.matches(&msg("Destiny", "look destiny.gg")));
+        assert!(!query.matches(&msg("bob", "look https://destiny.gg")));
+    }
+
+    #[test]
+    fn match_mention() {
+        let query = SearchQuery::parse("is:mention");
+        let mut mentioned = msg("bob", "hi destiny");
+        mentioned.mentioned = true;
+        assert!(query.matches(&mentioned));
+        assert!(!query.matches(&msg("bob", "hi")));
+    }
+
+    #[test]
+    fn highlight_words() {
+        let query = SearchQuery::parse("needle has:link");
+        assert!(query.highlights("Needles"));
+        assert!(query.highlights("https://destiny.gg"));
+        assert!(!query.highlights("hay"));
+    }
+
+    #[test]
+    fn empty_query_matches_nothing() {
+        assert!(!SearchQuery::parse("  ").matches(&msg("bob", "hi")));
+    }
+}
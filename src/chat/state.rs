@@ -1,60 +1,276 @@
-use std::sync::mpsc::Sender;
+use std::collections::VecDeque;
 
-use crate::ui::chat_input::ChatInput;
+// Older whispers are dropped
+const MAX_WHISPERS: usize = 100;
 
-use super::{action::Action, message::ChatMessage, user::UserList};
+use crate::{
+    config::{Profile, ScrollbackConfig, TaggedLinks},
+    ui::{chat_input::ChatInput, emotes::EmoteList, parser::parse_emote, theme::Theme},
+};
+
+use super::{
+    api::UserInfo,
+    chat_log::{ChatLog, LogEntry},
+    features::Flairs,
+    filter::{Filter, FilterAction},
+    mentions::{Mention, MentionKind, Mentions},
+    message::ChatMessage,
+    store::MessageStore,
+    user::UserList,
+};
 
 pub struct State {
-    pub io_sender: Sender<Action>,
     pub username: String,
     pub ul: UserList,
-    pub messages: Vec<ChatMessage>,
+    pub messages: MessageStore,
     pub message_to_send: Option<String>,
-    pub debugs: Vec<String>,
+    pub debugs: VecDeque<String>,
+    pub max_debugs: usize,
     pub chat_input: ChatInput,
     pub loaded: bool,
+    pub chat_log: Option<ChatLog>,
+    /// Whether something changed since the last draw
+    pub dirty: bool,
+    /// Shows the login screen with the reason why a login key is needed
+    pub login: Option<String>,
+    /// The account of the login key, from /api/chat/me
+    pub me: Option<UserInfo>,
+    /// Reading without a login, nothing can be sent
+    pub anonymous: bool,
+    /// Words of the profile that highlight a message
+    pub highlights: Vec<String>,
+    /// Nicks of the profile whose messages are hidden
+    pub ignores: Vec<String>,
+    pub theme: Theme,
+    /// Every known flair, the built-in ones until flairs.json was loaded
+    pub flairs: Flairs,
+    /// Rings the terminal bell on the next draw
+    pub bell: bool,
+    pub tagged_links: TaggedLinks,
+    /// Collapses messages of the same emote in a row into one line
+    pub combos: bool,
+    pub filters: Vec<Filter>,
+    /// Private messages to the account, newest last
+    pub whispers: VecDeque<ChatMessage>,
+    /// Of every tab, they stay with the active one when tabs are switched
+    pub mentions: Mentions,
+    /// The id of its tab, the mentions point back to it
+    pub tab: usize,
+    /// The profile of the tab, names where its mentions came from
+    pub channel: String,
 }
 
 impl State {
-    pub fn new(username: String, io_sender: Sender<Action>) -> State {
+    pub fn new(username: String) -> State {
         let ul = UserList::new();
-        let messages = Vec::new();
-        let debugs = Vec::new();
+        let scrollback = ScrollbackConfig::default();
+        let messages = MessageStore::new(scrollback.messages);
+        let debugs = VecDeque::with_capacity(scrollback.debugs);
         let chat_input = ChatInput::default();
 
         State {
-            io_sender,
             username,
             ul,
             messages,
             message_to_send: None,
             debugs,
+            max_debugs: scrollback.debugs,
             chat_input,
             loaded: false,
+            chat_log: None,
+            dirty: true,
+            login: None,
+            me: None,
+            anonymous: false,
+            highlights: Vec::new(),
+            ignores: Vec::new(),
+            theme: Theme::default(),
+            flairs: Flairs::default(),
+            bell: false,
+            tagged_links: TaggedLinks::default(),
+            combos: true,
+            filters: Vec::new(),
+            whispers: VecDeque::new(),
+            mentions: Mentions::default(),
+            tab: 0,
+            channel: String::new(),
         }
     }
 
-    // Sends Actions to the network.io_handle() method
-    pub fn dispatch(&self, action: Action) {
-        self.io_sender.send(action).unwrap();
+    /// Starts over as the profile, everything known about the last account is dropped.
+    pub fn set_profile(&mut self, profile: &Profile) {
+        self.username = profile.name.to_string();
+        self.anonymous = profile.anonymous;
+        self.highlights = profile.highlights.clone();
+        self.ignores = profile.ignores.clone();
+        self.me = None;
+        self.ul = UserList::new();
+    }
+
+    pub fn is_ignored(&self, nick: &str) -> bool {
+        self.ignores
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(nick))
+    }
+
+    pub fn is_moderator(&self) -> bool {
+        self.me.as_ref().is_some_and(UserInfo::is_moderator)
+    }
+
+    pub fn set_scrollback(&mut self, scrollback: &ScrollbackConfig) {
+        self.messages.set_capacity(scrollback.messages);
+        self.max_debugs = scrollback.debugs.max(1);
+        while self.debugs.len() > self.max_debugs {
+            self.debugs.pop_front();
+        }
+    }
+
+    /// Masks or reveals the tagged links of every message, the drawn lines need to be redone.
+    pub fn set_tagged_links(&mut self, tagged_links: TaggedLinks) {
+        self.tagged_links = tagged_links;
+        let revealed = tagged_links == TaggedLinks::Reveal;
+        for msg in self.messages.iter_mut() {
+            msg.revealed = revealed;
+        }
+        self.dirty = true;
     }
 
     pub fn add_error(&mut self, msg: String) {
-        self.add_message(ChatMessage::from_string("ERROR".to_string(), msg));
+        self.add_system_message(ChatMessage::from_string("ERROR".to_string(), msg));
+    }
+
+    pub fn add_info(&mut self, msg: String) {
+        self.add_system_message(ChatMessage::from_string("INFO".to_string(), msg));
+    }
+
+    fn add_system_message(&mut self, msg: ChatMessage) {
+        self.log(LogEntry::system(&msg));
+        self.add_message(msg);
     }
 
-    pub fn add_message(&mut self, msg: ChatMessage) {
-        if self.messages.len() >= 200 {
-            self.messages.drain(0..1);
+    // Writes to the chat log, logging gets disabled if that fails
+    pub fn log(&mut self, entry: LogEntry) {
+        if let Some(chat_log) = self.chat_log.as_mut() {
+            if let Err(err) = chat_log.write(&entry) {
+                self.chat_log = None;
+                self.add_error(format!("Disabled the chat log: {}", err));
+            }
         }
+    }
 
+    /// Adds a message of a chatter unless a filter hides it, the first matching filter wins.
+    /// Own messages and mentions always get through, mentions are kept for the mentions pane too.
+    pub fn add_chat_message(&mut self, mut msg: ChatMessage, emote_list: &EmoteList) {
+        if msg.mentioned && !msg.own_message {
+            let kind = match msg.highlighted {
+                true => MentionKind::Highlight,
+                false => MentionKind::Mention,
+            };
+            let mention = Mention::new(kind, self.tab, &self.channel, &msg, 0);
+            self.add_chat_line(msg, emote_list);
+            return self.add_mention(mention);
+        }
+        let filter = match msg.own_message || msg.mentioned {
+            true => None,
+            false => self
+                .filters
+                .iter_mut()
+                .find(|filter| filter.matches(&msg, emote_list, self.messages.iter())),
+        };
+        match filter.map(|filter| (filter.rule.action, filter)) {
+            Some((FilterAction::Hide, filter)) => {
+                filter.hidden += 1;
+                self.dirty = true;
+            }
+            Some((FilterAction::Collapse, filter)) => {
+                let rule = filter.rule.to_string();
+                self.collapse(rule);
+            }
+            Some((FilterAction::Dim, _)) => {
+                msg.dimmed = true;
+                self.add_chat_line(msg, emote_list);
+            }
+            None => self.add_chat_line(msg, emote_list),
+        }
+    }
+
+    // Adds the message, or counts it towards the combo of the last line
+    fn add_chat_line(&mut self, msg: ChatMessage, emote_list: &EmoteList) {
+        if self.combos && self.add_to_combo(&msg, emote_list) {
+            self.dirty = true;
+            return;
+        }
+        self.add_message(msg);
+    }
+
+    /// Keeps the whisper for the whispers pane and tells the chat about it
+    pub fn add_whisper(&mut self, msg: ChatMessage) {
+        self.add_message(ChatMessage::from_string(
+            "WHISPER".to_string(),
+            format!("{}: {}", msg.name, msg.message),
+        ));
+        let mention = Mention::new(MentionKind::Whisper, self.tab, &self.channel, &msg, 0);
+        self.add_mention(mention);
+        if self.whispers.len() == MAX_WHISPERS {
+            self.whispers.pop_front();
+        }
+        self.whispers.push_back(msg);
+    }
+
+    // Keeps the mention of the last line of the chat, a combo line if the message joined one
+    fn add_mention(&mut self, mut mention: Mention) {
+        mention.index = (self.messages.evicted() + self.messages.len()).saturating_sub(1);
+        self.mentions.push(mention);
+    }
+
+    /// How many messages the filters hid
+    pub fn hidden(&self) -> usize {
+        self.filters.iter().map(|filter| filter.hidden).sum()
+    }
+
+    // Counts the message on the last line if that one collapses the same rule already
+    fn collapse(&mut self, rule: String) {
+        self.dirty = true;
+        match self.messages.back_mut() {
+            Some(last) if last.collapsed > 0 && last.message == rule => last.collapsed += 1,
+            _ => {
+                let mut line = ChatMessage::from_string("FILTER".to_string(), rule);
+                line.collapsed = 1;
+                self.messages.push(line);
+            }
+        }
+    }
+
+    pub fn add_message(&mut self, mut msg: ChatMessage) {
+        self.dirty = true;
+        msg.revealed = self.tagged_links == TaggedLinks::Reveal;
         self.messages.push(msg);
     }
 
+    // Counts a message of a single emote towards the last message if it's the same emote
+    fn add_to_combo(&mut self, msg: &ChatMessage, emote_list: &EmoteList) -> bool {
+        let emote = msg.message.trim();
+        if emote.contains(char::is_whitespace) || parse_emote(emote, emote_list).is_none() {
+            return false;
+        }
+        match self.messages.back_mut() {
+            Some(last) if last.message.trim() == emote => {
+                last.combo = last.combo.max(1) + 1;
+                last.timestamp = msg.timestamp;
+                // the line belongs to everyone now
+                last.own_message = false;
+                last.mentioned = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn add_debug(&mut self, s: String) {
-        if self.debugs.len() >= 50 {
-            self.debugs.drain(0..1);
+        if self.debugs.len() >= self.max_debugs {
+            self.debugs.pop_front();
         }
-        self.debugs.push(format!("{}", s));
+        self.debugs.push_back(s);
+        self.dirty = true;
     }
 }
@@ -0,0 +1,225 @@
+use std::{
+    collections::{vec_deque, HashSet, VecDeque},
+    hash::Hash,
+    ops::Range,
+    sync::Arc,
+};
+
+use super::message::ChatMessage;
+
+/// A ring buffer of the newest chat messages, the oldest message is dropped once it's full.
+/// Nicks and feature lists are interned, so every chatter's name is only stored once
+/// no matter how many of their messages are kept.
+///
+/// Messages have an absolute index that keeps counting when old messages get dropped,
+/// e.g. for search results, and a relative index into the messages currently stored.
+pub struct MessageStore {
+    messages: VecDeque<ChatMessage>,
+    capacity: usize,
+    evicted: usize,
+    nicks: HashSet<Arc<str>>,
+    features: HashSet<Arc<[String]>>,
+}
+
+impl MessageStore {
+    pub fn new(capacity: usize) -> MessageStore {
+        let capacity = capacity.max(1);
+        MessageStore {
+            messages: VecDeque::with_capacity(capacity),
+            capacity,
+            evicted: 0,
+            nicks: HashSet::new(),
+            features: HashSet::new(),
+        }
+    }
+
+    pub fn push(&mut self, mut msg: ChatMessage) {
+        while self.messages.len() >= self.capacity {
+            self.evict();
+        }
+
+        msg.name = intern(&mut self.nicks, &msg.name);
+        msg.features = intern(&mut self.features, &msg.features);
+        self.messages.push_back(msg);
+    }
+
+    /// Changes the capacity, dropping the oldest messages if there are too many.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.messages.len() > self.capacity {
+            self.evict();
+        }
+        self.messages.shrink_to(self.capacity);
+    }
+
+    fn evict(&mut self) {
+        if let Some(msg) = self.messages.pop_front() {
+            self.evicted += 1;
+            release(&mut self.nicks, &msg.name);
+            release(&mut self.features, &msg.features);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// How many messages were dropped, which is also the absolute index of the oldest message.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// Converts an absolute index to a relative one, None if the message was dropped.
+    pub fn relative(&self, absolute: usize) -> Option<usize> {
+        absolute
+            .checked_sub(self.evicted)
+            .filter(|index| *index < self.messages.len())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&ChatMessage> {
+        self.messages.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut ChatMessage> {
+        self.messages.get_mut(index)
+    }
+
+    pub fn front(&self) -> Option<&ChatMessage> {
+        self.messages.front()
+    }
+
+    pub fn back(&self) -> Option<&ChatMessage> {
+        self.messages.back()
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut ChatMessage> {
+        self.messages.back_mut()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, ChatMessage> {
+        self.messages.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> vec_deque::IterMut<'_, ChatMessage> {
+        self.messages.iter_mut()
+    }
+
+    /// The messages in range, without touching the ones outside of it.
+    pub fn range(&self, range: Range<usize>) -> vec_deque::Iter<'_, ChatMessage> {
+        self.messages.range(range)
+    }
+
+    /// Every message together with its absolute index.
+    pub fn indexed(&self) -> impl DoubleEndedIterator<Item = (usize, &ChatMessage)> {
+        let offset = self.evicted;
+        self.messages
+            .iter()
+            .enumerate()
+            .map(move |(i, msg)| (offset + i, msg))
+    }
+}
+
+impl Default for MessageStore {
+    fn default() -> Self {
+        Self::new(200)
+    }
+}
+
+// Returns the already stored value if there is one
+fn intern<T: ?Sized + Eq + Hash>(set: &mut HashSet<Arc<T>>, value: &Arc<T>) -> Arc<T> {
+    match set.get(value) {
+        Some(interned) => Arc::clone(interned),
+        None => {
+            set.insert(Arc::clone(value));
+            Arc::clone(value)
+        }
+    }
+}
+
+// Forgets a value once the set and the dropped message are the last ones holding it
+fn release<T: ?Sized + Eq + Hash>(set: &mut HashSet<Arc<T>>, value: &Arc<T>) {
+    if Arc::strong_count(value) <= 2 {
+        set.remove(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(name: &str, message: &str) -> ChatMessage {
+        ChatMessage::from_string(name.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn drops_the_oldest_message() {
+        let mut store = MessageStore::new(3);
+        (0..5).for_each(|i| store.push(msg("bob", &i.to_string())));
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.evicted(), 2);
+        assert_eq!(store.front().unwrap().message, "2");
+        assert_eq!(store.back().unwrap().message, "4");
+    }
+
+    #[test]
+    fn absolute_and_relative_indices() {
+        let mut store = MessageStore::new(3);
+        (0..5).for_each(|i| store.push(msg("bob", &i.to_string())));
+        let absolute: Vec<usize> = store.indexed().map(|(i, _)| i).collect();
+        assert_eq!(absolute, vec![2, 3, 4]);
+        assert_eq!(store.relative(1), None);
+        assert_eq!(store.relative(3), Some(1));
+        assert_eq!(store.relative(5), None);
+    }
+
+    #[test]
+    fn range_only_touches_visible_messages() {
+        let mut store = MessageStore::new(10);
+        (0..10).for_each(|i| store.push(msg("bob", &i.to_string())));
+        let visible: Vec<&str> = store.range(7..9).map(|m| m.message.as_str()).collect();
+        assert_eq!(visible, vec!["7", "8"]);
+    }
+
+    #[test]
+    fn nicks_are_interned() {
+        let mut store = MessageStore::new(10);
+        store.push(msg("bob", "a"));
+        store.push(msg("bob", "b"));
+        assert!(Arc::ptr_eq(
+            &store.get(0).unwrap().name,
+            &store.get(1).unwrap().name
+        ));
+        assert!(Arc::ptr_eq(
+            &store.get(0).unwrap().features,
+            &store.get(1).unwrap().features
+        ));
+    }
+
+    #[test]
+    fn memory_stays_bounded() {
+        let mut store = MessageStore::new(10_000);
+        (0..50_000).for_each(|i| store.push(msg(&format!("user{}", i), "hi")));
+        assert_eq!(store.len(), 10_000);
+        assert_eq!(store.evicted(), 40_000);
+        // the nicks of dropped messages are forgotten
+        assert_eq!(store.nicks.len(), 10_000);
+    }
+
+    #[test]
+    fn shrink_capacity() {
+        let mut store = MessageStore::new(10);
+        (0..10).for_each(|i| store.push(msg("bob", &i.to_string())));
+        store.set_capacity(4);
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.evicted(), 6);
+        assert_eq!(store.front().unwrap().message, "6");
+    }
+}
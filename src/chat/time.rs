@@ -0,0 +1,103 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the unix epoch, the same unit the websocket uses for timestamps.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Converts days since the unix epoch to a (year, month, day) date
+// Taken from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Converts a (year, month, day) date to days since the unix epoch
+// Taken from http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parses a UTC "2022-10-26 21:06:29" back into milliseconds, the reverse of format_datetime.
+pub fn parse_datetime(s: &str) -> Option<u64> {
+    let (date, time) = s.split_once(' ')?;
+    let date: Vec<u32> = date
+        .split('-')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    if date.len() != 3 || time.len() != 3 {
+        return None;
+    }
+
+    let days = days_from_civil(date[0] as i64, date[1], date[2]);
+    let seconds = u64::try_from(days).ok()? * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+    Some(seconds * 1000)
+}
+
+/// The UTC date of a timestamp in milliseconds, e.g. 2022-10-26
+pub fn format_date(millis: u64) -> String {
+    let (year, month, day) = civil_from_days((millis / 1000 / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The UTC time of a timestamp in milliseconds, e.g. 13:37:00
+pub fn format_time(millis: u64) -> String {
+    let seconds = millis / 1000 % 86400;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+pub fn format_datetime(millis: u64) -> String {
+    format!("{} {}", format_date(millis), format_time(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_epoch() {
+        assert_eq!(format_datetime(0), "1970-01-01 00:00:00");
+    }
+
+    #[test]
+    fn format_timestamp() {
+        // 2022-10-26T21:06:29.374Z
+        assert_eq!(format_datetime(1666818389374), "2022-10-26 21:06:29");
+        // leap day
+        assert_eq!(format_date(951782400000), "2000-02-29");
+    }
+
+    #[test]
+    fn parse_formatted() {
+        assert_eq!(parse_datetime("2022-10-26 21:06:29"), Some(1666818389000));
+        assert_eq!(parse_datetime("2000-02-29 00:00:00"), Some(951782400000));
+        assert_eq!(parse_datetime("2022-10-26"), None);
+        assert_eq!(parse_datetime("yesterday 21:06:29"), None);
+    }
+}
@@ -1,4 +1,5 @@
-use super::features::Feature;
+use super::features::{Flair, Flairs};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -8,19 +9,18 @@ pub struct User {
     pub name: String,
     #[serde(skip_deserializing)]
     pub timestamp: String,
-    #[serde(skip_deserializing)]
-    pub flair: Feature,
+    /// The known flairs of features, most important first
+    #[serde(skip)]
+    pub flairs: Vec<Flair>,
 }
 
 impl User {
-    pub fn from_json(json: &str) -> User {
-        let mut user: User = serde_json::from_str(json).unwrap();
-        user.parse_flair();
-        user
+    pub fn from_json(json: &str) -> Result<User> {
+        Ok(serde_json::from_str(json)?)
     }
 
-    pub fn parse_flair(&mut self) {
-        self.flair = Feature::parse_flair(&self.features);
+    pub fn parse_flair(&mut self, flairs: &Flairs) {
+        self.flairs = flairs.parse(&self.features);
     }
 }
 
@@ -30,7 +30,7 @@ impl Default for User {
             features: Vec::new(),
             name: String::from("default_name"),
             timestamp: String::from("default_timestamp"),
-            flair: Feature::White,
+            flairs: Vec::new(),
         }
     }
 }
@@ -42,9 +42,21 @@ pub struct UserList {
     pub users: Vec<User>,
 }
 
+impl Default for UserList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UserList {
-    pub fn from_json(json: &str) -> UserList {
-        serde_json::from_str(json).unwrap()
+    pub fn from_json(json: &str) -> Result<UserList> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn get(&self, nick: &str) -> Option<&User> {
+        self.users
+            .iter()
+            .find(|user| user.name.eq_ignore_ascii_case(nick))
     }
 
     pub fn append(&mut self, other: &mut UserList) {
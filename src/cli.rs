@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+pub const USAGE: &str = "Usage: dgg [--profile <name>] [--anonymous | --token-file <file>]
+           [--replay <file> [--speed <factor>]]
+
+Options:
+    --profile <name>    Uses the profile from the config instead of the default one
+    --anonymous         Reads the chat without logging in
+    --token-file <file> Reads the login key from file instead of the stored one, DGG_TOKEN works too
+    --replay <file>     Plays a chat log or a recorded websocket transcript instead of connecting
+    --speed <factor>    Playback speed of the replay, 0 plays everything at once (default: 1)
+    -h, --help          Prints this help";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub profile: Option<String>,
+    pub anonymous: bool,
+    pub token_file: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub speed: f64,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            profile: None,
+            anonymous: false,
+            token_file: None,
+            replay: None,
+            speed: 1.0,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn from_env() -> Result<Args> {
+        Args::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--profile" => parsed.profile = Some(value(&arg, args.next())?),
+                "--anonymous" => parsed.anonymous = true,
+                "--token-file" => {
+                    parsed.token_file = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--replay" => parsed.replay = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--speed" => {
+                    let speed = value(&arg, args.next())?;
+                    parsed.speed = match speed.parse::<f64>() {
+                        Ok(speed) if speed >= 0.0 => speed,
+                        _ => bail!("--speed expects a positive number, got {}.", speed),
+                    };
+                }
+                "-h" | "--help" => parsed.help = true,
+                _ => bail!("Unknown argument {}.\n\n{}", arg, USAGE),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String> {
+    match value {
+        Some(value) => Ok(value),
+        None => bail!("{} expects a value.\n\n{}", arg, USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_args() {
+        assert_eq!(parse(&[]).unwrap(), Args::default());
+    }
+
+    #[test]
+    fn replay_with_speed() {
+        let args = parse(&["--replay", "2022-10-26.jsonl", "--speed", "10"]).unwrap();
+        assert_eq!(args.replay, Some(PathBuf::from("2022-10-26.jsonl")));
+        assert_eq!(args.speed, 10.0);
+    }
+
+    #[test]
+    fn token_file() {
+        let args = parse(&["--token-file", "/run/secrets/dgg"]).unwrap();
+        assert_eq!(args.token_file, Some(PathBuf::from("/run/secrets/dgg")));
+    }
+
+    #[test]
+    fn anonymous() {
+        assert!(parse(&["--anonymous"]).unwrap().anonymous);
+    }
+
+    #[test]
+    fn profile() {
+        let args = parse(&["--profile", "work"]).unwrap();
+        assert_eq!(args.profile.as_deref(), Some("work"));
+        assert!(parse(&["--profile"]).is_err());
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(parse(&["--replay"]).is_err());
+        assert!(parse(&["--token-file"]).is_err());
+        assert!(parse(&["--speed", "-1"]).is_err());
+        assert!(parse(&["--nope"]).is_err());
+    }
+}
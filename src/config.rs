@@ -1,24 +1,273 @@
 use std::{
-    fs::{self, File},
-    io::{self, BufReader},
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
     path::{Path, PathBuf},
 };
 
-use anyhow::bail;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::chat::filter::{self, FilterRule};
+use crate::ui::{
+    color::ColorDepth,
+    keymap::{KeyAction, Keymap, Preset},
+    layout::LayoutNode,
+    theme::{StyleConfig, Theme},
+};
 
 const FILE_NAME: &str = "config.json";
+// Used instead of config.json if it exists
+const TOML_FILE_NAME: &str = "config.toml";
+/// Bumped whenever older files need a migration
+pub const CONFIG_VERSION: u64 = 2;
+const TOKEN_FILE_NAME: &str = "token";
+pub const DEFAULT_PROFILE: &str = "default";
 const CONFIG_DIR: &str = ".config";
 const APP_NAME: &str = "dgg-tui";
+const FLAIRS_FILE_NAME: &str = "flairs.json";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Missing keys get their default, older versions are migrated on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
+    pub version: u64,
+    /// Only read to move old login keys to the TokenStore
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub token: String,
-    pub name: String,
+    /// The profile used without --profile
+    pub default_profile: String,
+    pub profiles: BTreeMap<String, Profile>,
+    pub logging: LogConfig,
+    pub scrollback: ScrollbackConfig,
+    pub theme: ThemeConfig,
+    pub notifications: NotificationConfig,
+    pub keys: KeyConfig,
+    pub links: LinkConfig,
+    pub chat: ChatConfig,
+    /// Managed with /filter, the first matching rule wins
+    pub filters: Vec<FilterRule>,
+    /// Saved on quit after the panes were toggled or resized
+    pub layout: LayoutNode,
     #[serde(skip_serializing, skip_deserializing)]
     pub path: Option<PathBuf>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            token: String::new(),
+            default_profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Profile::default())]),
+            logging: LogConfig::default(),
+            scrollback: ScrollbackConfig::default(),
+            theme: ThemeConfig::default(),
+            notifications: NotificationConfig::default(),
+            keys: KeyConfig::default(),
+            links: LinkConfig::default(),
+            chat: ChatConfig::default(),
+            filters: Vec::new(),
+            layout: LayoutNode::standard(),
+            path: None,
+        }
+    }
+}
+
+/// What reading the config file found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loaded {
+    /// There is no file yet, so the defaults are used
+    Missing,
+    Current,
+    /// The file had this older version and should be saved again
+    Migrated(u64),
+}
+
+/// An account to chat with, selected with --profile or /profile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// The nick to highlight until destiny.gg tells us the real one
+    pub name: String,
+    /// Reads the chat without logging in, like --anonymous
+    pub anonymous: bool,
+    pub server: ServerConfig,
+    /// Words that highlight a message like a mention
+    pub highlights: Vec<String>,
+    /// Nicks whose messages are hidden
+    pub ignores: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// The websocket of the chat
+    pub chat: String,
+    /// Where /api/chat/me and /api/chat/history live
+    pub api: String,
+    /// Where /flairs/flairs.json lives
+    pub cdn: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            chat: "wss://chat.destiny.gg/ws".to_string(),
+            api: "https://www.destiny.gg".to_string(),
+            cdn: "https://cdn.destiny.gg".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub enabled: bool,
+    pub format: LogFormat,
+    /// Defaults to the data directory, e.g. ~/.local/share/dgg-tui/logs
+    pub dir: Option<PathBuf>,
+    /// A new file is started once the current one reaches this size in bytes
+    pub max_file_size: u64,
+    /// Files older than this are deleted, 0 keeps them forever
+    pub max_age_days: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            enabled: false,
+            format: LogFormat::Text,
+            dir: None,
+            max_file_size: 10 * 1024 * 1024,
+            max_age_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrollbackConfig {
+    /// How many chat messages are kept in memory, older ones get dropped
+    pub messages: usize,
+    /// How many entries the debug window keeps
+    pub debugs: usize,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        ScrollbackConfig {
+            messages: 200,
+            debugs: 50,
+        }
+    }
+}
+
+/// Colours are names like "lightred" or hex like "#0a283c"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// dark, light or high-contrast
+    pub name: String,
+    /// A TOML or JSON file with styles and flair colours, relative to the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub color_depth: ColorDepth,
+    /// Changes on top of the theme and the theme file, e.g. "mention": { "bg": "blue" }
+    pub styles: BTreeMap<String, StyleConfig>,
+    pub flairs: BTreeMap<String, String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            name: "dark".to_string(),
+            file: None,
+            color_depth: ColorDepth::Auto,
+            styles: BTreeMap::new(),
+            flairs: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Rings the terminal bell when someone mentions you
+    pub bell: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatConfig {
+    /// Consecutive messages of the same emote become one line, e.g. "PEPE x 23 C-C-C-COMBO"
+    pub combos: bool,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig { combos: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkConfig {
+    /// Links tagged nsfw, nsfl, loud or weeb
+    pub tagged: TaggedLinks,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaggedLinks {
+    /// Masked until revealed with a key
+    #[default]
+    Mask,
+    Reveal,
+    /// Masked, the key doesn't reveal them
+    Hide,
+}
+
+impl TaggedLinks {
+    pub const NAMES: &'static [&'static str] = &["mask", "reveal", "hide"];
+
+    pub fn parse(name: &str) -> Result<TaggedLinks> {
+        match name {
+            "mask" => Ok(TaggedLinks::Mask),
+            "reveal" => Ok(TaggedLinks::Reveal),
+            "hide" => Ok(TaggedLinks::Hide),
+            _ => bail!("{} is not one of {}", name, TaggedLinks::NAMES.join(", ")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaggedLinks::Mask => "mask",
+            TaggedLinks::Reveal => "reveal",
+            TaggedLinks::Hide => "hide",
+        }
+    }
+}
+
+/// Keys are chords like "ctrl-f" or sequences like "g g", see the README
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    pub preset: Preset,
+    /// Added to the bindings of the preset, replacing the ones with the same keys
+    pub insert: BTreeMap<String, KeyAction>,
+    /// Bindings of the normal mode of the vim preset
+    pub normal: BTreeMap<String, KeyAction>,
+}
+
 impl Config {
     pub fn get_or_build_paths(&mut self) -> Result<(), anyhow::Error> {
         match dirs::home_dir() {
@@ -35,9 +284,13 @@ impl Config {
                     fs::create_dir(&app_config_dir)?;
                 }
 
-                let config_file_path = &app_config_dir.join(FILE_NAME);
+                let toml_path = app_config_dir.join(TOML_FILE_NAME);
+                let config_file_path = match toml_path.exists() {
+                    true => toml_path,
+                    false => app_config_dir.join(FILE_NAME),
+                };
 
-                self.path = Some(config_file_path.to_path_buf());
+                self.path = Some(config_file_path);
 
                 Ok(())
             }
@@ -45,84 +298,312 @@ impl Config {
         }
     }
 
-    pub fn askers(&mut self) -> anyhow::Result<()> {
-        if self.token.len() == 64 {
-            return Ok(());
+    /// Where the config file is, theme files are looked up here
+    pub fn dir(&self) -> Option<&Path> {
+        self.path.as_deref().and_then(Path::parent)
+    }
+
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.logging.dir {
+            return Some(dir.to_path_buf());
         }
-        println!(
-            "Creating a config file at {}",
-            self.path.as_ref().unwrap().display()
-        );
-        println!("Please get your login token at https://www.destiny.gg/profile/developer");
-        println!("Go to Connections and press the 'Add login key' Button.");
-        println!("Please paste the DGG Login Key into this console and press enter.");
-        println!("");
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                self.token = input.trim().to_string();
-            }
-            Err(_) => (),
+
+        match dirs::data_dir() {
+            Some(data) => Some(data.join(APP_NAME).join("logs")),
+            None => self
+                .path
+                .as_ref()
+                .and_then(|path| path.parent())
+                .map(|dir| dir.join("logs")),
         }
+    }
 
-        while self.token.len() != 64 {
-            println!("");
-            println!("There is something wrong with your token!");
-            println!("Please make sure you properly pasted it into the console.");
-            println!("Please paste it in again and confirm with enter.");
+    /// The flairs.json of the last start, e.g. ~/.cache/dgg-tui/flairs.json
+    pub fn flairs_cache(&self) -> Option<PathBuf> {
+        match dirs::cache_dir() {
+            Some(cache) => Some(cache.join(APP_NAME).join(FLAIRS_FILE_NAME)),
+            None => self.dir().map(|dir| dir.join(FLAIRS_FILE_NAME)),
+        }
+    }
 
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-                Ok(_) => {
-                    self.token = input.trim().to_string();
-                }
-                Err(_) => (),
-            }
+    /// Where the login key of a profile is kept when there is no Secret Service
+    pub fn token_file(&self, profile: &str) -> Option<PathBuf> {
+        let file_name = match profile {
+            DEFAULT_PROFILE => TOKEN_FILE_NAME.to_string(),
+            profile => format!("{}-{}", TOKEN_FILE_NAME, profile),
+        };
+        self.path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map(|dir| dir.join(file_name))
+    }
+
+    /// The profile with the name, or the default one.
+    pub fn profile(&self, name: Option<&str>) -> Result<(String, Profile)> {
+        let name = name.unwrap_or(&self.default_profile);
+        match self.profiles.get(name) {
+            Some(profile) => Ok((name.to_string(), profile.clone())),
+            None => bail!(
+                "There is no profile {}, the config has {}.",
+                name,
+                self.profile_names().join(", ")
+            ),
         }
-        println!("");
-        println!("Successfully saved the token!");
+    }
+
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.keys().map(|name| name.as_str()).collect()
+    }
 
-        println!("Please write your dgg username and confirm with enter.");
-        println!("");
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                self.name = input.trim().to_string();
+    /// Reads, migrates and validates the config file, errors name the file and the bad key.
+    pub fn read_user_data_from_file(&mut self) -> Result<Loaded> {
+        let path = self.path.take().unwrap();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                self.path = Some(path);
+                return Ok(Loaded::Missing);
             }
-            Err(_) => (),
-        }
+            Err(err) => bail!("Could not read {}: {}", path.display(), err),
+        };
 
-        Ok(())
+        let (config, loaded) = Config::parse(&text, is_toml(&path))
+            .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+        *self = config;
+        self.path = Some(path);
+        Ok(loaded)
+    }
+
+    /// Parses a config file of any version.
+    pub fn parse(text: &str, toml: bool) -> Result<(Config, Loaded)> {
+        let mut value: Value = match toml {
+            true => serde_json::to_value(toml::from_str::<toml::Table>(text)?)?,
+            false => serde_json::from_str(text)?,
+        };
+        let version = migrate(&mut value)?;
+
+        let config: Config = serde_path_to_error::deserialize(value)
+            .map_err(|err| anyhow::anyhow!("{}: {}", err.path(), err.inner()))?;
+        config.validate()?;
+
+        let loaded = match version {
+            CONFIG_VERSION => Loaded::Current,
+            version => Loaded::Migrated(version),
+        };
+        Ok((config, loaded))
     }
 
-    pub fn read_user_data_from_file(&mut self) -> anyhow::Result<()> {
-        let file = File::open(self.path.as_ref().unwrap());
+    /// Checks what serde can't, the error starts with the bad key.
+    pub fn validate(&self) -> Result<()> {
+        if self.scrollback.messages == 0 {
+            bail!("scrollback.messages: keeps at least 1 message");
+        }
+        if self.scrollback.debugs == 0 {
+            bail!("scrollback.debugs: keeps at least 1 entry");
+        }
+        if self.logging.max_file_size == 0 {
+            bail!("logging.max_file_size: must be more than 0 bytes");
+        }
+        if !self.profiles.contains_key(&self.default_profile) {
+            bail!(
+                "default_profile: there is no profile {}",
+                self.default_profile
+            );
+        }
 
-        if file.is_err() {
-            bail!("No config file exists.");
+        for (name, profile) in &self.profiles {
+            let server = &profile.server;
+            if !server.chat.starts_with("ws://") && !server.chat.starts_with("wss://") {
+                bail!(
+                    "profiles.{}.server.chat: must start with ws:// or wss://",
+                    name
+                );
+            }
+            if !server.api.starts_with("http://") && !server.api.starts_with("https://") {
+                bail!(
+                    "profiles.{}.server.api: must start with http:// or https://",
+                    name
+                );
+            }
         }
 
-        let reader = BufReader::new(file.unwrap());
-        let config: Config = serde_json::from_reader(reader)?;
-        *self = config;
+        // the theme file is only read on start, see Theme::load
+        Theme::new(&self.theme)?;
+
+        // names the key of the broken binding
+        Keymap::new(&self.keys)?;
+
+        filter::compile(&self.filters)?;
+        self.layout.validate()?;
 
         Ok(())
     }
 
-    pub fn save_to_config_file(&self) -> anyhow::Result<()> {
-        std::fs::write(
-            self.path.as_ref().unwrap(),
-            serde_json::to_string_pretty(&self).unwrap(),
-        )?;
+    pub fn save_to_config_file(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => bail!("There is no config file to save to."),
+        };
+        let text = match is_toml(path) {
+            true => toml::to_string_pretty(&self)?,
+            false => serde_json::to_string_pretty(&self)?,
+        };
+        fs::write(path, text)?;
         Ok(())
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            token: String::from(""),
-            name: String::from(""),
-            path: None,
-        }
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// Brings the config up to the current version and returns the version it had.
+/// Files without a version are from before versions were added.
+fn migrate(value: &mut Value) -> Result<u64> {
+    let config = match value.as_object_mut() {
+        Some(config) => config,
+        None => bail!("the config has to be a table of settings"),
+    };
+    let version = match config.get("version") {
+        None => 1,
+        Some(version) => match version.as_u64() {
+            Some(version) => version,
+            None => bail!("version: {} is not a number", version),
+        },
+    };
+    if version > CONFIG_VERSION {
+        bail!(
+            "version: {} is newer than this dgg understands ({})",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    // 2 moved the name into profiles
+    if version < 2 {
+        let name = config.remove("name").unwrap_or_else(|| json!(""));
+        let anonymous = config.remove("anonymous").unwrap_or(Value::Bool(false));
+        config.entry("profiles").or_insert_with(
+            || json!({ DEFAULT_PROFILE: { "name": name, "anonymous": anonymous } }),
+        );
+    }
+
+    config.insert("version".to_string(), json!(CONFIG_VERSION));
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config> {
+        Config::parse(text, false).map(|(config, _)| config)
+    }
+
+    #[test]
+    fn old_configs_become_the_default_profile() {
+        let (config, loaded) =
+            Config::parse(r#"{"token":"","name":"bob","anonymous":true}"#, false).unwrap();
+        assert_eq!(loaded, Loaded::Migrated(1));
+        assert_eq!(config.version, CONFIG_VERSION);
+
+        let (name, profile) = config.profile(None).unwrap();
+        assert_eq!(name, DEFAULT_PROFILE);
+        assert_eq!(profile.name, "bob");
+        assert!(profile.anonymous);
+        assert_eq!(profile.server, ServerConfig::default());
+
+        let saved = serde_json::to_string(&config).unwrap();
+        let (_, loaded) = Config::parse(&saved, false).unwrap();
+        assert_eq!(loaded, Loaded::Current);
+    }
+
+    #[test]
+    fn missing_keys_get_defaults() {
+        let config = parse(r#"{"version":2,"scrollback":{"debugs":10}}"#).unwrap();
+        assert_eq!(config.scrollback.messages, 200);
+        assert_eq!(config.scrollback.debugs, 10);
+        assert!(config.profile(None).is_ok());
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let err = parse(r#"{"scrollback":{"messages":"many"}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("scrollback.messages: "));
+
+        let err = parse(r#"{"scrollback":{"messages":0}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("scrollback.messages: "));
+
+        let err = parse(r##"{"theme":{"styles":{"mention":{"bg":"#nope"}}}}"##).unwrap_err();
+        assert!(err.to_string().starts_with("theme.styles.mention.bg: "));
+
+        let err = parse(r#"{"theme":{"name":"neon"}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("theme.name: "));
+
+        let err = parse(r#"{"theme":{"color_depth":"8"}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("theme.color_depth: "));
+
+        let err = parse(
+            r#"{"default_profile":"work","profiles":{"work":{"server":{"chat":"http://x"}}}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("profiles.work.server.chat: "));
+
+        let err = parse(r#"{"keys":{"preset":"nano"}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("keys.preset: "));
+
+        let err = parse(r#"{"keys":{"insert":{"ctrl-q":"explode"}}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("keys.insert.ctrl-q: "));
+
+        let err = parse(r#"{"keys":{"normal":{"hyper-q":"quit"}}}"#).unwrap_err();
+        assert!(err.to_string().starts_with("keys.normal.hyper-q: "));
+
+        let err = parse(r#"{"filters":[{"action":"hide","match":"bots"},{"action":"dim","match":{"regex":"("}}]}"#)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("filters.1: "));
+
+        let err =
+            parse(r#"{"layout":{"split":"vertical","children":[{"pane":"debug"}]}}"#).unwrap_err();
+        assert_eq!(err.to_string(), "layout: needs a chat pane");
+
+        let err = parse(r#"{"version":99}"#).unwrap_err();
+        assert!(err.to_string().starts_with("version: "));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut config = Config::default();
+        config.scrollback.messages = 1000;
+        let text = toml::to_string_pretty(&config).unwrap();
+        let (read, loaded) = Config::parse(&text, true).unwrap();
+        assert_eq!(loaded, Loaded::Current);
+        assert_eq!(read.scrollback.messages, 1000);
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let config = Config::default();
+        let err = config.profile(Some("work")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "There is no profile work, the config has default."
+        );
+    }
+
+    #[test]
+    fn token_file_per_profile() {
+        let config = Config {
+            path: Some(PathBuf::from("/config/dgg-tui/config.json")),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.token_file(DEFAULT_PROFILE),
+            Some(PathBuf::from("/config/dgg-tui/token"))
+        );
+        assert_eq!(
+            config.token_file("work"),
+            Some(PathBuf::from("/config/dgg-tui/token-work"))
+        );
     }
 }
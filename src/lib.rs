@@ -1,5 +1,12 @@
+pub mod app;
 pub mod chat;
+pub mod cli;
 pub mod config;
 pub mod network;
+pub mod replay;
+pub mod token;
 pub mod ui;
 pub mod ws_error;
+
+#[cfg(test)]
+mod test_util;
@@ -1,57 +1,126 @@
-use std::io::{self};
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::env;
+use std::io::{self, Write};
+use std::time::Duration;
 
+use anyhow::Context;
 use crossterm::cursor::{Hide, Show};
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen, SetTitle};
 use crossterm::{execute, terminal};
+use dgg::app::{self, App, Event, Flow};
 use dgg::chat::action::Action;
-use dgg::chat::command::parse_command_to_action;
+use dgg::chat::chat_log::ChatLog;
+use dgg::chat::features::Flairs;
+use dgg::chat::filter;
 use dgg::chat::state::State;
-use dgg::config::Config;
-use dgg::network::Network;
+use dgg::cli::{Args, USAGE};
+use dgg::config::{Config, Loaded};
+use dgg::network::{Network, Session};
+use dgg::replay::read_frames;
+use dgg::token::{self, Token, TokenStore, TOKEN_ENV};
 
 use dgg::ui::emotes::EmoteList;
-use dgg::ui::render;
-use dgg::ui::suggester::Suggestor;
-use dgg::ui::window::{WindowList, WindowType};
-use tokio::sync::Mutex;
-use tokio_tungstenite::tungstenite::Message;
+use dgg::ui::keymap::Keymap;
+use dgg::ui::theme::Theme;
+use tokio::sync::mpsc::unbounded_channel;
 use tui::{backend::CrosstermBackend, Terminal};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // TODO Read user input
+    let args = Args::from_env()?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
     let mut config = Config::default();
     config.get_or_build_paths()?;
-    let file_found = config.read_user_data_from_file();
-    match file_found {
-        Ok(_) => (),
-        Err(_) => {
-            config.askers()?;
-            config.save_to_config_file()?;
-        }
+    // Older versions are saved in the current format right away
+    if config.read_user_data_from_file()? != Loaded::Current {
+        config.save_to_config_file()?;
     }
 
-    let emote_list = EmoteList::new();
-    let mut suggestor = Suggestor::new(&emote_list);
-    let mut windows = WindowList::new();
+    // Older versions kept the login key in the config file, a broken one is dropped
+    let mut token_error = None;
+    if !config.token.is_empty() {
+        match Token::parse(&config.token) {
+            Ok(token) => {
+                let file = config.token_file(&config.default_profile);
+                TokenStore::new(file.context("No config directory")?).save(&token)?;
+            }
+            Err(err) => token_error = Some(err),
+        }
+        config.token.clear();
+        config.save_to_config_file()?;
+    }
 
-    let (chat_msg_sender, chat_msg_recv) = futures::channel::mpsc::channel(1);
-    let (io_sender, io_recv) = std::sync::mpsc::channel();
-    let io_sender_2 = io_sender.clone();
+    let (profile_name, profile) = config.profile(args.profile.as_deref())?;
+    let store = TokenStore::new(
+        config
+            .token_file(&profile_name)
+            .context("No config directory")?,
+    );
+
+    let replay = match &args.replay {
+        Some(path) => Some((read_frames(path)?, args.speed)),
+        None => None,
+    };
+    let replaying = replay.is_some();
+    let anonymous = args.anonymous || profile.anonymous;
+    // a replay doesn't need a login key
+    let token = match replaying || anonymous {
+        true => None,
+        false => token::resolve(args.token_file.as_deref(), env::var(TOKEN_ENV).ok(), &store)?,
+    };
+
+    let mut state = State::new(String::new());
+    state.set_profile(&profile);
+    state.set_scrollback(&config.scrollback);
+    state.set_tagged_links(config.links.tagged);
+    state.combos = config.chat.combos;
+    state.filters = filter::compile(&config.filters)?;
+    state.theme = match Theme::load(&config.theme, config.dir()) {
+        Ok(theme) => theme,
+        Err(err) => {
+            state.add_error(format!("Could not load the theme: {}", err));
+            Theme::new(&config.theme)?
+        }
+    };
+    if let Some(file) = config.flairs_cache() {
+        match Flairs::read_cache(&file) {
+            Ok(Some(flairs)) => state.flairs.update(flairs),
+            Ok(None) => (),
+            Err(err) => state.add_error(format!("Could not read the cached flairs: {}", err)),
+        }
+    }
+    if let Some(err) = token_error {
+        state.add_error(format!("Dropped the login key of the config file: {}", err));
+    }
+    state.anonymous = anonymous;
+    // a replay may be reading today's log, it must not write to it
+    if !replaying {
+        match ChatLog::from_config(&config) {
+            Ok(chat_log) => state.chat_log = chat_log,
+            Err(err) => state.add_error(format!("Could not open the chat log: {}", err)),
+        }
+    }
 
-    let state = Arc::new(Mutex::new(State::new(config.name.to_string(), io_sender)));
-    let cloned_state = Arc::clone(&state);
+    // Every task sends its events to the reducer, which passes network requests on
+    let (events, mut event_recv) = unbounded_channel();
+    let (requests, request_recv) = unbounded_channel();
 
-    // Network Thread
-    std::thread::spawn(move || {
-        let mut network = Network::new(&config.token, &state, chat_msg_sender);
-        start_tokio(io_recv, io_sender_2, chat_msg_recv, &mut network);
-    });
+    let emote_list = EmoteList::new();
+    let mut app = App::new(state, &emote_list, requests, replaying);
+    app.token_store = Some(store);
+    app.keymap = Keymap::new(&config.keys)?;
+    app.config = config;
+    app.state.channel = profile_name.to_string();
+    app.profile = profile_name;
+    app.events = Some(events.clone());
+
+    let network = Network::new(events.clone(), 0);
+    let network = tokio::spawn(network.run(replay, request_recv));
+    let input = tokio::spawn(app::read_input(events.clone()));
+    let timer = tokio::spawn(app::tick(events, Duration::from_millis(16)));
 
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -64,132 +133,43 @@ async fn main() -> anyhow::Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).unwrap();
 
-    let tick_rate = Duration::from_millis(16);
-    let last_tick = Instant::now();
-
-    // TODO make destiny.gg/api/chat/me work
-    // state.dispatch(Action::GetMe);
-    let state = cloned_state.lock().await;
-    state.dispatch(Action::GetChatHistory);
-    drop(state);
-
-    loop {
-        let mut state = cloned_state.lock().await;
-
-        // Can be used to debug without GUI
-        // if state.messages.len() > 0 && last_index != state.messages.len() - 1 {
-        //     last_index = state.messages.len() - 1;
-        //     if state.messages[last_index].name == "ERROR" {
-        //         println!("{}", "-".repeat(20));
-        //         println!("{}", state.messages[last_index]);
-        //         println!("{}", "-".repeat(20));
-        //     }
-        //     println!("{}", state.messages[last_index]);
-        // }
-
-        match terminal
-            .draw(|f| render::draw(f, &state, &emote_list, &suggestor, &mut windows).unwrap())
-        {
-            Ok(_) => (),
-            Err(_) => break,
+    if !replaying {
+        app.update(Event::Action(Action::Connect(Session {
+            server: profile.server,
+            token: token.map(|(token, _)| token),
+            anonymous,
+        })));
+        app.update(Event::Action(Action::GetChatHistory));
+        app.update(Event::Action(Action::GetFlairs(app.config.flairs_cache())));
+    }
+
+    while let Some(event) = event_recv.recv().await {
+        let tick = matches!(event, Event::Tick);
+        if app.update(event) == Flow::Quit {
+            break;
         }
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-
-        if crossterm::event::poll(timeout)? {
-            if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
-                // match keys with modifiers
-                match key {
-                    KeyEvent {
-                        code: KeyCode::Backspace,
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }
-                    | KeyEvent {
-                        // Gnome Terminal uses Control+h as Backspace
-                        code: KeyCode::Char('h'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    } => {
-                        state.chat_input.delete_current_word();
-                        suggestor.suggestions.clear();
-                    }
-                    // match keys without modifiers
-                    _ => match key.code {
-                        KeyCode::Esc => {
-                            state.dispatch(Action::QuitApp);
-                            break;
-                        }
-                        KeyCode::Char(c) => {
-                            state.chat_input.current_message.push(c);
-                            suggestor.update(&state.ul, state.chat_input.get_current_word());
-                        }
-                        KeyCode::Backspace => {
-                            state.chat_input.current_message.pop();
-                            suggestor.update(&state.ul, state.chat_input.get_current_word());
-                        }
-                        KeyCode::Tab => {
-                            // Autocomplete: delete the current word and add the suggestion
-                            if suggestor.suggestions.len() > 0 {
-                                state.chat_input.delete_current_word();
-                                state.chat_input.current_message.push_str(&suggestor.get())
-                            }
-                        }
-                        KeyCode::Enter => {
-                            if state.chat_input.current_message.starts_with("/") {
-                                match parse_command_to_action(&state.chat_input.current_message) {
-                                    Ok(action) => state.dispatch(action),
-                                    Err(err) => state.add_error(err.to_string()),
-                                }
-                                state.chat_input.add();
-                            } else if state.chat_input.current_message.starts_with(":q") {
-                                break;
-                            } else {
-                                state.dispatch(Action::SendMsg);
-                            }
-                            suggestor.suggestions.clear();
-                        }
-                        KeyCode::Up => {
-                            state.chat_input.next();
-                        }
-                        KeyCode::Down => {
-                            state.chat_input.prev();
-                        }
-                        KeyCode::F(1) => windows.get_mut(WindowType::Debug).flip(),
-                        KeyCode::F(2) => windows.get_mut(WindowType::UserList).flip(),
-                        KeyCode::F(3) => windows.get_mut(WindowType::Chat).auto_scroll = true,
-                        KeyCode::PageUp => {
-                            windows.get_mut(WindowType::Chat).scroll(-2);
-                        }
-                        KeyCode::PageDown => {
-                            windows.get_mut(WindowType::Chat).scroll(2);
-                        }
-                        _ => (),
-                    },
-                }
+        // Draw at most once per tick and only when something changed
+        if tick && app.state.dirty {
+            let mut drawn = Ok(());
+            if terminal.draw(|f| drawn = app.draw(f)).is_err() || drawn.is_err() {
+                app.update(Event::Action(Action::QuitApp));
+                break;
+            }
+            if std::mem::take(&mut app.state.bell) {
+                let _ = terminal.backend_mut().write_all(b"\x07");
+                let _ = terminal.backend_mut().flush();
             }
         }
-        // sleep for lower CPU usage
-        thread::sleep(tick_rate);
     }
 
+    // The network closes the websocket after QuitApp
+    input.abort();
+    timer.abort();
+    let _ = tokio::time::timeout(Duration::from_secs(2), network).await;
+
     let mut stdout = io::stdout();
     execute!(stdout, Show, LeaveAlternateScreen,)?;
     terminal::disable_raw_mode()?;
     Ok(())
 }
-
-#[tokio::main]
-async fn start_tokio(
-    io_recv: Receiver<Action>,
-    io_sender: Sender<Action>,
-    chat_msg_recv: futures::channel::mpsc::Receiver<Message>,
-    network: &mut Network,
-) {
-    network.start_websocket(io_sender, chat_msg_recv).await;
-    while let Ok(action) = io_recv.recv() {
-        network.handle_io(action).await;
-    }
-}
@@ -1,44 +1,134 @@
-use crate::chat::{action::Action, api::ApiCaller, message::ChatMessage, state::State};
-use futures::{channel::mpsc::Receiver, SinkExt, StreamExt};
-use reqwest::StatusCode;
-use std::sync::{mpsc::Sender, Arc};
-use tokio::sync::Mutex;
+use std::{path::PathBuf, time::Duration};
+
+use crate::app::{Event, EventSender};
+use crate::chat::{action::Action, api::ApiCaller, features::Flairs};
+use crate::config::ServerConfig;
+use crate::replay::{self, Frame};
+use crate::token::Token;
+use anyhow::Result;
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use serde_json::json;
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{self, handshake::client::Request, Message},
 };
 
-pub struct Network<'a> {
-    state: &'a Arc<Mutex<State>>,
-    api_caller: ApiCaller<'a>,
-    token: &'a str,
-    chat_msg_sender: futures::channel::mpsc::Sender<Message>,
+/// Who to connect as and where, sent with Action::Connect
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub server: ServerConfig,
+    pub token: Option<Token>,
+    /// Connects without the login key, the chat can only be read
+    pub anonymous: bool,
+}
+
+/// Talks to the websocket and the APIs. It runs as its own task, gets requests from the
+/// reducer and sends everything it receives back as Events.
+/// Every tab has one, the Events carry the id of the tab.
+pub struct Network {
+    tab: usize,
+    api_caller: ApiCaller,
+    server: ServerConfig,
+    token: Option<Token>,
+    events: EventSender,
+    chat_msg_sender: Option<mpsc::Sender<Message>>,
+    tasks: Vec<JoinHandle<()>>,
 }
 
-impl<'a> Network<'a> {
-    pub fn new(
-        token: &'a str,
-        state: &'a Arc<Mutex<State>>,
-        chat_msg_sender: futures::channel::mpsc::Sender<Message>,
-    ) -> Network<'a> {
-        let api_caller = ApiCaller::new(&token);
+impl Network {
+    pub fn new(events: EventSender, tab: usize) -> Network {
+        let server = ServerConfig::default();
         Network {
-            state,
-            api_caller,
-            token,
-            chat_msg_sender,
+            tab,
+            api_caller: ApiCaller::new("", &server.api),
+            server,
+            token: None,
+            events,
+            chat_msg_sender: None,
+            tasks: Vec::new(),
         }
     }
 
-    pub async fn start_websocket(
-        &mut self,
-        io_sender: Sender<Action>,
-        chat_recv: Receiver<Message>,
+    /// Plays the replay if there is one and handles requests until Action::QuitApp.
+    /// Nothing connects before the first Action::Connect.
+    pub async fn run(
+        mut self,
+        replay: Option<(Vec<Frame>, f64)>,
+        mut requests: UnboundedReceiver<Action>,
     ) {
-        let socket_url = "wss://destiny.gg/ws";
-        let request = Request::builder()
-            .header("Host", "chat.destiny.gg")
-            .header("Origin", "https://www.destiny.gg")
+        if let Some((frames, speed)) = replay {
+            self.start_replay(frames, speed);
+        }
+
+        while let Some(action) = requests.recv().await {
+            match action {
+                Action::QuitApp => break,
+                action => self.handle_request(action).await,
+            }
+        }
+
+        self.close().await;
+    }
+
+    // Drops the current connection and starts the session, e.g. after switching profiles
+    async fn start(&mut self, session: Session) {
+        self.server = session.server;
+        self.token = None;
+        self.api_caller = ApiCaller::new("", &self.server.api);
+
+        match session.token {
+            _ if session.anonymous => self.connect().await,
+            Some(token) => self.login(token).await,
+            None => {
+                self.close().await;
+                self.send(Action::LoginRequired("No login key found.".to_string()))
+            }
+        }
+    }
+
+    // Checks the login key before connecting with it
+    async fn login(&mut self, token: Token) {
+        self.api_caller = ApiCaller::new(token.as_str(), &self.server.api);
+        match self.api_caller.get_me().await {
+            Ok(Some(me)) => {
+                self.send(Action::LoggedIn(token.clone(), me.nick.to_string()));
+                self.send(Action::Me(me));
+            }
+            Ok(None) => {
+                return self.send(Action::LoginRequired(
+                    "destiny.gg rejected the login key.".to_string(),
+                ))
+            }
+            // Connecting might still work, e.g. when only the API is down
+            Err(err) => self.send(Action::Error(format!(
+                "Could not check the login key: {}",
+                err
+            ))),
+        }
+
+        self.token = Some(token);
+        self.connect().await;
+    }
+
+    async fn connect(&mut self) {
+        // A new login replaces the old connection
+        self.close().await;
+        if let Err(err) = self.start_websocket().await {
+            self.send(Action::Error(format!("Could not connect: {}", err)));
+        }
+    }
+
+    async fn start_websocket(&mut self) -> Result<()> {
+        let socket_url = &self.server.chat;
+        let host = socket_url
+            .parse::<tungstenite::http::Uri>()?
+            .host()
+            .unwrap_or_default()
+            .to_string();
+        let mut request = Request::builder()
+            .header("Host", host)
+            .header("Origin", &self.server.api)
             .header("Connection", "Upgrade")
             .header("Upgrade", "websocket")
             .header("Sec-WebSocket-Version", "13")
@@ -46,151 +136,161 @@ impl<'a> Network<'a> {
                 "Sec-WebSocket-Key",
                 tungstenite::handshake::client::generate_key(),
             )
-            .header("cookie", format!("authtoken={}", self.token))
-            .uri(socket_url)
-            .body(())
-            .unwrap();
-
-        let (ws_stream, res) = connect_async(request)
-            .await
-            .expect("Failed to connect to WebSocket.");
-
-        // TODO: proper error handling
-        match res.status() {
-            StatusCode::OK => (),
-            _ => (),
+            .uri(socket_url.as_str());
+        // Without the cookie the chat is read-only
+        if let Some(token) = &self.token {
+            request = request.header("cookie", format!("authtoken={}", token.as_str()));
         }
+        let request = request.body(())?;
 
+        let (ws_stream, _res) = connect_async(request).await?;
         let (write, mut read) = ws_stream.split();
 
-        tokio::spawn(async move {
-            loop {
-                match read.next().await.unwrap() {
-                    Ok(msg) => match msg {
-                        Message::Text(text) => {
-                            io_sender.send(parse_msg(&text)).unwrap();
-                        }
-                        _ => (),
-                    },
-                    Err(_) => break,
+        let events = self.events.clone();
+        let tab = self.tab;
+        self.tasks.push(tokio::spawn(async move {
+            while let Some(Ok(msg)) = read.next().await {
+                if let Message::Text(text) = msg {
+                    if events.send(Event::Tab(tab, parse_msg(&text))).is_err() {
+                        return;
+                    }
                 }
             }
-        });
+        }));
+
+        let (chat_msg_sender, chat_msg_recv) = mpsc::channel(1);
+        self.chat_msg_sender = Some(chat_msg_sender);
+        self.tasks.push(tokio::spawn(async move {
+            // Ends once the sender is dropped on close
+            let _ = chat_msg_recv.map(Ok).forward(write).await;
+        }));
+
+        Ok(())
+    }
 
-        tokio::spawn(async move {
-            chat_recv.map(Ok).forward(write).await.unwrap();
-        });
+    /// Plays recorded frames instead of connecting to the websocket.
+    fn start_replay(&mut self, frames: Vec<Frame>, speed: f64) {
+        let events = self.events.clone();
+        self.tasks
+            .push(tokio::spawn(replay::play(frames, speed, events)));
     }
 
-    fn close(&mut self) {}
+    // Says goodbye to the websocket and stops every task
+    async fn close(&mut self) {
+        if let Some(mut chat_msg_sender) = self.chat_msg_sender.take() {
+            let _ = chat_msg_sender.send(Message::Close(None)).await;
+        }
+
+        // The writer ends after the close frame, the reader once the server closed the socket
+        for mut task in self.tasks.drain(..) {
+            if tokio::time::timeout(Duration::from_secs(1), &mut task)
+                .await
+                .is_err()
+            {
+                task.abort();
+            }
+        }
+    }
+
+    fn send(&self, action: Action) {
+        // The reducer is gone when the app quits, nothing left to tell
+        let _ = self.events.send(Event::Tab(self.tab, action));
+    }
+
+    async fn handle_request(&mut self, action: Action) {
+        match action {
+            Action::Stalk(name, num) => self.stalk(name, num).await,
+            Action::Connect(session) => self.start(session).await,
+            Action::Login(token) => self.login(token).await,
+            Action::GetChatHistory => self.get_chat_history().await,
+            Action::GetMe => self.get_me().await,
+            Action::GetFlairs(cache) => self.get_flairs(cache).await,
+            Action::GetEmbeds => self.get_last_embeds().await,
+            Action::SendMsg(msg) => {
+                let frame = format!("MSG {}", json!({ "data": msg }));
+                self.send_frame(frame).await
+            }
+            Action::Moderate(command) => self.send_frame(command.frame()).await,
+            action => self.send(Action::Unreachable(format!(
+                "{} is not a network request",
+                action
+            ))),
+        }
+    }
 
     async fn get_last_embeds(&mut self) {
         match self.api_caller.get_last_embeds().await {
-            Ok(embeds) => {
-                let mut state = self.state.lock().await;
-                embeds.iter().for_each(|msg| {
-                    state.add_message(ChatMessage::from_string(
-                        "EMBED".to_string(),
-                        msg.to_string(),
-                    ))
-                });
-            }
-            Err(err) => self.state.lock().await.add_error(err.to_string()),
+            Ok(embeds) => self.send(Action::SystemMessages(
+                "EMBED".to_string(),
+                embeds.iter().map(|msg| msg.to_string()).collect(),
+            )),
+            Err(err) => self.send(Action::Error(err.to_string())),
         }
     }
 
     async fn stalk(&mut self, name: String, num: u8) {
         match self.api_caller.stalk(name, num).await {
-            Ok(stalks) => {
-                let mut state = self.state.lock().await;
-                stalks.iter().for_each(|msg| {
-                    state.add_message(ChatMessage::from_string(
-                        "STALK".to_string(),
-                        msg.to_string(),
-                    ))
-                });
-            }
-            Err(err) => self.state.lock().await.add_error(err.to_string()),
+            Ok(stalks) => self.send(Action::SystemMessages(
+                "STALK".to_string(),
+                stalks.iter().map(|msg| msg.to_string()).collect(),
+            )),
+            Err(err) => self.send(Action::Error(err.to_string())),
         }
     }
 
     async fn get_chat_history(&self) {
         match self.api_caller.get_chat_history().await {
-            Ok(chat_history) => {
-                let mut state = self.state.lock().await;
-                chat_history[chat_history.len() - 50..]
-                    .into_iter()
-                    .for_each(|msg| state.dispatch(parse_msg(msg)));
-                state.loaded = true;
+            Ok(chat_history) => self.send(Action::ChatHistory(chat_history)),
+            Err(err) => self.send(Action::Error(err.to_string())),
+        }
+    }
+
+    // The cache is only written when the flairs could be read
+    async fn get_flairs(&self, cache: Option<PathBuf>) {
+        let flairs = match self.api_caller.get_flairs(&self.server.cdn).await {
+            Ok(json) => Flairs::from_json(&json).map(|flairs| (json, flairs)),
+            Err(err) => Err(err),
+        };
+        match flairs {
+            Ok((json, flairs)) => {
+                if let Some(file) = cache {
+                    if let Err(err) = Flairs::write_cache(&file, &json) {
+                        self.send(Action::Error(format!("Could not save the flairs: {}", err)));
+                    }
+                }
+                self.send(Action::FlairsLoaded(flairs))
             }
-            Err(err) => self.state.lock().await.add_error(err.to_string()),
+            Err(err) => self.send(Action::Error(format!("Could not load the flairs: {}", err))),
         }
     }
 
     async fn get_me(&self) {
         match self.api_caller.get_me().await {
-            Ok(me) => {
-                self.state.lock().await.username = me.username;
-            }
-            Err(err) => self.state.lock().await.add_error(err.to_string()),
+            Ok(Some(me)) => self.send(Action::Me(me)),
+            Ok(None) => self.send(Action::Error("Not logged in.".to_string())),
+            Err(err) => self.send(Action::Error(err.to_string())),
         }
     }
 
-    async fn send_chat_message(&mut self) {
-        let mut state = self.state.lock().await;
-        let msg = format!(r#"MSG {{"data":"{}"}}"#, state.chat_input.current_message);
-        state.chat_input.add();
-        drop(state);
-
-        let msg = Message::Text(msg);
-        self.chat_msg_sender.send(msg).await.unwrap();
-    }
+    async fn send_frame(&mut self, frame: String) {
+        let chat_msg_sender = match self.chat_msg_sender.as_mut() {
+            Some(chat_msg_sender) => chat_msg_sender,
+            None => return self.send(Action::Error("Not connected to the chat.".to_string())),
+        };
 
-    pub async fn handle_io(&mut self, action: Action) {
-        self.state.lock().await.add_debug(action.to_string());
-        match action {
-            Action::RecvMsg(mut chat_msg) => {
-                let mut state = self.state.lock().await;
-                chat_msg.parse(&state.username);
-                state.add_message(chat_msg)
-            }
-            Action::Stalk(name, num) => self.stalk(name, num).await,
-            Action::QuitApp => self.close(),
-            Action::GetChatHistory => self.get_chat_history().await,
-            Action::GetMe => self.get_me().await,
-            Action::GetEmbeds => self.get_last_embeds().await,
-            Action::SendMsg => self.send_chat_message().await,
-            Action::UserJoin(user) => self.state.lock().await.ul.add(user),
-            Action::UserQuit(user) => self.state.lock().await.ul.remove(user),
-            Action::UsersInit(mut user_list) => self.state.lock().await.ul.append(&mut user_list),
-            Action::Mute => (),
-            Action::Unmute => (),
-            Action::Ban => (),
-            Action::Unban => (),
-            Action::Subonly => (),
-            Action::Broadcast => (),
-            Action::PrivMsg => (),
-            Action::Ping => (),
-            Action::Pong => (),
-            Action::Refresh => (),
-            Action::Binary => (),
-            Action::Err(ws_err) => self.state.lock().await.add_error(ws_err.to_string()),
-            Action::Unreachable(un_msg) => self
-                .state
-                .lock()
-                .await
-                .add_error(format!("NETWORK: Unreachable = {}", un_msg)),
+        if chat_msg_sender.send(Message::Text(frame)).await.is_err() {
+            self.send(Action::Error(
+                "Lost the connection to the chat.".to_string(),
+            ));
         }
     }
 }
 
-pub fn parse_msg(msg: &String) -> Action {
-    let msg_splits: Vec<String> = msg
-        .to_string()
-        .splitn(2, " ")
-        .map(|s| s.to_string())
-        .collect();
-
-    let (prefix, json) = (&msg_splits[0], &msg_splits[1]);
-    Action::from_prefix_and_json(&prefix, &json)
+/// Frames that can't be read become Unreachable, so a broken frame doesn't stop the chat
+pub fn parse_msg(msg: &str) -> Action {
+    match msg.split_once(' ') {
+        Some((prefix, json)) => Action::from_prefix_and_json(prefix, json)
+            .unwrap_or_else(|err| Action::Unreachable(format!("{} ({})", msg, err))),
+        None => Action::Unreachable(msg.to_string()),
+    }
 }
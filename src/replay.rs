@@ -0,0 +1,215 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::{
+    app::{Event, EventSender},
+    chat::{
+        action::Action,
+        chat_log::{LogEntry, LogKind},
+    },
+    network::parse_msg,
+};
+
+/// A websocket frame, e.g. `MSG {"nick":"destiny","data":"hello"}`, and when it was received.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub timestamp: Option<u64>,
+    pub text: String,
+}
+
+impl Frame {
+    /// Reads a single line of a chat log or a raw websocket transcript.
+    /// Returns None for lines that weren't received from the chat, e.g. sent or system messages.
+    pub fn from_line(line: &str) -> Option<Frame> {
+        let line = line.trim();
+        if line.starts_with('{') || line.starts_with('[') {
+            Frame::from_log_entry(LogEntry::from_line(line)?)
+        } else {
+            Frame::from_raw(line)
+        }
+    }
+
+    fn from_log_entry(entry: LogEntry) -> Option<Frame> {
+        if entry.kind != LogKind::Received {
+            return None;
+        }
+
+        Some(Frame::msg(
+            entry.timestamp,
+            &entry.nick,
+            &entry.features,
+            &entry.data,
+        ))
+    }
+
+    // PREFIX {json}
+    fn from_raw(line: &str) -> Option<Frame> {
+        let (_, json) = line.split_once(' ')?;
+        let timestamp = serde_json::from_str::<Value>(json)
+            .ok()
+            .and_then(|v| v["timestamp"].as_u64());
+
+        Some(Frame {
+            timestamp,
+            text: line.to_string(),
+        })
+    }
+
+    // Whether the client can read the frame, e.g. `MSG {bad` can't be replayed
+    fn check(&self) -> Result<()> {
+        match self.text.split_once(' ') {
+            Some((prefix, json)) => Action::from_prefix_and_json(prefix, json).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn msg(timestamp: u64, nick: &str, features: &[String], data: &str) -> Frame {
+        let json = json!({
+            "nick": nick,
+            "features": features,
+            "timestamp": timestamp,
+            "data": data,
+        });
+
+        Frame {
+            timestamp: Some(timestamp),
+            text: format!("MSG {}", json),
+        }
+    }
+}
+
+/// Fails on the first line that can't be replayed, naming its number.
+pub fn read_frames(path: &Path) -> Result<Vec<Frame>> {
+    let content = fs::read_to_string(path)?;
+    let mut frames = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        if let Some(frame) = Frame::from_line(line) {
+            frame
+                .check()
+                .map_err(|err| anyhow!("{} line {}: {}", path.display(), number + 1, err))?;
+            frames.push(frame);
+        }
+    }
+
+    if frames.is_empty() {
+        bail!("{} contains nothing to replay.", path.display());
+    }
+    Ok(frames)
+}
+
+/// Sends the frames as Actions with the recorded delays between them divided by speed.
+/// A speed of 0 sends all frames at once.
+pub async fn play(frames: Vec<Frame>, speed: f64, events: EventSender) {
+    let start = Instant::now();
+    let first = frames.iter().find_map(|frame| frame.timestamp);
+
+    for frame in frames {
+        if let (Some(first), Some(timestamp), true) = (first, frame.timestamp, speed > 0.0) {
+            let offset = Duration::from_millis(timestamp.saturating_sub(first)).div_f64(speed);
+            tokio::time::sleep_until(start + offset).await;
+        }
+
+        if events.send(Event::Action(parse_msg(&frame.text))).is_err() {
+            return;
+        }
+    }
+
+    let _ = events.send(Event::Action(Action::Info("Replay finished.".to_string())));
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn frame_from_json_log() {
+        let line = r#"{"timestamp":1666818389374,"kind":"received","nick":"destiny","data":"hi"}"#;
+        let frame = Frame::from_line(line).unwrap();
+        assert_eq!(frame.timestamp, Some(1666818389374));
+        assert!(frame.text.starts_with("MSG {"));
+
+        let line = r#"{"timestamp":1666818389374,"kind":"sent","nick":"destiny","data":"hi"}"#;
+        assert_eq!(Frame::from_line(line), None);
+    }
+
+    #[test]
+    fn frame_from_text_log() {
+        let frame = Frame::from_line("[2022-10-26 21:06:29] destiny: hello: world").unwrap();
+        assert_eq!(frame.timestamp, Some(1666818389000));
+        match parse_msg(&frame.text) {
+            Action::RecvMsg(msg) => {
+                assert_eq!(&*msg.name, "destiny");
+                assert_eq!(msg.message, "hello: world");
+            }
+            action => panic!("expected a message, got {}", action),
+        }
+
+        assert_eq!(
+            Frame::from_line("[2022-10-26 21:06:29] * ERROR: oops"),
+            None
+        );
+    }
+
+    #[test]
+    fn frame_from_raw_transcript() {
+        let frame = Frame::from_line(r#"JOIN {"nick":"destiny","features":[],"timestamp":5}"#);
+        assert_eq!(frame.unwrap().timestamp, Some(5));
+        assert_eq!(Frame::from_line(""), None);
+    }
+
+    #[test]
+    fn broken_lines_name_their_number() {
+        let dir = temp_dir("replay");
+        let file = dir.join("broken.log");
+        fs::write(
+            &file,
+            "MSG {\"nick\":\"destiny\",\"features\":[],\"data\":\"hi\"}\nMSG {bad\n",
+        )
+        .unwrap();
+        let err = read_frames(&file).unwrap_err().to_string();
+        fs::remove_dir_all(dir).unwrap();
+        assert!(err.contains(" line 2: "), "{}", err);
+
+        assert!(matches!(parse_msg("MSG {bad"), Action::Unreachable(_)));
+        assert!(matches!(parse_msg("ERR {}"), Action::Unreachable(_)));
+        assert!(matches!(parse_msg("PRIVMSG {}"), Action::Unreachable(_)));
+    }
+
+    #[tokio::test]
+    async fn play_sends_frames_in_order() {
+        let frames = vec![
+            Frame::msg(10_000, "first", &[], "a"),
+            Frame::msg(20_000, "second", &[], "b"),
+        ];
+        let (sender, mut receiver) = unbounded_channel();
+        play(frames, 0.0, sender).await;
+
+        let mut names = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            names.push(match event {
+                Event::Action(Action::RecvMsg(msg)) => msg.name.to_string(),
+                Event::Action(Action::Info(info)) => info,
+                event => format!("{:?}", event),
+            });
+        }
+        assert_eq!(names, vec!["first", "second", "Replay finished."]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn play_keeps_the_recorded_delay() {
+        let frames = vec![
+            Frame::msg(10_000, "first", &[], "a"),
+            Frame::msg(20_000, "second", &[], "b"),
+        ];
+        let (sender, _receiver) = unbounded_channel();
+        let start = Instant::now();
+        play(frames, 2.0, sender).await;
+        assert_eq!(start.elapsed().as_secs(), 5);
+    }
+}
@@ -0,0 +1,11 @@
+//! Helpers shared by the tests of every module
+
+use std::{fs, path::PathBuf};
+
+/// An empty directory in the temp dir that only this test run uses, the test removes it
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dgg-tui-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,235 @@
+use std::{
+    fmt::{Debug, Display},
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+/// Overrides the stored login key
+pub const TOKEN_ENV: &str = "DGG_TOKEN";
+
+/// A login key from https://www.destiny.gg/profile/developer.
+/// Debug doesn't print it, so it never ends up in the debug window.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(String);
+
+impl Token {
+    /// Trims the key and checks that it looks like a login key.
+    pub fn parse(token: &str) -> Result<Token> {
+        let token = token.trim();
+        if token.len() != 64 {
+            bail!(
+                "A login key is 64 characters long, this one has {}.",
+                token.len()
+            );
+        }
+        Ok(Token(token.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Token(..)")
+    }
+}
+
+/// Where the login key came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource {
+    TokenFile,
+    Env,
+    SecretService,
+    File,
+}
+
+impl Display for TokenSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenSource::TokenFile => write!(f, "--token-file"),
+            TokenSource::Env => write!(f, "{}", TOKEN_ENV),
+            TokenSource::SecretService => write!(f, "the Secret Service"),
+            TokenSource::File => write!(f, "the token file"),
+        }
+    }
+}
+
+/// Keeps the login key in the Secret Service if the secret-service feature is enabled
+/// and a keyring is available, otherwise in a file only the user can read.
+pub struct TokenStore {
+    file: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(file: PathBuf) -> TokenStore {
+        TokenStore { file }
+    }
+
+    pub fn load(&self) -> Result<Option<(Token, TokenSource)>> {
+        #[cfg(feature = "secret-service")]
+        if let Some(token) = secret_service::load(&self.account()) {
+            return Ok(Some((Token::parse(&token)?, TokenSource::SecretService)));
+        }
+
+        match fs::read_to_string(&self.file) {
+            Ok(token) => Ok(Some((Token::parse(&token)?, TokenSource::File))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => bail!("Could not read {}: {}", self.file.display(), err),
+        }
+    }
+
+    /// Saves the key and returns where it went.
+    pub fn save(&self, token: &Token) -> Result<TokenSource> {
+        #[cfg(feature = "secret-service")]
+        if secret_service::save(&self.account(), token.as_str()) {
+            // don't leave an old key lying around
+            let _ = fs::remove_file(&self.file);
+            return Ok(TokenSource::SecretService);
+        }
+
+        write_private(&self.file, token.as_str())?;
+        Ok(TokenSource::File)
+    }
+
+    // Every profile has its own file, so the file name tells the keyring entries apart
+    #[cfg(feature = "secret-service")]
+    fn account(&self) -> String {
+        self.file.file_name().map_or("token".to_string(), |name| {
+            name.to_string_lossy().to_string()
+        })
+    }
+}
+
+#[cfg(feature = "secret-service")]
+mod secret_service {
+    const SERVICE: &str = "dgg-tui";
+
+    pub fn load(account: &str) -> Option<String> {
+        keyring::Entry::new(SERVICE, account)
+            .and_then(|entry| entry.get_password())
+            .ok()
+    }
+
+    // false if there is no keyring to save to
+    pub fn save(account: &str, token: &str) -> bool {
+        keyring::Entry::new(SERVICE, account)
+            .and_then(|entry| entry.set_password(token))
+            .is_ok()
+    }
+}
+
+// Writes a file that only the user can read and write
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // mode only applies to new files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+pub fn read_token_file(path: &Path) -> Result<Token> {
+    match fs::read_to_string(path) {
+        Ok(token) => Token::parse(&token),
+        Err(err) => bail!("Could not read {}: {}", path.display(), err),
+    }
+}
+
+/// Finds the login key, --token-file goes before DGG_TOKEN which goes before the stored key.
+pub fn resolve(
+    token_file: Option<&Path>,
+    env: Option<String>,
+    store: &TokenStore,
+) -> Result<Option<(Token, TokenSource)>> {
+    if let Some(path) = token_file {
+        return Ok(Some((read_token_file(path)?, TokenSource::TokenFile)));
+    }
+
+    if let Some(token) = env.filter(|token| !token.trim().is_empty()) {
+        return Ok(Some((Token::parse(&token)?, TokenSource::Env)));
+    }
+
+    store.load()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn token(c: char) -> String {
+        c.to_string().repeat(64)
+    }
+
+    #[test]
+    fn parse_token() {
+        assert!(Token::parse(&format!(" {}\n", token('a'))).is_ok());
+        assert!(Token::parse("too short").is_err());
+    }
+
+    #[test]
+    fn debug_hides_the_token() {
+        let parsed = Token::parse(&token('a')).unwrap();
+        assert_eq!(format!("{:?}", parsed), "Token(..)");
+    }
+
+    #[cfg(not(feature = "secret-service"))]
+    #[test]
+    fn store_in_private_file() {
+        let dir = temp_dir("token-store");
+        let file = dir.join("token");
+        let store = TokenStore::new(file.to_path_buf());
+        assert_eq!(store.load().unwrap(), None);
+
+        let saved = Token::parse(&token('a')).unwrap();
+        assert_eq!(store.save(&saved).unwrap(), TokenSource::File);
+        assert_eq!(store.load().unwrap(), Some((saved, TokenSource::File)));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overrides_go_first() {
+        let dir = temp_dir("token-overrides");
+        let file = dir.join("token");
+        let store = TokenStore::new(file.with_extension("missing"));
+        fs::write(&file, token('f')).unwrap();
+
+        let (found, source) = resolve(Some(&file), Some(token('e')), &store)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (found.as_str(), source),
+            (token('f').as_str(), TokenSource::TokenFile)
+        );
+
+        let (found, source) = resolve(None, Some(token('e')), &store).unwrap().unwrap();
+        assert_eq!(
+            (found.as_str(), source),
+            (token('e').as_str(), TokenSource::Env)
+        );
+
+        assert!(resolve(None, Some("bad".to_string()), &store).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -8,8 +8,8 @@ pub struct ChatInput {
     pub buffer_message: String,
 }
 
-impl ChatInput {
-    pub fn default() -> Self {
+impl Default for ChatInput {
+    fn default() -> Self {
         let max_messages = 200;
         let index = 0;
         let history = VecDeque::with_capacity(max_messages);
@@ -24,7 +24,9 @@ impl ChatInput {
             current_message,
         }
     }
+}
 
+impl ChatInput {
     /// Delete the last word of the current message and every whitespace before.
     pub fn delete_current_word(&mut self) {
         let index_back = self
@@ -67,7 +69,7 @@ impl ChatInput {
 
     pub fn add(&mut self) {
         // don't add the message to the history if its the same
-        if self.history.len() > 0 && self.history[0] == self.current_message {
+        if !self.history.is_empty() && self.history[0] == self.current_message {
             self.current_message.clear();
             return;
         }
@@ -80,7 +82,7 @@ impl ChatInput {
     }
 
     pub fn next(&mut self) {
-        if self.history.len() == 0 {
+        if self.history.is_empty() {
             return;
         }
 
@@ -98,7 +100,7 @@ impl ChatInput {
     }
 
     pub fn prev(&mut self) {
-        if self.history.len() == 0 {
+        if self.history.is_empty() {
             return;
         }
 
@@ -120,6 +122,7 @@ impl ChatInput {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,212 @@
+use std::env;
+
+use serde::{Deserialize, Serialize};
+use tui::style::Color;
+
+/// Reads a colour from the config, either a name like "lightred" or "#rrggbb".
+pub fn parse_color(s: &str) -> Option<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+
+    let color = match s.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// How many colours the terminal can show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorDepth {
+    /// Guessed from COLORTERM and TERM
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+impl ColorDepth {
+    pub const NAMES: &'static [&'static str] = &["auto", "truecolor", "256", "16"];
+
+    pub fn parse(name: &str) -> Option<ColorDepth> {
+        match name {
+            "auto" => Some(ColorDepth::Auto),
+            "truecolor" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Ansi256),
+            "16" => Some(ColorDepth::Ansi16),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorDepth::Auto => "auto",
+            ColorDepth::TrueColor => "truecolor",
+            ColorDepth::Ansi256 => "256",
+            ColorDepth::Ansi16 => "16",
+        }
+    }
+
+    /// Replaces Auto with what the terminal says it supports.
+    pub fn detect(self) -> ColorDepth {
+        if self != ColorDepth::Auto {
+            return self;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// The closest colour the terminal can show.
+    pub fn convert(&self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Auto | ColorDepth::TrueColor, color) => color,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(to_ansi256(r, g, b)),
+            (ColorDepth::Ansi256, color) => color,
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => to_ansi16(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(index)) => {
+                let (r, g, b) = indexed_rgb(index);
+                to_ansi16(r, g, b)
+            }
+            (ColorDepth::Ansi16, color) => color,
+        }
+    }
+}
+
+// The 16 colours like xterm shows them
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map_or(Color::White, |(color, _)| *color)
+}
+
+// Picks the 6x6x6 cube or the grey ramp, whichever is closer
+fn to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    };
+    let (cr, cg, cb) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * cr + 6 * cg + cb;
+
+    let average = (r as u16 + g as u16 + b as u16) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    match distance(indexed_rgb(grey)) < distance(indexed_rgb(cube)) {
+        true => grey,
+        false => cube,
+    }
+}
+
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_by_name_and_hex() {
+        assert_eq!(parse_color("LightRed"), Some(Color::LightRed));
+        assert_eq!(parse_color("dark-gray"), Some(Color::DarkGray));
+        assert_eq!(parse_color("#0a283c"), Some(Color::Rgb(10, 40, 60)));
+        assert_eq!(parse_color("#0a283"), None);
+        assert_eq!(parse_color("#zz283c"), None);
+        assert_eq!(parse_color("pink"), None);
+    }
+
+    #[test]
+    fn fewer_colors() {
+        let depth = ColorDepth::Ansi256;
+        assert_eq!(depth.convert(Color::Rgb(255, 0, 0)), Color::Indexed(196));
+        assert_eq!(depth.convert(Color::Rgb(50, 50, 50)), Color::Indexed(236));
+        assert_eq!(depth.convert(Color::LightRed), Color::LightRed);
+
+        let depth = ColorDepth::Ansi16;
+        assert_eq!(depth.convert(Color::Rgb(250, 0, 140)), Color::Magenta);
+        assert_eq!(depth.convert(Color::Rgb(10, 40, 60)), Color::Black);
+        assert_eq!(depth.convert(Color::Indexed(196)), Color::LightRed);
+
+        assert_eq!(
+            ColorDepth::TrueColor.convert(Color::Rgb(1, 2, 3)),
+            Color::Rgb(1, 2, 3)
+        );
+    }
+}
@@ -1,3 +1,10 @@
+// Modifiers that can be appended to any emote, e.g. PEPE:wide
+pub const MODIFIERS: [&str; 16] = [
+    "banned", "dank", "flip", "hyper", "jam", "lag", "love", "mirror", "pause", "rain", "rustle",
+    "smol", "snow", "spin", "virus", "wide",
+];
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct Emote {
     pub name: &'static str,
     pub emote: &'static str,
@@ -7,197 +14,208 @@ pub struct EmoteList {
     pub emotes: Vec<Emote>,
 }
 
+impl Default for EmoteList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EmoteList {
     pub fn new() -> EmoteList {
-        let mut emotes = Vec::new();
-        emotes.push(Emote {
-            name: "COOMER",
-            emote: "🥵",
-        });
-        emotes.push(Emote {
-            name: "Sweatstiny",
-            emote: "😥",
-        });
-        emotes.push(Emote {
-            name: "OMEGALUL",
-            emote: "😂",
-        });
-        emotes.push(Emote {
-            name: "OOOO",
-            emote: "🐠",
-        });
-        emotes.push(Emote {
-            name: "GRUG",
-            emote: "🦍",
-        });
-        emotes.push(Emote {
-            name: "melW",
-            emote: "🙍",
-        });
-        emotes.push(Emote {
-            name: "MMMM",
-            emote: "🐟",
-        });
-        emotes.push(Emote {
-            name: "GIGACHAD",
-            emote: "😎",
-        });
-        emotes.push(Emote {
-            name: "PEPE",
-            emote: "🐸",
-        });
-        emotes.push(Emote {
-            name: "YEE",
-            emote: "💩",
-        });
-        emotes.push(Emote {
-            name: "PepeMods",
-            emote: "😒",
-        });
-        emotes.push(Emote {
-            name: "Askers",
-            emote: "❓",
-        });
-        emotes.push(Emote {
-            name: "Painstiny",
-            emote: "🤕",
-        });
-        emotes.push(Emote {
-            name: "DANKMEMES",
-            emote: "🗿",
-        });
-        emotes.push(Emote {
-            name: "FeelsOkayMan",
-            emote: "😏",
-        });
-        emotes.push(Emote {
-            name: "SOY",
-            emote: "🤓",
-        });
-        emotes.push(Emote {
-            name: "nathanW",
-            emote: "🧔",
-        });
-        emotes.push(Emote {
-            name: "FeelsWeirdMan",
-            emote: "🤨",
-        });
-        emotes.push(Emote {
-            name: "Clap",
-            emote: "👏",
-        });
-        emotes.push(Emote {
-            name: "ECH",
-            emote: "🤢",
-        });
-        emotes.push(Emote {
-            name: "WhoahDude",
-            emote: "😯",
-        });
-        emotes.push(Emote {
-            name: "Sleepstiny",
-            emote: "😴",
-        });
-        emotes.push(Emote {
-            name: "ApeHands",
-            emote: "👉",
-        });
-        emotes.push(Emote {
-            name: "Derpstiny",
-            emote: "😵",
-        });
-        emotes.push(Emote {
-            name: "SpookerZ",
-            emote: "👻",
-        });
-        emotes.push(Emote {
-            name: "Pog",
-            emote: "🤩",
-        });
-        emotes.push(Emote {
-            name: "monkaSMEGA",
-            emote: "😱",
-        });
-        emotes.push(Emote {
-            name: "Shrugstiny",
-            emote: "💁",
-        });
-        emotes.push(Emote {
-            name: "monkaS",
-            emote: "😰",
-        });
-        emotes.push(Emote {
-            name: "LOVE",
-            emote: "💏",
-        });
-        emotes.push(Emote {
-            name: "tf",
-            emote: "🤪",
-        });
-        emotes.push(Emote {
-            name: "LULW",
-            emote: "😁",
-        });
-        emotes.push(Emote {
-            name: "LUL",
-            emote: "😄",
-        });
-        emotes.push(Emote {
-            name: "PepeLaugh",
-            emote: "🤣",
-        });
-        emotes.push(Emote {
-            name: "SNAP",
-            emote: "📷",
-        });
-        emotes.push(Emote {
-            name: "REE",
-            emote: "😡",
-        });
-        emotes.push(Emote {
-            name: "HmmStiny",
-            emote: "🤔",
-        });
-        emotes.push(Emote {
-            name: "DaFeels",
-            emote: "😞",
-        });
-        emotes.push(Emote {
-            name: "Depresstiny",
-            emote: "🥺",
-        });
-        emotes.push(Emote {
-            name: "OhKrappa",
-            emote: "🙊",
-        });
-        emotes.push(Emote {
-            name: "AngelThump",
-            emote: "🌞",
-        });
-        emotes.push(Emote {
-            name: "MLADY",
-            emote: "️🥷",
-        });
-        emotes.push(Emote {
-            name: "pepeW",
-            emote: "😫",
-        });
-        emotes.push(Emote {
-            name: "DuckerZ",
-            emote: "🦢",
-        });
-        emotes.push(Emote {
-            name: "Copium",
-            emote: "😷",
-        });
-        emotes.push(Emote {
-            name: "SOTRIGGERED",
-            emote: "🔫",
-        });
-        emotes.push(Emote {
-            name: "catJAM",
-            emote: "🐈",
-        });
+        let emotes = vec![
+            Emote {
+                name: "COOMER",
+                emote: "🥵",
+            },
+            Emote {
+                name: "Sweatstiny",
+                emote: "😥",
+            },
+            Emote {
+                name: "OMEGALUL",
+                emote: "😂",
+            },
+            Emote {
+                name: "OOOO",
+                emote: "🐠",
+            },
+            Emote {
+                name: "GRUG",
+                emote: "🦍",
+            },
+            Emote {
+                name: "melW",
+                emote: "🙍",
+            },
+            Emote {
+                name: "MMMM",
+                emote: "🐟",
+            },
+            Emote {
+                name: "GIGACHAD",
+                emote: "😎",
+            },
+            Emote {
+                name: "PEPE",
+                emote: "🐸",
+            },
+            Emote {
+                name: "YEE",
+                emote: "💩",
+            },
+            Emote {
+                name: "PepeMods",
+                emote: "😒",
+            },
+            Emote {
+                name: "Askers",
+                emote: "❓",
+            },
+            Emote {
+                name: "Painstiny",
+                emote: "🤕",
+            },
+            Emote {
+                name: "DANKMEMES",
+                emote: "🗿",
+            },
+            Emote {
+                name: "FeelsOkayMan",
+                emote: "😏",
+            },
+            Emote {
+                name: "SOY",
+                emote: "🤓",
+            },
+            Emote {
+                name: "nathanW",
+                emote: "🧔",
+            },
+            Emote {
+                name: "FeelsWeirdMan",
+                emote: "🤨",
+            },
+            Emote {
+                name: "Clap",
+                emote: "👏",
+            },
+            Emote {
+                name: "ECH",
+                emote: "🤢",
+            },
+            Emote {
+                name: "WhoahDude",
+                emote: "😯",
+            },
+            Emote {
+                name: "Sleepstiny",
+                emote: "😴",
+            },
+            Emote {
+                name: "ApeHands",
+                emote: "👉",
+            },
+            Emote {
+                name: "Derpstiny",
+                emote: "😵",
+            },
+            Emote {
+                name: "SpookerZ",
+                emote: "👻",
+            },
+            Emote {
+                name: "Pog",
+                emote: "🤩",
+            },
+            Emote {
+                name: "monkaSMEGA",
+                emote: "😱",
+            },
+            Emote {
+                name: "Shrugstiny",
+                emote: "💁",
+            },
+            Emote {
+                name: "monkaS",
+                emote: "😰",
+            },
+            Emote {
+                name: "LOVE",
+                emote: "💏",
+            },
+            Emote {
+                name: "tf",
+                emote: "🤪",
+            },
+            Emote {
+                name: "LULW",
+                emote: "😁",
+            },
+            Emote {
+                name: "LUL",
+                emote: "😄",
+            },
+            Emote {
+                name: "PepeLaugh",
+                emote: "🤣",
+            },
+            Emote {
+                name: "SNAP",
+                emote: "📷",
+            },
+            Emote {
+                name: "REE",
+                emote: "😡",
+            },
+            Emote {
+                name: "HmmStiny",
+                emote: "🤔",
+            },
+            Emote {
+                name: "DaFeels",
+                emote: "😞",
+            },
+            Emote {
+                name: "Depresstiny",
+                emote: "🥺",
+            },
+            Emote {
+                name: "OhKrappa",
+                emote: "🙊",
+            },
+            Emote {
+                name: "AngelThump",
+                emote: "🌞",
+            },
+            Emote {
+                name: "MLADY",
+                emote: "️🥷",
+            },
+            Emote {
+                name: "pepeW",
+                emote: "😫",
+            },
+            Emote {
+                name: "DuckerZ",
+                emote: "🦢",
+            },
+            Emote {
+                name: "Copium",
+                emote: "😷",
+            },
+            Emote {
+                name: "SOTRIGGERED",
+                emote: "🔫",
+            },
+            Emote {
+                name: "catJAM",
+                emote: "🐈",
+            },
+        ];
         EmoteList { emotes }
     }
+
+    pub fn get(&self, name: &str) -> Option<&Emote> {
+        self.emotes.iter().find(|emote| emote.name == name)
+    }
 }
@@ -0,0 +1,580 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::config::KeyConfig;
+
+/// Everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    Quit,
+    /// Sends the input or runs the command in it
+    Send,
+    Complete,
+    CompletePrev,
+    DeleteWord,
+    ClearInput,
+    HistoryPrev,
+    HistoryNext,
+    Search,
+    ScrollUp,
+    ScrollDown,
+    ScrollTop,
+    /// Follows the chat again
+    ScrollBottom,
+    ToggleDebug,
+    ToggleUserList,
+    Settings,
+    ShowBindings,
+    Reveal,
+    NextTab,
+    PrevTab,
+    /// The pane that gets resized
+    FocusNext,
+    GrowPane,
+    ShrinkPane,
+    NormalMode,
+    InsertMode,
+}
+
+impl KeyAction {
+    /// The line shown in the bindings overlay
+    pub fn describe(&self) -> &'static str {
+        match self {
+            KeyAction::Quit => "Quit",
+            KeyAction::Send => "Send the message or command",
+            KeyAction::Complete => "Next suggestion",
+            KeyAction::CompletePrev => "Previous suggestion",
+            KeyAction::DeleteWord => "Delete the last word",
+            KeyAction::ClearInput => "Clear the input",
+            KeyAction::HistoryPrev => "Older sent message",
+            KeyAction::HistoryNext => "Newer sent message",
+            KeyAction::Search => "Search the chat",
+            KeyAction::ScrollUp => "Scroll up",
+            KeyAction::ScrollDown => "Scroll down",
+            KeyAction::ScrollTop => "Scroll to the oldest message",
+            KeyAction::ScrollBottom => "Follow the chat",
+            KeyAction::ToggleDebug => "Show or hide the debug window",
+            KeyAction::ToggleUserList => "Show or hide the user list",
+            KeyAction::Settings => "Open the settings",
+            KeyAction::ShowBindings => "Show the key bindings",
+            KeyAction::Reveal => "Reveal or mask spoilers and tagged links",
+            KeyAction::NextTab => "Next tab",
+            KeyAction::PrevTab => "Previous tab",
+            KeyAction::FocusNext => "Focus the next pane",
+            KeyAction::GrowPane => "Grow the focused pane",
+            KeyAction::ShrinkPane => "Shrink the focused pane",
+            KeyAction::NormalMode => "Normal mode",
+            KeyAction::InsertMode => "Insert mode",
+        }
+    }
+}
+
+/// The built-in bindings the config adds to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl Preset {
+    pub const NAMES: &'static [&'static str] = &["default", "vim", "emacs"];
+
+    pub fn parse(name: &str) -> Result<Preset> {
+        match name {
+            "default" => Ok(Preset::Default),
+            "vim" => Ok(Preset::Vim),
+            "emacs" => Ok(Preset::Emacs),
+            _ => bail!("{} is not one of {}", name, Preset::NAMES.join(", ")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Vim => "vim",
+            Preset::Emacs => "emacs",
+        }
+    }
+}
+
+/// Only the vim preset leaves the insert mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Insert,
+    Normal,
+}
+
+/// One key press with its modifiers, written like "ctrl-f", "esc" or "G"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn from_event(key: KeyEvent) -> KeyChord {
+        let mut modifiers = key.modifiers;
+        // The shift is already in the character, "G" or "?"
+        if matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyChord {
+            code: key.code,
+            modifiers,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<KeyChord> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // "ctrl--" is control and minus, so a lone "-" always is the key
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => bail!("{} is not a modifier in {}", modifier, s),
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "esc" | "escape" => KeyCode::Esc,
+                "enter" | "return" => KeyCode::Enter,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                key => match key.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                    _ => bail!("{} is not a key", rest),
+                },
+            },
+        };
+
+        let event = KeyEvent::new(code, modifiers);
+        Ok(KeyChord::from_event(event))
+    }
+
+    // Keys that type text unless the input is empty
+    fn is_text(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && self.modifiers.is_empty()
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl-"),
+            (KeyModifiers::ALT, "alt-"),
+            (KeyModifiers::SHIFT, "shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::BackTab => write!(f, "shift-tab"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// Keys pressed one after another, written like "g g"
+pub fn parse_sequence(s: &str) -> Result<Vec<KeyChord>> {
+    let sequence = s
+        .split_whitespace()
+        .map(KeyChord::parse)
+        .collect::<Result<Vec<_>>>()?;
+    if sequence.is_empty() {
+        bail!("there are no keys");
+    }
+    Ok(sequence)
+}
+
+fn format_sequence(sequence: &[KeyChord]) -> String {
+    sequence
+        .iter()
+        .map(|chord| chord.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What a key press turned into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    Action(KeyAction),
+    /// The key starts a longer sequence
+    Pending,
+    /// Not bound, typed into the input in insert mode
+    Unbound,
+}
+
+type Bindings = Vec<(Vec<KeyChord>, KeyAction)>;
+
+/// Maps keys to actions. The bindings of the preset come first, the config replaces
+/// or adds to them.
+pub struct Keymap {
+    pub preset: Preset,
+    insert: Bindings,
+    normal: Bindings,
+    pub mode: Mode,
+    pending: Vec<KeyChord>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new(&KeyConfig::default()).expect("The presets are valid")
+    }
+}
+
+impl Keymap {
+    pub fn new(config: &KeyConfig) -> Result<Keymap> {
+        let (mut insert, mut normal) = preset_bindings(config.preset);
+        for (mode, bindings, custom) in [
+            ("insert", &mut insert, &config.insert),
+            ("normal", &mut normal, &config.normal),
+        ] {
+            for (keys, action) in custom {
+                let sequence = parse_sequence(keys)
+                    .map_err(|err| anyhow!("keys.{}.{}: {}", mode, keys, err))?;
+                bind(bindings, sequence, *action);
+            }
+        }
+
+        Ok(Keymap {
+            preset: config.preset,
+            insert,
+            normal,
+            mode: Mode::Insert,
+            pending: Vec::new(),
+        })
+    }
+
+    /// The keys of an unfinished sequence
+    pub fn pending(&self) -> String {
+        format_sequence(&self.pending)
+    }
+
+    /// Looks up the key, `typing` is true if the input has text.
+    /// Plain characters are typed then, so "?" still is a question mark.
+    pub fn feed(&mut self, key: KeyEvent, typing: bool) -> Feed {
+        let chord = KeyChord::from_event(key);
+        if self.mode == Mode::Insert && typing && self.pending.is_empty() && chord.is_text() {
+            return Feed::Unbound;
+        }
+
+        self.pending.push(chord);
+        let bindings = match self.mode {
+            Mode::Insert => &self.insert,
+            Mode::Normal => &self.normal,
+        };
+        let mut exact = None;
+        let mut longer = false;
+        for (sequence, action) in bindings {
+            if *sequence == self.pending {
+                exact = Some(*action);
+            } else if sequence.starts_with(&self.pending) {
+                longer = true;
+            }
+        }
+
+        match (exact, longer) {
+            // Without a timeout the shorter sequence wins
+            (Some(action), _) => {
+                self.pending.clear();
+                Feed::Action(action)
+            }
+            (None, true) => Feed::Pending,
+            (None, false) => {
+                let restart = self.pending.len() > 1;
+                self.pending.clear();
+                // The key may start a sequence on its own
+                match restart {
+                    true => self.feed(key, typing),
+                    false => Feed::Unbound,
+                }
+            }
+        }
+    }
+
+    /// Every binding of the current preset and the config, for the overlay
+    pub fn bindings(&self) -> Vec<(Mode, String, KeyAction)> {
+        let insert = self.insert.iter().map(|binding| (Mode::Insert, binding));
+        let normal = self.normal.iter().map(|binding| (Mode::Normal, binding));
+        insert
+            .chain(normal)
+            .map(|(mode, (sequence, action))| (mode, format_sequence(sequence), *action))
+            .collect()
+    }
+}
+
+// Replaces the action of a sequence that is already bound
+fn bind(bindings: &mut Bindings, sequence: Vec<KeyChord>, action: KeyAction) {
+    match bindings.iter_mut().find(|(bound, _)| *bound == sequence) {
+        Some(binding) => binding.1 = action,
+        None => bindings.push((sequence, action)),
+    }
+}
+
+const DEFAULT_BINDINGS: &[(&str, KeyAction)] = &[
+    ("esc", KeyAction::Quit),
+    ("enter", KeyAction::Send),
+    ("tab", KeyAction::Complete),
+    ("shift-tab", KeyAction::CompletePrev),
+    ("ctrl-backspace", KeyAction::DeleteWord),
+    // Gnome Terminal uses Control+h as Backspace
+    ("ctrl-h", KeyAction::DeleteWord),
+    ("ctrl-f", KeyAction::Search),
+    ("up", KeyAction::HistoryPrev),
+    ("down", KeyAction::HistoryNext),
+    ("pageup", KeyAction::ScrollUp),
+    ("pagedown", KeyAction::ScrollDown),
+    ("f1", KeyAction::ToggleDebug),
+    ("f2", KeyAction::ToggleUserList),
+    ("f3", KeyAction::ScrollBottom),
+    ("f4", KeyAction::Reveal),
+    ("alt-right", KeyAction::NextTab),
+    ("alt-left", KeyAction::PrevTab),
+    ("alt-o", KeyAction::FocusNext),
+    ("alt-=", KeyAction::GrowPane),
+    ("alt--", KeyAction::ShrinkPane),
+    ("?", KeyAction::ShowBindings),
+];
+
+const EMACS_BINDINGS: &[(&str, KeyAction)] = &[
+    ("ctrl-w", KeyAction::DeleteWord),
+    ("ctrl-u", KeyAction::ClearInput),
+    ("ctrl-p", KeyAction::HistoryPrev),
+    ("ctrl-n", KeyAction::HistoryNext),
+    ("ctrl-s", KeyAction::Search),
+    ("ctrl-r", KeyAction::Search),
+    ("alt-v", KeyAction::ScrollUp),
+    ("ctrl-v", KeyAction::ScrollDown),
+    ("alt-<", KeyAction::ScrollTop),
+    ("alt->", KeyAction::ScrollBottom),
+    ("ctrl-x ctrl-c", KeyAction::Quit),
+    ("ctrl-x s", KeyAction::Settings),
+    ("ctrl-x right", KeyAction::NextTab),
+    ("ctrl-x left", KeyAction::PrevTab),
+    ("ctrl-x o", KeyAction::FocusNext),
+    ("ctrl-x }", KeyAction::GrowPane),
+    ("ctrl-x {", KeyAction::ShrinkPane),
+];
+
+const VIM_INSERT_BINDINGS: &[(&str, KeyAction)] = &[
+    ("esc", KeyAction::NormalMode),
+    ("ctrl-w", KeyAction::DeleteWord),
+    ("ctrl-u", KeyAction::ClearInput),
+];
+
+const VIM_NORMAL_BINDINGS: &[(&str, KeyAction)] = &[
+    ("i", KeyAction::InsertMode),
+    ("a", KeyAction::InsertMode),
+    ("enter", KeyAction::InsertMode),
+    ("k", KeyAction::ScrollUp),
+    ("j", KeyAction::ScrollDown),
+    ("ctrl-u", KeyAction::ScrollUp),
+    ("ctrl-d", KeyAction::ScrollDown),
+    ("pageup", KeyAction::ScrollUp),
+    ("pagedown", KeyAction::ScrollDown),
+    ("g g", KeyAction::ScrollTop),
+    ("G", KeyAction::ScrollBottom),
+    ("/", KeyAction::Search),
+    ("f1", KeyAction::ToggleDebug),
+    ("f2", KeyAction::ToggleUserList),
+    ("f3", KeyAction::ScrollBottom),
+    ("f4", KeyAction::Reveal),
+    ("z o", KeyAction::Reveal),
+    ("g t", KeyAction::NextTab),
+    ("g T", KeyAction::PrevTab),
+    ("ctrl-w w", KeyAction::FocusNext),
+    ("ctrl-w +", KeyAction::GrowPane),
+    ("ctrl-w -", KeyAction::ShrinkPane),
+    ("?", KeyAction::ShowBindings),
+    (": q enter", KeyAction::Quit),
+    ("Z Z", KeyAction::Quit),
+    ("Z Q", KeyAction::Quit),
+];
+
+fn preset_bindings(preset: Preset) -> (Bindings, Bindings) {
+    let mut insert = Vec::new();
+    let mut normal = Vec::new();
+    let add = |bindings: &mut Bindings, keys: &[(&str, KeyAction)]| {
+        for (keys, action) in keys {
+            let sequence = parse_sequence(keys).expect("The presets are valid");
+            bind(bindings, sequence, *action);
+        }
+    };
+    add(&mut insert, DEFAULT_BINDINGS);
+    match preset {
+        Preset::Default => (),
+        Preset::Emacs => add(&mut insert, EMACS_BINDINGS),
+        Preset::Vim => {
+            add(&mut insert, VIM_INSERT_BINDINGS);
+            add(&mut normal, VIM_NORMAL_BINDINGS);
+        }
+    }
+    (insert, normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn press(keymap: &mut Keymap, keys: &str) -> Feed {
+        let mut feed = Feed::Unbound;
+        for chord in parse_sequence(keys).unwrap() {
+            let event = KeyEvent::new(chord.code, chord.modifiers);
+            feed = keymap.feed(event, false);
+        }
+        feed
+    }
+
+    #[test]
+    fn chords_read_back() {
+        for keys in [
+            "ctrl-f",
+            "esc",
+            "f1",
+            "G",
+            "?",
+            "alt-<",
+            "shift-tab",
+            "ctrl--",
+        ] {
+            assert_eq!(KeyChord::parse(keys).unwrap().to_string(), keys);
+        }
+        assert_eq!(
+            KeyChord::parse("Ctrl-PageUp").unwrap().to_string(),
+            "ctrl-pageup"
+        );
+        assert!(KeyChord::parse("hyper-x").is_err());
+        assert!(KeyChord::parse("f13").is_err());
+        assert!(parse_sequence(" ").is_err());
+    }
+
+    #[test]
+    fn shift_is_in_the_character() {
+        let event = KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(KeyChord::from_event(event), KeyChord::parse("G").unwrap());
+    }
+
+    #[test]
+    fn default_preset() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            press(&mut keymap, "ctrl-f"),
+            Feed::Action(KeyAction::Search)
+        );
+        assert_eq!(press(&mut keymap, "esc"), Feed::Action(KeyAction::Quit));
+        assert_eq!(
+            press(&mut keymap, "?"),
+            Feed::Action(KeyAction::ShowBindings)
+        );
+        assert_eq!(press(&mut keymap, "x"), Feed::Unbound);
+
+        // a question mark in a message is just text
+        let question = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
+        assert_eq!(keymap.feed(question, true), Feed::Unbound);
+    }
+
+    #[test]
+    fn sequences() {
+        let config = KeyConfig {
+            preset: Preset::Emacs,
+            ..KeyConfig::default()
+        };
+        let mut keymap = Keymap::new(&config).unwrap();
+        assert_eq!(press(&mut keymap, "ctrl-x"), Feed::Pending);
+        assert_eq!(keymap.pending(), "ctrl-x");
+        assert_eq!(press(&mut keymap, "ctrl-c"), Feed::Action(KeyAction::Quit));
+        assert_eq!(keymap.pending(), "");
+
+        // a key that doesn't continue the sequence counts on its own
+        assert_eq!(
+            press(&mut keymap, "ctrl-x ctrl-f"),
+            Feed::Action(KeyAction::Search)
+        );
+    }
+
+    #[test]
+    fn vim_modes() {
+        let config = KeyConfig {
+            preset: Preset::Vim,
+            ..KeyConfig::default()
+        };
+        let mut keymap = Keymap::new(&config).unwrap();
+        assert_eq!(
+            press(&mut keymap, "esc"),
+            Feed::Action(KeyAction::NormalMode)
+        );
+
+        keymap.mode = Mode::Normal;
+        assert_eq!(press(&mut keymap, "j"), Feed::Action(KeyAction::ScrollDown));
+        assert_eq!(
+            press(&mut keymap, "g g"),
+            Feed::Action(KeyAction::ScrollTop)
+        );
+        assert_eq!(
+            press(&mut keymap, "G"),
+            Feed::Action(KeyAction::ScrollBottom)
+        );
+        assert_eq!(
+            press(&mut keymap, ": q enter"),
+            Feed::Action(KeyAction::Quit)
+        );
+        assert_eq!(press(&mut keymap, "x"), Feed::Unbound);
+    }
+
+    #[test]
+    fn config_overrides_the_preset() {
+        let config = KeyConfig {
+            preset: Preset::Default,
+            insert: BTreeMap::from([
+                ("esc".to_string(), KeyAction::ClearInput),
+                ("ctrl-q".to_string(), KeyAction::Quit),
+            ]),
+            normal: BTreeMap::new(),
+        };
+        let mut keymap = Keymap::new(&config).unwrap();
+        assert_eq!(
+            press(&mut keymap, "esc"),
+            Feed::Action(KeyAction::ClearInput)
+        );
+        assert_eq!(press(&mut keymap, "ctrl-q"), Feed::Action(KeyAction::Quit));
+
+        let config = KeyConfig {
+            insert: BTreeMap::from([("ctrl-nope".to_string(), KeyAction::Quit)]),
+            ..KeyConfig::default()
+        };
+        let err = Keymap::new(&config).err().unwrap();
+        assert!(err.to_string().starts_with("keys.insert.ctrl-nope: "));
+    }
+}
@@ -0,0 +1,423 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+use super::window::WindowType;
+
+// Resizing never makes a pane smaller than this share of its split
+const MIN_SIZE: i32 = 5;
+// Rows or columns a pane keeps for its borders and one line, if its split has room for them
+const MIN_CELLS: u16 = 3;
+
+/// How a split places its children
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Side by side
+    Horizontal,
+    /// On top of each other
+    Vertical,
+}
+
+/// A node of the layout tree of the config, either a pane or a split of more nodes, e.g.
+/// `{ "split": "horizontal", "children": [{ "pane": "chat", "size": 80 }, { "pane": "user_list", "size": 20 }] }`.
+/// Sizes are shares of the parent split, hidden panes leave theirs to the visible ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutNode {
+    pub size: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pane: Option<WindowType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split: Option<Split>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<LayoutNode>,
+    /// Toggled from the keyboard or with /pane
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Default for LayoutNode {
+    fn default() -> Self {
+        LayoutNode {
+            size: 50,
+            pane: None,
+            split: None,
+            children: Vec::new(),
+            hidden: false,
+        }
+    }
+}
+
+/// Where a visible pane is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneArea {
+    pub window_type: WindowType,
+    pub area: Rect,
+    /// Resized from the keyboard, only set when more than one pane is visible
+    pub focused: bool,
+}
+
+impl LayoutNode {
+    pub fn pane(window_type: WindowType, size: u16) -> LayoutNode {
+        LayoutNode {
+            size,
+            pane: Some(window_type),
+            ..LayoutNode::default()
+        }
+    }
+
+    pub fn split(split: Split, size: u16, children: Vec<LayoutNode>) -> LayoutNode {
+        LayoutNode {
+            size,
+            split: Some(split),
+            children,
+            ..LayoutNode::default()
+        }
+    }
+
+    /// The debug window above the chat, the user list and the mentions next to it, all hidden
+    pub fn standard() -> LayoutNode {
+        let hidden = |node: LayoutNode| LayoutNode {
+            hidden: true,
+            ..node
+        };
+        LayoutNode::split(
+            Split::Vertical,
+            100,
+            vec![
+                hidden(LayoutNode::pane(WindowType::Debug, 50)),
+                LayoutNode::split(
+                    Split::Horizontal,
+                    50,
+                    vec![
+                        LayoutNode::pane(WindowType::Chat, 80),
+                        hidden(LayoutNode::pane(WindowType::UserList, 20)),
+                        hidden(LayoutNode::pane(WindowType::Mentions, 30)),
+                    ],
+                ),
+            ],
+        )
+    }
+
+    /// Every node is a pane or a split with children, errors name the key, e.g. "layout.children.1.size".
+    /// There is exactly one chat and it can't be hidden.
+    pub fn validate(&self) -> Result<()> {
+        self.check("layout")?;
+
+        let mut panes = self.panes(false);
+        if !panes.contains(&WindowType::Chat) {
+            bail!("layout: needs a chat pane");
+        }
+        panes.sort_by_key(|pane| pane.name());
+        if let Some(pair) = panes.windows(2).find(|pair| pair[0] == pair[1]) {
+            bail!("layout: has more than one {} pane", pair[0].name());
+        }
+        Ok(())
+    }
+
+    fn check(&self, key: &str) -> Result<()> {
+        if self.size == 0 {
+            bail!("{}.size: must be more than 0", key);
+        }
+        match (self.pane, self.split) {
+            (Some(WindowType::ChatInput), _) => {
+                bail!("{}.pane: chat_input is part of the chat pane", key)
+            }
+            (Some(WindowType::Chat), _) if self.hidden => {
+                bail!("{}.hidden: the chat can't be hidden", key)
+            }
+            (Some(_), None) if self.children.is_empty() => Ok(()),
+            (Some(_), _) => bail!("{}: a pane can't be split", key),
+            (None, Some(_)) if self.children.is_empty() => {
+                bail!("{}.children: a split needs at least one pane", key)
+            }
+            (None, Some(_)) => self
+                .children
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, child)| child.check(&format!("{}.children.{}", key, i))),
+            (None, None) => bail!("{}: needs a pane or a split", key),
+        }
+    }
+
+    fn is_visible(&self) -> bool {
+        !self.hidden && (self.pane.is_some() || self.children.iter().any(LayoutNode::is_visible))
+    }
+
+    /// The panes of the layout in order, only the visible ones or all of them
+    pub fn panes(&self, visible: bool) -> Vec<WindowType> {
+        if visible && !self.is_visible() {
+            return Vec::new();
+        }
+        match self.pane {
+            Some(pane) => vec![pane],
+            None => self
+                .children
+                .iter()
+                .flat_map(|child| child.panes(visible))
+                .collect(),
+        }
+    }
+
+    /// Splits the area between the visible panes
+    pub fn areas(&self, area: Rect, focus: WindowType) -> Vec<PaneArea> {
+        let mut areas = Vec::new();
+        self.place(area, &mut areas);
+        let several = areas.len() > 1;
+        for pane in areas.iter_mut() {
+            pane.focused = several && pane.window_type == focus;
+        }
+        areas
+    }
+
+    fn place(&self, area: Rect, areas: &mut Vec<PaneArea>) {
+        if !self.is_visible() {
+            return;
+        }
+        if let Some(window_type) = self.pane {
+            return areas.push(PaneArea {
+                window_type,
+                area,
+                focused: false,
+            });
+        }
+
+        let visible: Vec<&LayoutNode> = self.children.iter().filter(|c| c.is_visible()).collect();
+        let total: u32 = visible.iter().map(|child| child.size as u32).sum();
+        let constraints: Vec<Constraint> = visible
+            .iter()
+            .map(|child| Constraint::Ratio(child.size as u32, total))
+            .collect();
+        let vertical = self.split == Some(Split::Vertical);
+        let direction = match vertical {
+            true => Direction::Vertical,
+            false => Direction::Horizontal,
+        };
+        let chunks = Layout::default()
+            .direction(direction)
+            .constraints(constraints)
+            .split(area);
+
+        let mut lengths: Vec<u16> = chunks
+            .iter()
+            .map(|chunk| match vertical {
+                true => chunk.height,
+                false => chunk.width,
+            })
+            .collect();
+        keep_min_cells(&mut lengths);
+        let mut chunk = Rect {
+            width: 0,
+            height: 0,
+            ..area
+        };
+        for (child, length) in visible.into_iter().zip(lengths) {
+            chunk = match vertical {
+                true => Rect {
+                    y: chunk.y + chunk.height,
+                    height: length,
+                    ..area
+                },
+                false => Rect {
+                    x: chunk.x + chunk.width,
+                    width: length,
+                    ..area
+                },
+            };
+            child.place(chunk, areas);
+        }
+    }
+
+    /// Shows or hides the pane, false if the layout has none or it's the chat
+    pub fn toggle(&mut self, window_type: WindowType) -> bool {
+        if window_type == WindowType::Chat {
+            return false;
+        }
+        if self.pane == Some(window_type) {
+            self.hidden = !self.hidden;
+            return true;
+        }
+        self.children
+            .iter_mut()
+            .any(|child| child.toggle(window_type))
+    }
+
+    /// Moves the border between the pane and the next visible pane of its split, a pane
+    /// that is alone in its split resizes the split. False if nothing could be resized.
+    pub fn resize(&mut self, window_type: WindowType, delta: i16) -> bool {
+        if !self.panes(true).contains(&window_type) {
+            return false;
+        }
+        self.resize_in(window_type, delta as i32) == Some(true)
+    }
+
+    // None if the pane isn't in this node, Some(false) if it is but wasn't resized yet
+    fn resize_in(&mut self, window_type: WindowType, delta: i32) -> Option<bool> {
+        if self.pane == Some(window_type) {
+            return Some(false);
+        }
+
+        let visible: Vec<usize> = (0..self.children.len())
+            .filter(|i| self.children[*i].is_visible())
+            .collect();
+        for i in 0..self.children.len() {
+            match self.children[i].resize_in(window_type, delta) {
+                None => continue,
+                Some(false) if visible.len() > 1 && visible.contains(&i) => {
+                    let position = visible.iter().position(|v| *v == i).unwrap_or_default();
+                    let other = match visible.get(position + 1) {
+                        Some(next) => *next,
+                        None => visible[position - 1],
+                    };
+                    let (size, other_size) = (
+                        self.children[i].size as i32,
+                        self.children[other].size as i32,
+                    );
+                    let delta = delta.clamp(MIN_SIZE - size, other_size - MIN_SIZE);
+                    if delta == 0 {
+                        return Some(false);
+                    }
+                    self.children[i].size = (size + delta) as u16;
+                    self.children[other].size = (other_size - delta) as u16;
+                    return Some(true);
+                }
+                found => return found,
+            }
+        }
+        None
+    }
+}
+
+// Grows the panes below MIN_CELLS with cells of the biggest one
+fn keep_min_cells(lengths: &mut [u16]) {
+    for i in 0..lengths.len() {
+        while lengths[i] < MIN_CELLS {
+            let biggest = match (0..lengths.len()).max_by_key(|j| lengths[*j]) {
+                Some(biggest) if lengths[biggest] > MIN_CELLS => biggest,
+                _ => return,
+            };
+            lengths[biggest] -= 1;
+            lengths[i] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn areas(layout: &LayoutNode) -> Vec<(WindowType, u16, u16)> {
+        layout
+            .areas(Rect::new(0, 0, 100, 40), WindowType::Chat)
+            .iter()
+            .map(|pane| (pane.window_type, pane.area.width, pane.area.height))
+            .collect()
+    }
+
+    #[test]
+    fn hidden_panes_leave_their_space() {
+        let mut layout = LayoutNode::standard();
+        assert_eq!(areas(&layout), vec![(WindowType::Chat, 100, 40)]);
+
+        assert!(layout.toggle(WindowType::UserList));
+        assert!(layout.toggle(WindowType::Debug));
+        assert_eq!(
+            areas(&layout),
+            vec![
+                (WindowType::Debug, 100, 20),
+                (WindowType::Chat, 80, 20),
+                (WindowType::UserList, 20, 20)
+            ]
+        );
+        assert!(!layout.toggle(WindowType::Chat));
+        assert!(!layout.toggle(WindowType::Whispers));
+    }
+
+    #[test]
+    fn only_the_focused_pane_of_several_stands_out() {
+        let mut layout = LayoutNode::standard();
+        let area = Rect::new(0, 0, 100, 40);
+        assert!(!layout.areas(area, WindowType::Chat)[0].focused);
+        layout.toggle(WindowType::UserList);
+        let focused: Vec<bool> = layout
+            .areas(area, WindowType::Chat)
+            .iter()
+            .map(|pane| pane.focused)
+            .collect();
+        assert_eq!(focused, vec![true, false]);
+    }
+
+    #[test]
+    fn resize_moves_the_border() {
+        let mut layout = LayoutNode::standard();
+        layout.toggle(WindowType::UserList);
+        assert!(layout.resize(WindowType::UserList, 10));
+        assert_eq!(areas(&layout)[1], (WindowType::UserList, 30, 40));
+        assert!(layout.resize(WindowType::Chat, 100));
+        assert_eq!(areas(&layout)[1], (WindowType::UserList, 5, 40));
+        assert!(!layout.resize(WindowType::Chat, 1));
+
+        // alone in its split, so the split above gets resized
+        layout.toggle(WindowType::UserList);
+        layout.toggle(WindowType::Debug);
+        assert!(layout.resize(WindowType::Chat, 30));
+        assert_eq!(areas(&layout)[0], (WindowType::Debug, 100, 8));
+        assert!(!layout.resize(WindowType::Whispers, 5));
+    }
+
+    #[test]
+    fn small_panes_keep_their_borders() {
+        let mut layout = LayoutNode::standard();
+        layout.toggle(WindowType::Debug);
+        assert!(layout.resize(WindowType::Debug, -100));
+        let area = Rect::new(0, 0, 80, 24);
+        let areas = layout.areas(area, WindowType::Debug);
+        assert_eq!(areas[0].area, Rect::new(0, 0, 80, 3));
+        assert_eq!(areas[1].area, Rect::new(0, 3, 80, 21));
+
+        let mut lengths = [1, 0, 10];
+        keep_min_cells(&mut lengths);
+        assert_eq!(lengths, [3, 3, 5]);
+        let mut lengths = [1, 4];
+        keep_min_cells(&mut lengths);
+        assert_eq!(lengths, [2, 3]);
+    }
+
+    #[test]
+    fn broken_layouts_name_the_key() {
+        let error = |layout: LayoutNode| layout.validate().unwrap_err().to_string();
+        assert!(LayoutNode::standard().validate().is_ok());
+
+        let mut layout = LayoutNode::standard();
+        layout.children[1].children[1].size = 0;
+        assert_eq!(
+            error(layout),
+            "layout.children.1.children.1.size: must be more than 0"
+        );
+
+        let mut layout = LayoutNode::standard();
+        layout.children[0] = LayoutNode::pane(WindowType::Chat, 50);
+        assert_eq!(error(layout), "layout: has more than one chat pane");
+
+        let layout = LayoutNode::pane(WindowType::Debug, 100);
+        assert_eq!(error(layout), "layout: needs a chat pane");
+
+        let layout = LayoutNode::split(Split::Vertical, 100, Vec::new());
+        assert_eq!(
+            error(layout),
+            "layout.children: a split needs at least one pane"
+        );
+
+        let json = r#"{"split":"vertical","children":[{"pane":"chat"},{"pane":"chat_input"}]}"#;
+        let layout: LayoutNode = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            error(layout),
+            "layout.children.1.pane: chat_input is part of the chat pane"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashMap, ops::Range};
+
+use tui::text::Spans;
+
+use super::search::Search;
+
+/// The wrapped lines of the visible chat messages, keyed by their absolute index.
+/// Messages only get wrapped again when the width or the search changes
+/// instead of on every frame.
+pub struct LineCache {
+    width: usize,
+    search: Option<(String, Option<usize>)>,
+    lines: HashMap<usize, Vec<Spans<'static>>>,
+}
+
+impl LineCache {
+    pub fn new() -> LineCache {
+        LineCache {
+            width: 0,
+            search: None,
+            lines: HashMap::new(),
+        }
+    }
+
+    /// Forgets every message outside of visible and everything if the width or the search changed.
+    pub fn prepare(&mut self, width: usize, search: Option<&Search>, visible: Range<usize>) {
+        let search = search.map(|search| (search.input.to_string(), search.current));
+        if self.width != width || self.search != search {
+            self.lines.clear();
+            self.width = width;
+            self.search = search;
+        }
+
+        self.lines.retain(|index, _| visible.contains(index));
+    }
+
+    pub fn get_or_insert_with(
+        &mut self,
+        index: usize,
+        wrap: impl FnOnce() -> Vec<Spans<'static>>,
+    ) -> &[Spans<'static>] {
+        self.lines.entry(index).or_insert_with(wrap)
+    }
+
+    /// Wraps the message again on the next draw, e.g. after revealing its links.
+    pub fn remove(&mut self, index: usize) {
+        self.lines.remove(&index);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl Default for LineCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_once_per_width() {
+        let mut cache = LineCache::new();
+        let mut wrapped = 0;
+        for _ in 0..3 {
+            cache.prepare(80, None, 0..1);
+            cache.get_or_insert_with(0, || {
+                wrapped += 1;
+                vec![Spans::from("hi")]
+            });
+        }
+        assert_eq!(wrapped, 1);
+
+        cache.prepare(40, None, 0..1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn forgets_invisible_messages() {
+        let mut cache = LineCache::new();
+        cache.prepare(80, None, 0..2);
+        cache.get_or_insert_with(0, Vec::new);
+        cache.get_or_insert_with(1, Vec::new);
+        cache.prepare(80, None, 1..3);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn search_changes_invalidate() {
+        let mut cache = LineCache::new();
+        cache.prepare(80, None, 0..1);
+        cache.get_or_insert_with(0, Vec::new);
+        let search = Search::from_query("hi".to_string());
+        cache.prepare(80, Some(&search), 0..1);
+        assert!(cache.is_empty());
+    }
+}
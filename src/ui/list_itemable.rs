@@ -4,20 +4,27 @@ use tui::{
     widgets::ListItem,
 };
 
-use crate::chat::{message::ChatMessage, user::User};
+use crate::{
+    chat::{message::ChatMessage, user::User},
+    ui::{render::badge_spans, theme::Theme},
+};
 
 // We basically use ListItems in every UI, so a Trait that can make something to an ListItem is good
 pub trait ListItemable {
-    fn to_list_item(&self) -> ListItem;
+    fn to_list_item(&self, theme: &Theme) -> ListItem<'_>;
 }
 
 impl ListItemable for User {
-    fn to_list_item(&self) -> ListItem {
-        let name_color = self.flair.to_color();
-        let line = Spans::from(vec![Span::styled(
-            format!("{}", &self.name),
-            Style::default().fg(name_color),
-        )]);
+    fn to_list_item(&self, theme: &Theme) -> ListItem<'_> {
+        let mut spans = badge_spans(&self.flairs, theme, Style::default());
+        if !spans.is_empty() {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::styled(
+            self.name.to_string(),
+            Style::default().fg(theme.nick_color(&self.flairs)),
+        ));
+        let line = Spans::from(spans);
         ListItem::new(line)
     }
 }
@@ -25,7 +32,7 @@ impl ListItemable for User {
 // Probably not possible, since Linewraps return multiple ListItems
 // Would need to return a Vec<ListItem>
 impl ListItemable for ChatMessage {
-    fn to_list_item(&self) -> ListItem {
+    fn to_list_item(&self, _theme: &Theme) -> ListItem<'_> {
         todo!()
     }
 }
@@ -1,7 +1,14 @@
 pub mod chat_input;
+pub mod color;
 pub mod emotes;
+pub mod keymap;
+pub mod layout;
+pub mod line_cache;
 pub mod list_itemable;
 pub mod parser;
 pub mod render;
+pub mod search;
+pub mod settings;
 pub mod suggester;
+pub mod theme;
 pub mod window;
@@ -1,119 +1,263 @@
-use crate::chat::{features::Feature, message::ChatMessage, user::UserList};
-use anyhow::Result;
-use tui::{style::Style, text::Span, widgets::ListItem};
+use std::ops::Range;
 
-use super::emotes::EmoteList;
+use super::emotes::{Emote, EmoteList, MODIFIERS};
 
-// TODO: currently not used at all
-// dont know if it even makes sense rn
+/// A single whitespace separated word of a chat message after parsing.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Part<'a> {
+    Emote(ModifiedEmote<'a>),
+    Word(&'a str),
+}
 
-pub struct ParsedMessage<'a> {
-    parts: Vec<ListItem<'a>>,
-    words: Vec<String>,
-    name: String,
-    emote_list: &'a EmoteList,
-    user_list: &'a UserList,
+/// An emote together with the modifiers written after it, e.g. `PEPE:wide:flip`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ModifiedEmote<'a> {
+    pub emote: &'a Emote,
+    pub modifiers: Vec<&'a str>,
 }
 
-impl<'a> ParsedMessage<'a> {
-    pub fn new(emote_list: &'a EmoteList, user_list: &'a UserList) -> Self {
-        ParsedMessage {
-            parts: Vec::new(),
-            words: Vec::new(),
-            name: String::from(""),
-            emote_list,
-            user_list,
+/// Parses a word as an emote with optional modifiers.
+/// Returns None if the base isn't an emote or any modifier is unknown.
+pub fn parse_emote<'a>(word: &'a str, emotes: &'a EmoteList) -> Option<ModifiedEmote<'a>> {
+    let mut splits = word.split(':');
+    let emote = emotes.get(splits.next()?)?;
+
+    let modifiers: Vec<&str> = splits.collect();
+    if !modifiers.iter().all(|m| MODIFIERS.contains(m)) {
+        return None;
+    }
+
+    Some(ModifiedEmote { emote, modifiers })
+}
+
+pub fn parse_parts<'a>(message: &'a str, emotes: &'a EmoteList) -> Vec<Part<'a>> {
+    message
+        .split_whitespace()
+        .map(|word| match parse_emote(word, emotes) {
+            Some(emote) => Part::Emote(emote),
+            None => Part::Word(word),
+        })
+        .collect()
+}
+
+/// The destiny.gg formatting of a piece of a message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Format {
+    /// Between `||`, hidden until revealed
+    pub spoiler: bool,
+    /// Between backticks, nothing inside is an emote or spoiler
+    pub code: bool,
+}
+
+/// A piece of a word with its format, e.g. `secret` and `.` of `||secret||.`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub part: Part<'a>,
+    pub format: Format,
+}
+
+/// Splits the message into formatted pieces without the markers.
+/// Markers without a closing one or with nothing in between are kept as text.
+pub fn parse_format(message: &str) -> Vec<(Range<usize>, Format)> {
+    let mut pieces = Vec::new();
+    let mut format = Format::default();
+    let mut start = 0;
+    let mut i = 0;
+
+    while let Some(c) = message[i..].chars().next() {
+        let rest = &message[i..];
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`').filter(|end| *end > 0) {
+                pieces.push((start..i, format));
+                let code = Format {
+                    code: true,
+                    ..format
+                };
+                pieces.push((i + 1..i + 1 + end, code));
+                i += end + 2;
+                start = i;
+                continue;
+            }
+        } else if let Some(after) = rest.strip_prefix("||") {
+            let toggles = match format.spoiler {
+                true => true,
+                false => !after.starts_with("||") && after.contains("||"),
+            };
+            if toggles {
+                pieces.push((start..i, format));
+                format.spoiler = !format.spoiler;
+                i += 2;
+                start = i;
+                continue;
+            }
         }
+        i += c.len_utf8();
     }
+    pieces.push((start..message.len(), format));
+
+    pieces.retain(|(range, _)| !range.is_empty());
+    pieces
+}
+
+/// Whether the message has a spoiler that needs revealing
+pub fn has_spoiler(message: &str) -> bool {
+    parse_format(message)
+        .iter()
+        .any(|(range, format)| format.spoiler && !message[range.clone()].trim().is_empty())
+}
 
-    pub fn from_chat_message(
-        chat_message: ChatMessage,
-        emote_list: &'a EmoteList,
-        user_list: &'a UserList,
-    ) -> Self {
-        let words = chat_message
-            .message
-            .split_whitespace()
-            .map(|s| s.to_owned())
-            .collect();
-
-        ParsedMessage {
-            parts: Vec::new(),
-            name: chat_message.name,
-            words,
-            emote_list,
-            user_list,
+/// The whitespace separated words of a message, a word has more than one segment
+/// when its format changes in the middle, e.g. `||secret||.`
+pub fn parse_words<'a>(message: &'a str, emotes: &'a EmoteList) -> Vec<Vec<Segment<'a>>> {
+    let mut words: Vec<Vec<Segment>> = Vec::new();
+    // the last piece ended in the middle of a word
+    let mut glued = false;
+
+    for (range, format) in parse_format(message) {
+        let text = &message[range];
+        let starts_word = text.starts_with(char::is_whitespace);
+        for (n, word) in text.split_whitespace().enumerate() {
+            let part = match format.code {
+                true => Part::Word(word),
+                false => match parse_emote(word, emotes) {
+                    Some(emote) => Part::Emote(emote),
+                    None => Part::Word(word),
+                },
+            };
+            let segment = Segment { part, format };
+            match words.last_mut() {
+                Some(last) if n == 0 && glued && !starts_word => last.push(segment),
+                _ => words.push(vec![segment]),
+            }
         }
+        glued = !text.ends_with(char::is_whitespace);
     }
+    words
+}
 
-    pub fn parse_chat_message(&mut self) {
-        // Handle Linewrapping
-        let full_line = format!("{}: {}", self.name, self.words.join(" "));
-        // let lines = textwrap::wrap(&full_line, width);
-
-        let items: Vec<ListItem> = self
-            .words
-            .iter()
-            .map(|word| match word {
-                arrow_right if word.starts_with(">") => {
-                    ListItem::new(Span::styled(arrow_right, Style::default()))
-                }
-                link if word.starts_with("https://") => {
-                    ListItem::new(Span::styled(link, Style::default()))
-                }
-                embed if word.starts_with("#youtube") => {
-                    ListItem::new(Span::styled(embed, Style::default()))
-                }
-                embed if word.starts_with("#twitch") => {
-                    ListItem::new(Span::styled(embed, Style::default()))
-                }
-                tag if word.len() == 4 && word.contains("nsfw") => {
-                    ListItem::new(Span::styled(tag, Style::default()))
-                }
-                tag if word.len() == 4 && word.contains("nsfl") => {
-                    ListItem::new(Span::styled(tag, Style::default()))
-                }
-                name if self.is_emote(&word) => ListItem::new(Span::styled(name, Style::default())),
-                name if self.is_user(&word) => ListItem::new(Span::styled(name, Style::default())),
-                _ => ListItem::new(Span::styled(word, Style::default())),
-            })
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain_emote() {
+        let emotes = EmoteList::new();
+        let emote = parse_emote("PEPE", &emotes).unwrap();
+        assert_eq!(emote.emote.name, "PEPE");
+        assert!(emote.modifiers.is_empty());
     }
 
-    pub fn is_user(&self, word: &str) -> bool {
-        self.user_list.users.iter().any(|user| word == user.name)
+    #[test]
+    fn parse_emote_with_modifiers() {
+        let emotes = EmoteList::new();
+        let emote = parse_emote("PEPE:wide:flip", &emotes).unwrap();
+        assert_eq!(emote.emote.name, "PEPE");
+        assert_eq!(emote.modifiers, vec!["wide", "flip"]);
     }
 
-    pub fn is_emote(&self, word: &str) -> bool {
-        self.emote_list
-            .emotes
-            .iter()
-            .any(|emote| word == emote.name)
+    #[test]
+    fn parse_emote_unknown_modifier() {
+        let emotes = EmoteList::new();
+        assert!(parse_emote("PEPE:notamodifier", &emotes).is_none());
+        assert!(parse_emote("PEPE:", &emotes).is_none());
     }
-}
 
-pub enum Part<'a> {
-    ArrowRight(&'a str),
-    Link(&'a str),
-    YoutubeEmbed(&'a str),
-    TwitchEmbed(&'a str),
-    Nsfw(&'a str),
-    Nsfl(&'a str),
-    Emote(&'a str),
-    User(&'a str),
-    Word(&'a str),
-}
+    #[test]
+    fn parse_emote_unknown_emote() {
+        let emotes = EmoteList::new();
+        assert!(parse_emote("pepe:wide", &emotes).is_none());
+        assert!(parse_emote(":wide", &emotes).is_none());
+    }
 
-pub fn parse_emotes(words: &mut Vec<&str>, emotes: &EmoteList) -> String {
-    // replace words into emotes
-    emotes.emotes.iter().for_each(|emote| {
-        for word in words.iter_mut() {
-            if *word == emote.name {
-                *word = emote.emote;
-            }
-        }
-    });
+    #[test]
+    fn parse_parts_mixed() {
+        let emotes = EmoteList::new();
+        let parts = parse_parts("hello PEPE:rain world", &emotes);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], Part::Word("hello"));
+        assert!(matches!(&parts[1], Part::Emote(e) if e.modifiers == vec!["rain"]));
+        assert_eq!(parts[2], Part::Word("world"));
+    }
+
+    fn formats(message: &str) -> Vec<(&str, Format)> {
+        parse_format(message)
+            .into_iter()
+            .map(|(range, format)| (&message[range], format))
+            .collect()
+    }
+
+    const PLAIN: Format = Format {
+        spoiler: false,
+        code: false,
+    };
+    const SPOILER: Format = Format {
+        spoiler: true,
+        code: false,
+    };
+    const CODE: Format = Format {
+        spoiler: false,
+        code: true,
+    };
 
-    // reconstruct into message and return
-    words.join(" ")
+    #[test]
+    fn spoilers_and_code() {
+        assert_eq!(
+            formats("a ||b c|| d"),
+            vec![("a ", PLAIN), ("b c", SPOILER), (" d", PLAIN)]
+        );
+        assert_eq!(
+            formats("run `cargo test`"),
+            vec![("run ", PLAIN), ("cargo test", CODE)]
+        );
+        assert_eq!(
+            formats("||a `b`||"),
+            vec![
+                ("a ", SPOILER),
+                (
+                    "b",
+                    Format {
+                        spoiler: true,
+                        code: true
+                    }
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn markers_need_content_and_a_closing_one() {
+        assert_eq!(formats("a || b"), vec![("a || b", PLAIN)]);
+        assert_eq!(formats("||||"), vec![("||||", PLAIN)]);
+        assert_eq!(formats("a ` b"), vec![("a ` b", PLAIN)]);
+        assert_eq!(formats("``"), vec![("``", PLAIN)]);
+        // spoiler markers are text inside code
+        assert_eq!(formats("`||a||`"), vec![("||a||", CODE)]);
+        assert!(!has_spoiler("a || b"));
+        assert!(!has_spoiler("|| ||"));
+        assert!(has_spoiler("a ||b||"));
+    }
+
+    #[test]
+    fn words_keep_their_segments_together() {
+        let emotes = EmoteList::new();
+        let words = parse_words("see ||secret||. `PEPE` PEPE", &emotes);
+        assert_eq!(words.len(), 4);
+        assert_eq!(words[0][0].part, Part::Word("see"));
+        assert_eq!(
+            words[1],
+            vec![
+                Segment {
+                    part: Part::Word("secret"),
+                    format: SPOILER
+                },
+                Segment {
+                    part: Part::Word("."),
+                    format: PLAIN
+                },
+            ]
+        );
+        // emotes aren't emotes in code
+        assert_eq!(words[2][0].part, Part::Word("PEPE"));
+        assert!(matches!(words[3][0].part, Part::Emote(_)));
+    }
 }
@@ -4,73 +4,541 @@ use anyhow::Result;
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Tabs, Wrap},
     Frame,
 };
 
-use crate::chat::{message::ChatMessage, state::State};
+use crate::chat::{
+    chat_log::LogEntry,
+    features::{Flair, Flairs},
+    mentions::MentionKind,
+    message::{ChatMessage, LinkTag},
+    search::is_link,
+    state::State,
+    store::MessageStore,
+    time::{format_datetime, format_time},
+};
 
 use super::{
     emotes::EmoteList,
+    keymap::{Keymap, Mode},
+    layout::PaneArea,
+    line_cache::LineCache,
     list_itemable::ListItemable,
-    parser::parse_emotes,
+    parser::{parse_words, Part, Segment},
+    search::Search,
+    settings::{SettingKind, Settings, SETTINGS},
     suggester::Suggestor,
+    theme::Theme,
     window::{Window, WindowList, WindowType},
 };
 
+// Messages of the client the log pane lists
+const CLIENT_NAMES: &[&str] = &["INFO", "ERROR", "LOG", "STALK"];
+
+/// The panes of the active tab where the layout put them, the chat with its input below.
 pub fn draw<B: Backend>(
     f: &mut Frame<B>,
+    panes: &[PaneArea],
     state: &State,
-    emote_list: &EmoteList,
     suggestions: &Suggestor,
+    search: Option<&Search>,
     windows: &mut WindowList,
+    cache: &mut LineCache,
 ) -> Result<()> {
-    let debug_active = windows.get(WindowType::Debug).active;
-    let userlist_active = windows.get(WindowType::UserList).active;
-    let size = f.size();
-    let chunks = get_chunks(&size, windows);
+    let emote_list = suggestions.emote_list;
+    let mut chat = None;
+    for pane in panes {
+        match pane.window_type {
+            WindowType::Chat => chat = Some(*pane),
+            WindowType::UserList => render_users(f, pane, state),
+            WindowType::Debug => render_debug(f, pane, state, windows),
+            WindowType::Mentions => render_mentions(f, pane, state, windows),
+            WindowType::Whispers | WindowType::Embeds | WindowType::Log => {
+                render_messages(f, pane, state, windows)
+            }
+            WindowType::ChatInput => (),
+        }
+    }
 
-    if debug_active == true && userlist_active == true {
-        render_debug(f, chunks[2], &state, windows);
-        render_users(f, chunks[3], &state);
-    } else if debug_active {
-        render_debug(f, chunks[2], &state, windows);
-    } else if userlist_active {
-        render_users(f, chunks[2], &state);
+    // Drawn last, the login screen and the suggestions may cover the other panes
+    let chat = match chat {
+        Some(chat) => chat,
+        None => return Ok(()),
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Min(3), Constraint::Length(3)])
+        .split(chat.area);
+    let (chat, input) = (
+        PaneArea {
+            area: chunks[0],
+            ..chat
+        },
+        chunks[1],
+    );
+    let chat_window = windows.get_mut(WindowType::Chat);
+    render_chat(f, &chat, state, emote_list, search, chat_window, cache)?;
+    if let Some(reason) = &state.login {
+        render_login(f, chat.area, input, state, reason);
+        return Ok(());
     }
 
-    // Always render chat and chat_input
-    let mut chat_window = windows.get_mut(WindowType::Chat);
-    render_chat(f, chunks[0], &state, &emote_list, &mut chat_window)?;
-    render_chat_input(f, chunks[1], &state, &suggestions);
+    match search {
+        Some(search) => render_search_input(f, input, state, search),
+        None => {
+            render_chat_input(f, input, state);
+            render_suggestions(f, input, suggestions, &state.theme);
+        }
+    }
 
     Ok(())
 }
 
-fn render_chat_input<B: Backend>(
+// The border of a pane, the focused one is drawn in the accent colour
+fn pane_block<'a>(title: impl Into<Spans<'a>>, pane: &PaneArea, theme: &Theme) -> Block<'a> {
+    let block = Block::default()
+        .style(theme.window)
+        .borders(Borders::ALL)
+        .title(title);
+    match pane.focused {
+        true => block.border_style(theme.accent),
+        false => block,
+    }
+}
+
+/// The tabs in the first row of the area with their unread messages and mentions, e.g. "local 3 @1".
+pub fn draw_tabs<B: Backend>(
     f: &mut Frame<B>,
-    chunk: Rect,
+    area: Rect,
+    tabs: &[(&str, usize, usize)],
+    active: usize,
+    theme: &Theme,
+) {
+    let titles = tabs
+        .iter()
+        .map(|(name, unread, mentions)| tab_title(name, *unread, *mentions, theme))
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(active)
+        .style(theme.window)
+        .highlight_style(theme.selected)
+        .divider(Span::styled("│", theme.hint));
+    f.render_widget(
+        tabs,
+        Rect {
+            height: area.height.min(1),
+            ..area
+        },
+    );
+}
+
+fn tab_title(name: &str, unread: usize, mentions: usize, theme: &Theme) -> Spans<'static> {
+    let mut spans = vec![Span::raw(name.to_string())];
+    if unread > 0 {
+        spans.push(Span::styled(format!(" {}", unread), theme.hint));
+    }
+    if mentions > 0 {
+        spans.push(Span::styled(format!(" @{}", mentions), theme.mention));
+    }
+    Spans::from(spans)
+}
+
+/// The settings screen on top of everything else.
+pub fn draw_settings<B: Backend>(f: &mut Frame<B>, settings: &Settings, theme: &Theme) {
+    let size = f.size();
+    let rows = settings.rows();
+    let key_width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+
+    let mut items: Vec<ListItem> = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, (key, value))| {
+            let selected = i == settings.selected;
+            let value = match &settings.editing {
+                Some(editing) if selected => format!("{}_", editing),
+                _ => value,
+            };
+            let style = match selected {
+                true => theme.selected,
+                false => theme.window,
+            };
+            ListItem::new(Spans::from(Span::styled(
+                format!("{:width$}  {}", key, value, width = key_width),
+                style,
+            )))
+        })
+        .collect();
+
+    items.push(ListItem::new(""));
+    if let Some(error) = &settings.error {
+        items.push(ListItem::new(Span::styled(error.to_string(), theme.error)));
+    }
+    let hint = match settings.editing {
+        Some(_) => "Enter applies, Esc cancels",
+        None => match SETTINGS[settings.selected].kind {
+            SettingKind::Bool | SettingKind::Choice(_) => "Enter changes, s saves, Esc closes",
+            SettingKind::Number | SettingKind::Text => "Enter edits, s saves, Esc closes",
+        },
+    };
+    items.push(ListItem::new(Span::styled(hint, theme.hint)));
+
+    let width = size.width.min(70);
+    let height = ((items.len() + 2) as u16).min(size.height);
+    let area = Rect::new(
+        (size.width - width) / 2,
+        (size.height - height) / 2,
+        width,
+        height,
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .style(theme.window)
+            .borders(Borders::ALL)
+            .title("Settings"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// Every key binding, opened with "?" and closed with any key.
+pub fn draw_bindings<B: Backend>(f: &mut Frame<B>, keymap: &Keymap, theme: &Theme) {
+    let size = f.size();
+    let bindings = keymap.bindings();
+    let has_normal = bindings.iter().any(|(mode, _, _)| *mode == Mode::Normal);
+    let keys_width = bindings
+        .iter()
+        .map(|(_, keys, _)| keys.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut items: Vec<ListItem> = bindings
+        .iter()
+        .map(|(mode, keys, action)| {
+            let mode = match (has_normal, mode) {
+                (false, _) => "",
+                (true, Mode::Insert) => "insert  ",
+                (true, Mode::Normal) => "normal  ",
+            };
+            ListItem::new(Spans::from(vec![
+                Span::styled(mode, theme.hint),
+                Span::styled(format!("{:width$}", keys, width = keys_width), theme.accent),
+                Span::raw("  "),
+                Span::raw(action.describe()),
+            ]))
+        })
+        .collect();
+    items.push(ListItem::new(""));
+    items.push(ListItem::new(Span::styled("Any key closes", theme.hint)));
+
+    let width = size.width.min(70);
+    let height = ((items.len() + 2) as u16).min(size.height);
+    let area = Rect::new(
+        (size.width - width) / 2,
+        (size.height - height) / 2,
+        width,
+        height,
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .style(theme.window)
+            .borders(Borders::ALL)
+            .title(format!("Keys ({})", keymap.preset.name())),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// Every flair of a nick, opened with /flairs and closed with any key.
+pub fn draw_flairs<B: Backend>(
+    f: &mut Frame<B>,
+    nick: &str,
+    features: &[String],
+    flairs: &Flairs,
+    theme: &Theme,
+) {
+    let size = f.size();
+    let mut items: Vec<ListItem> = flairs
+        .parse(features)
+        .iter()
+        .map(|flair| {
+            let style = Style::default().fg(theme.nick_color(std::slice::from_ref(flair)));
+            let label = match flair.label.is_empty() {
+                true => &flair.name,
+                false => &flair.label,
+            };
+            let mut spans = vec![
+                Span::styled(format!("{:4}", flair.badge().unwrap_or("")), style),
+                Span::styled(label.to_string(), style),
+            ];
+            if flair.hidden {
+                spans.push(Span::styled(" (hidden)", theme.hint));
+            }
+            ListItem::new(Spans::from(spans))
+        })
+        .collect();
+    // not even the last flairs.json knew them
+    items.extend(
+        flairs
+            .unknown(features)
+            .into_iter()
+            .map(|name| ListItem::new(Span::styled(format!("    {} (unknown)", name), theme.hint))),
+    );
+    if items.is_empty() {
+        items.push(ListItem::new(Span::styled("No flairs", theme.hint)));
+    }
+    items.push(ListItem::new(""));
+    items.push(ListItem::new(Span::styled("Any key closes", theme.hint)));
+
+    let width = size.width.min(40);
+    let height = ((items.len() + 2) as u16).min(size.height);
+    let area = Rect::new(
+        (size.width - width) / 2,
+        (size.height - height) / 2,
+        width,
+        height,
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .style(theme.window)
+            .borders(Borders::ALL)
+            .title(format!("Flairs of {}", nick)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// The vim mode and unfinished sequences, in the bottom right corner.
+pub fn draw_key_status<B: Backend>(f: &mut Frame<B>, keymap: &Keymap, theme: &Theme) {
+    let pending = keymap.pending();
+    let status = match (keymap.mode, pending.is_empty()) {
+        (_, false) => pending,
+        (Mode::Normal, true) => "-- NORMAL --".to_string(),
+        (Mode::Insert, true) => return,
+    };
+
+    let size = f.size();
+    let width = (status.len() as u16 + 2).min(size.width);
+    let area = Rect::new(
+        size.width - width,
+        size.height.saturating_sub(1),
+        width,
+        1.min(size.height),
+    );
+    let status = Paragraph::new(format!(" {} ", status)).style(theme.window.patch(theme.accent));
+    f.render_widget(status, area);
+}
+
+// Covers the chat with the login steps and hides the key while it is typed
+fn render_login<B: Backend>(
+    f: &mut Frame<B>,
+    chat_chunk: Rect,
+    input_chunk: Rect,
     state: &State,
+    reason: &str,
+) {
+    let theme = &state.theme;
+    let lines = vec![
+        Spans::from(Span::styled(reason.to_string(), theme.error)),
+        Spans::from(""),
+        Spans::from("1. Open https://www.destiny.gg/profile/developer"),
+        Spans::from("2. Go to Connections and press 'Add login key'"),
+        Spans::from("3. Paste the key below and press Enter"),
+        Spans::from(""),
+        Spans::from("Esc quits."),
+    ];
+
+    let width = chat_chunk.width.min(60);
+    let height = ((lines.len() + 4) as u16).min(chat_chunk.height);
+    let area = Rect::new(
+        chat_chunk.x + (chat_chunk.width - width) / 2,
+        chat_chunk.y + (chat_chunk.height - height) / 2,
+        width,
+        height,
+    );
+    let popup = Paragraph::new(lines)
+        .wrap(Wrap { trim: true })
+        .style(theme.window)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Log in to destiny.gg"),
+        );
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+
+    let masked = "*".repeat(state.chat_input.current_message.chars().count());
+    render_input(f, input_chunk, &masked, "Login key".to_string(), theme);
+}
+
+// Popup with the autocompletion candidates right above the chat input
+fn render_suggestions<B: Backend>(
+    f: &mut Frame<B>,
+    input_chunk: Rect,
     suggestions: &Suggestor,
+    theme: &Theme,
 ) {
-    let title = format!("Send:─{}", suggestions);
+    if suggestions.suggestions.is_empty() {
+        return;
+    }
 
+    let longest = suggestions
+        .suggestions
+        .iter()
+        .map(|s| s.len())
+        .max()
+        .unwrap_or(0);
+    let width = ((longest + 4) as u16).min(input_chunk.width);
+    let height = ((suggestions.suggestions.len() + 2) as u16).min(input_chunk.y);
+    let area = Rect::new(input_chunk.x, input_chunk.y - height, width, height);
+
+    let items: Vec<ListItem> = suggestions
+        .suggestions
+        .iter()
+        .enumerate()
+        .map(|(i, suggestion)| {
+            let style = if suggestions.selected == Some(i) {
+                theme.selected
+            } else {
+                theme.window
+            };
+            ListItem::new(Span::styled(suggestion.to_string(), style))
+        })
+        .collect();
+
+    let list = List::new(items).block(Block::default().style(theme.window).borders(Borders::ALL));
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+fn render_chat_input<B: Backend>(f: &mut Frame<B>, chunk: Rect, state: &State) {
+    let theme = &state.theme;
+    // Commands still work, so the notice only stands in for an empty input
+    if state.anonymous && state.chat_input.current_message.is_empty() {
+        let notice = Paragraph::new("Reading anonymously. Type / for commands, log in to chat.")
+            .style(theme.window.patch(theme.hint))
+            .block(Block::default().borders(Borders::ALL).title("Read-only"));
+        f.render_widget(notice, chunk);
+        return;
+    }
+
+    render_input(
+        f,
+        chunk,
+        &state.chat_input.current_message,
+        match state.is_moderator() {
+            true => "Send (moderator)".to_string(),
+            false => "Send".to_string(),
+        },
+        theme,
+    );
+}
+
+// The input box turns into the search bar while a search is open
+fn render_search_input<B: Backend>(f: &mut Frame<B>, chunk: Rect, state: &State, search: &Search) {
+    let theme = &state.theme;
+    if search.typing {
+        let title = "Search (from:nick has:link is:mention)".to_string();
+        render_input(f, chunk, &search.input, title, theme);
+        return;
+    }
+
+    let matches = search.matches(&state.messages);
+    let position = search
+        .current
+        .and_then(|current| matches.iter().rev().position(|i| *i == current))
+        .map_or(0, |i| i + 1);
+    let older = match &search.log_matches {
+        Some(entries) => format!(", {} older in the chat log", entries.len()),
+        None => String::new(),
+    };
+    let title = format!(
+        "Search: {}/{} matches{} (n/N to jump, Esc to close)",
+        position,
+        matches.len(),
+        older
+    );
+    let input = Paragraph::new(search.input.as_str())
+        .style(theme.window)
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(input, chunk);
+    if let Some(entries) = &search.log_matches {
+        render_log_matches(f, chunk, entries, state);
+    }
+}
+
+// The matches of the chat log above the search bar, the newest at the bottom
+fn render_log_matches<B: Backend>(
+    f: &mut Frame<B>,
+    input_chunk: Rect,
+    entries: &[LogEntry],
+    state: &State,
+) {
+    let theme = &state.theme;
+    // the chat keeps at least half of its room for the matches in it
+    let height = ((entries.len() + 2) as u16).min(input_chunk.y / 2);
+    if entries.is_empty() || height < 3 {
+        return;
+    }
+    let area = Rect::new(
+        input_chunk.x,
+        input_chunk.y - height,
+        input_chunk.width,
+        height,
+    );
+
+    let start = entries.len().saturating_sub(height as usize - 2);
+    let items: Vec<ListItem> = entries[start..]
+        .iter()
+        .map(|entry| {
+            let flairs = state.flairs.parse(&entry.features);
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("{} ", format_datetime(entry.timestamp)), theme.hint),
+                Span::styled(
+                    entry.nick.to_string(),
+                    Style::default()
+                        .fg(theme.nick_color(&flairs))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(format!(": {}", entry.data), theme.message),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .style(theme.window)
+            .borders(Borders::ALL)
+            .title("Older in the chat log"),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+fn render_input<B: Backend>(
+    f: &mut Frame<B>,
+    chunk: Rect,
+    text: &str,
+    title: String,
+    theme: &Theme,
+) {
     // Scrolling when we exceed the width of the input rect
     let start_range;
-    let mut cursor_x = chunk.x + state.chat_input.current_message.len() as u16 + 1;
-    if state.chat_input.current_message.len() < (chunk.width - 2).into() {
+    let mut cursor_x = chunk.x + text.len() as u16 + 1;
+    let width = chunk.width.saturating_sub(2) as usize;
+    if text.len() < width {
         start_range = 0;
     } else {
-        start_range = state.chat_input.current_message.len() - ((chunk.width - 2) as usize);
-        if start_range > (chunk.width - 2).into() {
+        start_range = text.len() - width;
+        if start_range > width {
             cursor_x -= 1;
         }
     }
 
-    let input = Paragraph::new(&state.chat_input.current_message[start_range..])
-        .style(Style::default().bg(Color::Black).fg(Color::White))
+    let input = Paragraph::new(&text[start_range..])
+        .style(theme.window)
         .block(Block::default().borders(Borders::ALL).title(title));
 
     f.set_cursor(cursor_x, chunk.y + 1);
@@ -79,15 +547,19 @@ fn render_chat_input<B: Backend>(
 
 fn render_chat<B: Backend>(
     f: &mut Frame<B>,
-    chunk: Rect,
+    pane: &PaneArea,
     state: &State,
     emote_list: &EmoteList,
+    search: Option<&Search>,
     window: &mut Window,
+    cache: &mut LineCache,
 ) -> Result<()> {
+    let theme = &state.theme;
+    let chunk = pane.area;
     // this is the absolute max of messages we can render
     //  we need to update this later because of line wraps
-    let height = (chunk.height - 2) as usize;
-    let width = (chunk.width - 2) as usize;
+    let height = chunk.height.saturating_sub(2) as usize;
+    let width = chunk.width.saturating_sub(2) as usize;
 
     // Compute first range
     let viewport = window.compute_viewport(height, state.messages.len());
@@ -95,7 +567,15 @@ fn render_chat<B: Backend>(
 
     // items.len() can be bigger than the selected range,
     // because line wraps return multiple lines
-    let mut items: Vec<ListItem> = get_chat_items(viewport, width, &state.messages, &emote_list);
+    let mut items: Vec<ListItem> = get_chat_items(
+        viewport,
+        width,
+        &state.messages,
+        emote_list,
+        search,
+        &state.theme,
+        cache,
+    );
 
     // update after linewraps
     if state.messages.len() > height && items.len() > range_len {
@@ -103,12 +583,11 @@ fn render_chat<B: Backend>(
         items.drain(0..diff);
     }
 
-    let chat_messages = List::new(items).block(
-        Block::default()
-            .style(Style::default().bg(Color::Black))
-            .borders(Borders::ALL)
-            .title("DGG-Chat"),
-    );
+    let title = match state.hidden() {
+        0 => "DGG-Chat".to_string(),
+        hidden => format!("DGG-Chat ({} hidden)", hidden),
+    };
+    let chat_messages = List::new(items).block(pane_block(title, pane, theme));
     f.render_widget(chat_messages, chunk);
 
     Ok(())
@@ -116,34 +595,32 @@ fn render_chat<B: Backend>(
 
 fn render_debug<B: Backend>(
     f: &mut Frame<B>,
-    chunk: Rect,
+    pane: &PaneArea,
     state: &State,
     windows: &mut WindowList,
 ) {
-    let height = (chunk.height - 2) as usize;
-    let width = (chunk.width - 2) as usize;
+    let theme = &state.theme;
+    let chunk = pane.area;
+    let height = chunk.height.saturating_sub(2) as usize;
+    let width = chunk.width.saturating_sub(2) as usize;
     let viewport = windows
         .get_mut(WindowType::Debug)
         .compute_viewport(height, state.debugs.len());
     let range_len = viewport.end - viewport.start;
 
-    let mut items: Vec<ListItem> = state.debugs[viewport]
-        .iter()
-        .map(|msg| {
-            let lines = textwrap::wrap(&msg, width);
-            let line = Spans::from(Span::styled(msg, Style::default().fg(Color::White)));
+    let mut items: Vec<ListItem> = state
+        .debugs
+        .range(viewport)
+        .flat_map(|msg| {
+            let lines = textwrap::wrap(msg, width);
+            let line = Spans::from(Span::styled(msg, theme.window));
 
             if lines.len() > 1 {
                 let mut spans = Vec::with_capacity(lines.len());
                 let mut extra_lines: Vec<ListItem> = lines
                     .iter()
                     .skip(1)
-                    .map(|l| {
-                        ListItem::new(Span::styled(
-                            format!("{}", l),
-                            Style::default().fg(Color::White),
-                        ))
-                    })
+                    .map(|l| ListItem::new(Span::styled(format!("{}", l), theme.window)))
                     .collect();
 
                 spans.push(ListItem::new(line));
@@ -153,7 +630,6 @@ fn render_debug<B: Backend>(
                 vec![ListItem::new(line)]
             }
         })
-        .flatten()
         .collect();
 
     // update after linewraps
@@ -162,82 +638,137 @@ fn render_debug<B: Backend>(
         items.drain(0..diff);
     }
 
-    let debug_messages = List::new(items).block(
-        Block::default()
-            .style(Style::default().bg(Color::Black))
-            .borders(Borders::ALL)
-            .title("Debug"),
-    );
+    let debug_messages = List::new(items).block(pane_block("Debug", pane, theme));
     f.render_widget(debug_messages, chunk);
 }
 
-fn render_users<B: Backend>(f: &mut Frame<B>, chunk: Rect, state: &State) {
+// The mentions of every tab with where they came from, the selected one is the one the chat jumped to
+fn render_mentions<B: Backend>(
+    f: &mut Frame<B>,
+    pane: &PaneArea,
+    state: &State,
+    windows: &mut WindowList,
+) {
+    let theme = &state.theme;
+    let mentions = &state.mentions;
+    let height = pane.area.height.saturating_sub(2) as usize;
+    let viewport = windows
+        .get_mut(WindowType::Mentions)
+        .compute_viewport(height, mentions.len());
+    let start = viewport.start;
+    let items: Vec<ListItem> = mentions
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(viewport.len())
+        .map(|(index, mention)| {
+            let kind = match mention.kind {
+                MentionKind::Mention => "",
+                MentionKind::Highlight => " (highlight)",
+                MentionKind::Whisper => " (whisper)",
+            };
+            let item = ListItem::new(Spans::from(vec![
+                Span::styled(
+                    format!("{} {} ", format_time(mention.timestamp), mention.channel),
+                    theme.hint,
+                ),
+                Span::styled(
+                    mention.name.to_string(),
+                    Style::default()
+                        .fg(theme.nick_color(&mention.flairs))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(kind, theme.hint),
+                Span::styled(format!(": {}", mention.message), theme.message),
+            ]));
+            match mentions.selected() == Some(index) {
+                true => item.style(theme.selected),
+                false => item,
+            }
+        })
+        .collect();
+
+    let list = List::new(items).block(pane_block(
+        format!("Mentions ({})", mentions.len()),
+        pane,
+        theme,
+    ));
+    f.render_widget(list, pane.area);
+}
+
+// Whispers, embeds and the log list their messages one per line with the time
+fn render_messages<B: Backend>(
+    f: &mut Frame<B>,
+    pane: &PaneArea,
+    state: &State,
+    windows: &mut WindowList,
+) {
+    let theme = &state.theme;
+    let messages: Vec<&ChatMessage> = match pane.window_type {
+        WindowType::Whispers => state.whispers.iter().collect(),
+        WindowType::Embeds => state
+            .messages
+            .iter()
+            .filter(|msg| &*msg.name == "EMBED")
+            .collect(),
+        _ => state
+            .messages
+            .iter()
+            .filter(|msg| CLIENT_NAMES.contains(&&*msg.name))
+            .collect(),
+    };
+    let title = match pane.window_type {
+        WindowType::Whispers => "Whispers",
+        WindowType::Embeds => "Embeds",
+        _ => "Log",
+    };
+
+    let height = pane.area.height.saturating_sub(2) as usize;
+    let viewport = windows
+        .get_mut(pane.window_type)
+        .compute_viewport(height, messages.len());
+    let items: Vec<ListItem> = messages[viewport]
+        .iter()
+        .map(|msg| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("{} ", format_time(msg.timestamp)), theme.hint),
+                Span::styled(
+                    msg.name.to_string(),
+                    Style::default()
+                        .fg(theme.nick_color(&msg.flairs))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(format!(": {}", msg.message), theme.message),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items).block(pane_block(
+        format!("{} ({})", title, messages.len()),
+        pane,
+        theme,
+    ));
+    f.render_widget(list, pane.area);
+}
+
+fn render_users<B: Backend>(f: &mut Frame<B>, pane: &PaneArea, state: &State) {
+    let theme = &state.theme;
+    let chunk = pane.area;
     let (_height, start) = get_height_and_start(chunk, state.ul.users.len());
     let items: Vec<ListItem> = state.ul.users[start..]
         .iter()
-        .map(|user| user.to_list_item())
+        .map(|user| user.to_list_item(theme))
         .collect();
 
-    let chatter_names = List::new(items).block(
-        Block::default()
-            .style(Style::default().bg(Color::Black))
-            .borders(Borders::ALL)
-            .title(format!("{} Users", state.ul.users.len())),
-    );
+    let chatter_names = List::new(items).block(pane_block(
+        format!("{} Users", state.ul.users.len()),
+        pane,
+        theme,
+    ));
 
     f.render_widget(chatter_names, chunk);
 }
 
-// Deals with splitting the chunks into the right size for the different windows
-fn get_chunks(size: &Rect, windows: &WindowList) -> Vec<Rect> {
-    fn get_chat_chunks(chunk: Rect) -> (Rect, Rect) {
-        let windows = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Min(3), Constraint::Length(3)])
-            .split(chunk);
-        (windows[0], windows[1])
-    }
-    fn get_userlist_chunks(chunk: Rect) -> (Rect, Rect) {
-        let windows = Layout::default()
-            .constraints([Constraint::Percentage(80), Constraint::Percentage(20)])
-            .direction(Direction::Horizontal)
-            .split(chunk);
-        (windows[0], windows[1])
-    }
-    fn get_debug_chunks(chunk: Rect) -> (Rect, Rect) {
-        let windows = Layout::default()
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-            .direction(Direction::Vertical)
-            .split(chunk);
-        (windows[0], windows[1])
-    }
-
-    let area = *size;
-    let debug_active = windows.get(WindowType::Debug).active;
-    let userlist_active = windows.get(WindowType::UserList).active;
-
-    // chat, userlist and debug
-    if debug_active == true && userlist_active == true {
-        let (debug, rest_window) = get_debug_chunks(area);
-        let (rest_window, user_list) = get_userlist_chunks(rest_window);
-        let (chat, chat_input) = get_chat_chunks(rest_window);
-        return vec![chat, chat_input, debug, user_list];
-    // only chat and debug
-    } else if debug_active {
-        let (debug, rest_window) = get_debug_chunks(area);
-        let (chat, chat_input) = get_chat_chunks(rest_window);
-        return vec![chat, chat_input, debug];
-    // only chat and userlist
-    } else if userlist_active {
-        let (rest_window, user_list) = get_userlist_chunks(area);
-        let (chat, chat_input) = get_chat_chunks(rest_window);
-        return vec![chat, chat_input, user_list];
-    }
-    // only chat
-    let (chat, chat_input) = get_chat_chunks(area);
-    return vec![chat, chat_input];
-}
-
 fn get_height_and_start(chunk: Rect, list_len: usize) -> (usize, usize) {
     let height = (chunk.height) as usize;
     let start = if list_len > height + 2 {
@@ -253,158 +784,400 @@ fn get_height_and_start(chunk: Rect, list_len: usize) -> (usize, usize) {
 fn get_chat_items<'a>(
     range: Range<usize>,
     width: usize,
-    messages: &Vec<ChatMessage>,
+    messages: &MessageStore,
     emote_list: &EmoteList,
+    search: Option<&Search>,
+    theme: &Theme,
+    cache: &mut LineCache,
 ) -> Vec<ListItem<'a>> {
-    fn render_chat_line<'a>(
-        name: &str,
-        pm: &str,
-        bg_color: Color,
-        message_color: Color,
-        name_color: Color,
-        modifier: Modifier,
-    ) -> Spans<'a> {
-        // Each line has 3 components which can be styled differently
-        // [name ][: ] [rest of message]
-        Spans::from(vec![
-            Span::styled(
-                format!("{}", name),
-                Style::default()
-                    .fg(name_color)
-                    .bg(bg_color)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(
-                ": ",
-                Style::default()
-                    .bg(bg_color)
-                    .remove_modifier(Modifier::BOLD),
-            ),
-            Span::styled(
-                format!("{}", pm),
-                Style::default()
-                    .fg(message_color)
-                    .bg(bg_color)
-                    .add_modifier(modifier),
-            ),
-        ])
-    }
-
-    messages[range] // only render messages in view
-        .iter()
-        .map(|m| {
-            let name = &m.name;
-            let mut words: Vec<&str> = m.message.split_whitespace().collect();
+    let start = messages.evicted() + range.start;
+    cache.prepare(width, search, start..start + range.len());
 
-            // Replace Emote Strings in Message
-            let pm = parse_emotes(&mut words, emote_list);
+    messages
+        .range(range) // only render messages in view
+        .enumerate()
+        .flat_map(|(i, m)| {
+            // combos and collapsed lines grow in place, so their line isn't cached
+            if m.combo > 1 {
+                return combo_lines(m, width, emote_list, theme)
+                    .into_iter()
+                    .map(ListItem::new)
+                    .collect();
+            }
+            if m.collapsed > 0 {
+                return collapsed_lines(m, width, theme)
+                    .into_iter()
+                    .map(ListItem::new)
+                    .collect();
+            }
+            cache
+                .get_or_insert_with(start + i, || {
+                    message_lines(m, start + i, width, emote_list, search, theme)
+                })
+                .iter()
+                .cloned()
+                .map(ListItem::new)
+                .collect::<Vec<ListItem>>()
+        })
+        .collect()
+}
 
-            // Default styles
-            let mut message_color = Color::White;
-            let mut bg_color = Color::Black;
-            let mut modifier = Modifier::empty();
+// Styles and wraps a single message, index is its absolute index in the MessageStore
+fn message_lines(
+    m: &ChatMessage,
+    index: usize,
+    width: usize,
+    emote_list: &EmoteList,
+    search: Option<&Search>,
+    theme: &Theme,
+) -> Vec<Spans<'static>> {
+    let name = &m.name;
 
-            // Handle Name
-            let mut name_color = m.flair.to_color();
+    // Every kind of message patches its style on top, the last one wins
+    let mut message_style = theme.message;
+    let mut name_style = Style::default().fg(theme.nick_color(&m.flairs));
 
-            // Handle Greentext
-            if m.greentext {
-                message_color = Color::Green;
-            }
+    if m.greentext {
+        message_style = message_style.patch(theme.greentext);
+    }
+    if m.own_message {
+        message_style = message_style.patch(theme.own_message);
+    }
+    if m.mentioned {
+        message_style = message_style.patch(theme.mention);
+    }
+    if m.dimmed {
+        message_style = message_style.patch(theme.hint);
+        name_style = name_style.patch(theme.hint);
+    }
 
-            // Handle Name Hightlight own Message
-            if m.own_message {
-                bg_color = Color::Rgb(50, 50, 50);
-            }
+    // Messages of the client colour the name and the whole line
+    let client_style = if name.contains("ERROR") {
+        Some(theme.error_message)
+    } else if name.contains("STALK")
+        || name.contains("EMBED")
+        || name.contains("INFO")
+        || name.contains("LOG")
+        || name.contains("WHISPER")
+    {
+        Some(theme.info)
+    } else {
+        None
+    };
+    if let Some(client_style) = client_style {
+        name_style = name_style.patch(client_style);
+        if let Some(bg) = client_style.bg {
+            message_style = message_style.bg(bg);
+        }
+    }
 
-            // Handle Highlight other Message
-            if m.mentioned {
-                bg_color = Color::Rgb(10, 40, 60);
-            }
+    let bg_style = Style {
+        bg: message_style.bg,
+        ..Style::default()
+    };
 
-            // Handle nsfw messages
-            // TODO: only mark nsfw when a link is found
-            if m.nsfw {
-                bg_color = Color::Rgb(130, 100, 150);
-            }
+    // Mark the current search match
+    let mut name_modifier = Modifier::BOLD;
+    if let Some(search) = search {
+        if search.current == Some(index) {
+            name_modifier |= Modifier::REVERSED;
+        }
+    }
 
-            if m.nsfl {
-                bg_color = Color::Rgb(130, 100, 150);
-            }
+    // Each message starts with [badges] [name][:] followed by the parsed words
+    let mut words = Vec::new();
+    let badges = badge_spans(&m.flairs, theme, bg_style);
+    if !badges.is_empty() {
+        words.push(badges);
+    }
+    words.push(vec![
+        Span::styled(
+            name.to_string(),
+            bg_style.patch(name_style).add_modifier(name_modifier),
+        ),
+        Span::styled(":", bg_style),
+    ]);
+    words.extend(parse_words(&m.message, emote_list).iter().map(|word| {
+        word.iter()
+            .flat_map(|segment| segment_spans(segment, m, message_style, search, theme))
+            .collect()
+    }));
 
-            if name.contains("STALK") || name.contains("EMBED") {
-                name_color = Color::Rgb(250, 0, 140);
-                bg_color = Color::Rgb(50, 50, 50);
-            }
+    wrap_words(words, width, bg_style)
+}
 
-            if name.contains("ERROR") {
-                name_color = Color::LightRed;
-                bg_color = Color::Rgb(50, 50, 50);
-            }
+// A combo shows the emote and the count, e.g. "PEPE x 23 C-C-C-COMBO"
+fn combo_lines(
+    m: &ChatMessage,
+    width: usize,
+    emote_list: &EmoteList,
+    theme: &Theme,
+) -> Vec<Spans<'static>> {
+    let bg_style = Style {
+        bg: theme.message.bg,
+        ..Style::default()
+    };
+    let accent = bg_style.patch(theme.accent).add_modifier(Modifier::BOLD);
+    let mut words: Vec<Vec<Span>> = parse_words(&m.message, emote_list)
+        .iter()
+        .flat_map(|word| {
+            word.iter()
+                .map(|segment| part_to_spans(&segment.part, theme.message, theme))
+        })
+        .collect();
+    words.push(vec![Span::styled("x", theme.message)]);
+    words.push(vec![Span::styled(m.combo.to_string(), accent)]);
+    words.push(vec![Span::styled("C-C-C-COMBO", accent)]);
+    wrap_words(words, width, bg_style)
+}
+
+// Messages of a collapse filter in a row, e.g. "4 messages collapsed (collapse bots)"
+fn collapsed_lines(m: &ChatMessage, width: usize, theme: &Theme) -> Vec<Spans<'static>> {
+    let bg_style = Style {
+        bg: theme.message.bg,
+        ..Style::default()
+    };
+    let count = match m.collapsed {
+        1 => "1 message collapsed".to_string(),
+        count => format!("{} messages collapsed", count),
+    };
+    let text = format!("{} ({})", count, m.message);
+    let words = text
+        .split(' ')
+        .map(|word| vec![Span::styled(word.to_string(), bg_style.patch(theme.hint))])
+        .collect();
+    wrap_words(words, width, bg_style)
+}
 
-            // Handle Line Wraps
-            let full_line = format!("{}: {}", name, pm);
-            let lines = textwrap::wrap(&full_line, width);
+/// The badges of the flairs in their colours, e.g. "M·T3"
+pub fn badge_spans<'a>(flairs: &[Flair], theme: &Theme, style: Style) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    for flair in flairs {
+        let badge = match flair.badge() {
+            Some(badge) => badge,
+            None => continue,
+        };
+        if !spans.is_empty() {
+            spans.push(Span::styled("·", style.patch(theme.hint)));
+        }
+        spans.push(Span::styled(
+            badge,
+            style.fg(theme.nick_color(std::slice::from_ref(flair))),
+        ));
+    }
+    spans
+}
 
-            // Text wrapping of really long messages
-            // ": ".len() is 2, i think the wrapper strips the whitespace after the :
-            // to the next line when dealing with really long messages
-            let first_line_length;
-            if lines[0].len() - name.len() < 2 {
-                first_line_length = lines[0].len() - name.len() - 1;
-            } else {
-                first_line_length = lines[0].len() - name.len() - 2;
+// Masked spoilers keep their width, so the lines don't move when they get revealed
+fn segment_spans<'a>(
+    segment: &Segment,
+    m: &ChatMessage,
+    style: Style,
+    search: Option<&Search>,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    let style = match segment.format.code {
+        true => style.patch(theme.code),
+        false => style,
+    };
+    let spans = match (&segment.part, search) {
+        (Part::Word(word), _) if !m.link_tags.is_empty() && is_link(word) => {
+            tagged_link_spans(word, m, style, theme)
+        }
+        (Part::Word(word), Some(search)) if search.query.highlights(word) => {
+            part_to_spans(&segment.part, style.patch(theme.search_match), theme)
+        }
+        (part, _) => part_to_spans(part, style, theme),
+    };
+
+    if segment.format.spoiler && m.is_masked() {
+        let width = spans.iter().map(Span::width).sum();
+        return vec![Span::styled("▒".repeat(width), style.patch(theme.spoiler))];
+    }
+    spans
+}
+
+// Masked links only show their tags, e.g. "[nsfw loud link]"
+fn tagged_link_spans<'a>(
+    link: &str,
+    m: &ChatMessage,
+    style: Style,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    let style = match m.link_tags.contains(&LinkTag::Nsfl) {
+        true => style.patch(theme.nsfl),
+        false => style.patch(theme.nsfw),
+    };
+    let text = match m.is_masked() {
+        true => {
+            let tags: Vec<&str> = m.link_tags.iter().map(LinkTag::name).collect();
+            format!("[{} link]", tags.join(" "))
+        }
+        false => link.to_string(),
+    };
+    vec![Span::styled(text, style)]
+}
+
+// A word can consist of multiple spans, e.g. an emote with a modifier tag
+fn part_to_spans<'a>(part: &Part, style: Style, theme: &Theme) -> Vec<Span<'a>> {
+    match part {
+        Part::Word(word) => vec![Span::styled(word.to_string(), style)],
+        Part::Emote(modified) => {
+            let mut spans = vec![Span::styled(modified.emote.emote, style)];
+            if !modified.modifiers.is_empty() {
+                spans.push(Span::styled(
+                    format!("[{}]", modified.modifiers.join(":")),
+                    style.patch(theme.emote_modifier),
+                ));
             }
+            spans
+        }
+    }
+}
 
-            let line = render_chat_line(
-                &name,
-                &pm[..first_line_length],
-                bg_color,
-                message_color,
-                name_color,
-                modifier,
-            );
+// Puts the words into lines of at most width, words that are too long on their own get split
+fn wrap_words<'a>(words: Vec<Vec<Span<'a>>>, width: usize, space_style: Style) -> Vec<Spans<'a>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line: Vec<Span> = Vec::new();
+    let mut line_width = 0;
 
-            if lines.len() > 1 {
-                let mut spans = Vec::with_capacity(lines.len());
-                let mut extra_lines: Vec<ListItem> = lines
-                    .iter()
-                    .skip(1) // Skip the first line, it's already handled
-                    .map(|l| {
-                        ListItem::new(Span::styled(
-                            format!("{}", l),
-                            Style::default().fg(message_color).bg(bg_color),
-                        ))
-                    })
-                    .collect();
+    for mut word in words {
+        let word_width: usize = word.iter().map(|span| span.width()).sum();
 
-                spans.push(ListItem::new(line));
-                spans.append(&mut extra_lines);
-                spans
-            } else {
-                vec![ListItem::new(line)]
+        if line_width > 0 && line_width + 1 + word_width > width {
+            lines.push(Spans::from(std::mem::take(&mut line)));
+            line_width = 0;
+        }
+
+        if word_width > width && word.len() == 1 {
+            if line_width > 0 {
+                lines.push(Spans::from(std::mem::take(&mut line)));
             }
-        })
-        .flatten()
-        .collect()
+            let span = word.pop().unwrap();
+            let mut chunks: Vec<String> = textwrap::wrap(&span.content, width)
+                .into_iter()
+                .map(|chunk| chunk.to_string())
+                .collect();
+            let last = chunks.pop().unwrap_or_default();
+            chunks.into_iter().for_each(|chunk| {
+                lines.push(Spans::from(Span::styled(chunk, span.style)));
+            });
+            line_width = textwrap::core::display_width(&last);
+            line = vec![Span::styled(last, span.style)];
+            continue;
+        }
+
+        if line_width > 0 {
+            line.push(Span::styled(" ", space_style));
+            line_width += 1;
+        }
+        line.append(&mut word);
+        line_width += word_width;
+    }
+
+    if !line.is_empty() {
+        lines.push(Spans::from(line));
+    }
+    lines
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::mpsc::channel;
-
     use super::*;
 
     #[test]
     fn really_long_message_no_whitespace() {
-        let (sender, _) = channel();
-        let state = State::new("onlyclose".to_string(), sender);
+        let state = State::new("onlyclose".to_string());
         let emote_list = EmoteList::new();
-        let messages = vec![ChatMessage::from_string(
+        let mut messages = MessageStore::default();
+        messages.push(ChatMessage::from_string(
             state.username.to_string(),
             "x".repeat(100),
-        )];
-        let _ = get_chat_items(0..1, 20, &messages, &emote_list);
-        // println!("{:#?}", _);
+        ));
+        let items = get_chat_items(
+            0..1,
+            20,
+            &messages,
+            &emote_list,
+            None,
+            &Theme::default(),
+            &mut LineCache::new(),
+        );
+        assert_eq!(items.len(), 6);
+    }
+
+    #[test]
+    fn wrap_words_keeps_words_together() {
+        let words: Vec<Vec<Span>> = "aaa bbb ccc"
+            .split_whitespace()
+            .map(|w| vec![Span::raw(w)])
+            .collect();
+        let lines = wrap_words(words, 7, Style::default());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].width(), 7);
+        assert_eq!(lines[1].width(), 3);
+    }
+
+    fn text(lines: &[Spans]) -> String {
+        lines
+            .iter()
+            .flat_map(|line| line.0.iter().map(|span| span.content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn spoilers_and_code() {
+        let emote_list = EmoteList::new();
+        let theme = Theme::default();
+        let mut msg = ChatMessage::from_string("bob".to_string(), "a ||bc||! `d`".to_string());
+        msg.parse("alice", &[], &Default::default());
+
+        let lines = message_lines(&msg, 0, 80, &emote_list, None, &theme);
+        assert_eq!(text(&lines), "bob: a ▒▒! d");
+        let code = lines[0].0.last().unwrap();
+        assert_eq!(code.style, theme.message.patch(theme.code));
+
+        msg.revealed = true;
+        let lines = message_lines(&msg, 0, 80, &emote_list, None, &theme);
+        assert_eq!(text(&lines), "bob: a bc! d");
+    }
+
+    #[test]
+    fn combo_line() {
+        let emote_list = EmoteList::new();
+        let mut msg = ChatMessage::from_string("bob".to_string(), "PEPE".to_string());
+        msg.combo = 23;
+        let lines = combo_lines(&msg, 80, &emote_list, &Theme::default());
+        assert_eq!(text(&lines), "🐸 x 23 C-C-C-COMBO");
+    }
+
+    #[test]
+    fn tab_titles() {
+        let theme = Theme::default();
+        let text = |spans: Spans| -> String { spans.0.iter().map(|span| &*span.content).collect() };
+        assert_eq!(text(tab_title("dgg", 0, 0, &theme)), "dgg");
+        assert_eq!(text(tab_title("local", 12, 0, &theme)), "local 12");
+        let title = tab_title("local", 3, 1, &theme);
+        assert_eq!(title.0[2].style, theme.mention);
+        assert_eq!(text(title), "local 3 @1");
+    }
+
+    #[test]
+    fn collapsed_line() {
+        let mut msg = ChatMessage::from_string("FILTER".to_string(), "collapse bots".to_string());
+        msg.collapsed = 4;
+        let lines = collapsed_lines(&msg, 80, &Theme::default());
+        assert_eq!(text(&lines), "4 messages collapsed (collapse bots)");
+    }
+
+    #[test]
+    fn emote_modifier_is_tagged() {
+        let emote_list = EmoteList::new();
+        let words = parse_words("PEPE:wide", &emote_list);
+        let spans = part_to_spans(&words[0][0].part, Style::default(), &Theme::default());
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].content, "🐸");
+        assert_eq!(spans[1].content, "[wide]");
     }
 }
@@ -0,0 +1,128 @@
+use crate::chat::{chat_log::LogEntry, search::SearchQuery, store::MessageStore};
+
+/// The state of a search over the chat.
+/// Matches are absolute indices of the MessageStore, so they stay valid when old messages get dropped.
+pub struct Search {
+    pub input: String,
+    pub query: SearchQuery,
+    pub typing: bool,
+    pub current: Option<usize>,
+    /// Matches in the chat log that are older than the chat, None until they were found
+    pub log_matches: Option<Vec<LogEntry>>,
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search {
+            input: String::new(),
+            query: SearchQuery::default(),
+            typing: true,
+            current: None,
+            log_matches: None,
+        }
+    }
+
+    /// A search that's done typing, e.g. from the /search command.
+    pub fn from_query(input: String) -> Search {
+        let mut search = Search::new();
+        search.input = input;
+        search.confirm();
+        search
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.query = SearchQuery::parse(&self.input);
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.query = SearchQuery::parse(&self.input);
+    }
+
+    pub fn confirm(&mut self) {
+        self.typing = false;
+        self.query = SearchQuery::parse(&self.input);
+        self.log_matches = None;
+    }
+
+    pub fn matches(&self, messages: &MessageStore) -> Vec<usize> {
+        messages
+            .indexed()
+            .filter(|(_, msg)| self.query.matches(msg))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Jumps to the next older match, starting at the newest one.
+    pub fn older(&mut self, messages: &MessageStore) -> Option<usize> {
+        let matches = self.matches(messages);
+        let next = match self.current {
+            Some(current) => matches.into_iter().rev().find(|i| *i < current),
+            None => matches.last().copied(),
+        };
+        self.jump(next)
+    }
+
+    /// Jumps to the next newer match.
+    pub fn newer(&mut self, messages: &MessageStore) -> Option<usize> {
+        let matches = self.matches(messages);
+        let next = match self.current {
+            Some(current) => matches.into_iter().find(|i| *i > current),
+            None => matches.last().copied(),
+        };
+        self.jump(next)
+    }
+
+    // Stays on the current match if there is no other one
+    fn jump(&mut self, next: Option<usize>) -> Option<usize> {
+        if next.is_some() {
+            self.current = next;
+        }
+        self.current
+    }
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::message::ChatMessage;
+
+    use super::*;
+
+    // 10 dropped messages followed by the ones that can be found
+    fn messages() -> MessageStore {
+        let mut store = MessageStore::new(4);
+        ["old"; 10]
+            .iter()
+            .chain(["a match", "nothing", "b match", "c match"].iter())
+            .for_each(|m| store.push(ChatMessage::from_string("bob".to_string(), m.to_string())));
+        store
+    }
+
+    #[test]
+    fn navigate_matches() {
+        let messages = messages();
+        let mut search = Search::from_query("match".to_string());
+        assert_eq!(search.matches(&messages), vec![10, 12, 13]);
+        assert_eq!(search.older(&messages), Some(13));
+        assert_eq!(search.older(&messages), Some(12));
+        assert_eq!(search.older(&messages), Some(10));
+        // there is nothing older, stay on the oldest match
+        assert_eq!(search.older(&messages), Some(10));
+        assert_eq!(search.newer(&messages), Some(12));
+    }
+
+    #[test]
+    fn no_matches() {
+        let messages = messages();
+        let mut search = Search::from_query("nope".to_string());
+        assert_eq!(search.older(&messages), None);
+        assert_eq!(search.newer(&messages), None);
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    config::{Config, LogFormat, TaggedLinks},
+    ui::{color::ColorDepth, keymap::Preset, theme::THEME_NAMES},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingKind {
+    Bool,
+    /// Cycles through the values
+    Choice(&'static [&'static str]),
+    Number,
+    Text,
+}
+
+pub struct Setting {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<()>,
+}
+
+// Everything the settings screen can edit, new settings only need an entry here
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "scrollback.messages",
+        kind: SettingKind::Number,
+        get: |config| config.scrollback.messages.to_string(),
+        set: |config, value| {
+            config.scrollback.messages = parse_number(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "scrollback.debugs",
+        kind: SettingKind::Number,
+        get: |config| config.scrollback.debugs.to_string(),
+        set: |config, value| {
+            config.scrollback.debugs = parse_number(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "logging.enabled",
+        kind: SettingKind::Bool,
+        get: |config| config.logging.enabled.to_string(),
+        set: |config, value| {
+            config.logging.enabled = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "logging.format",
+        kind: SettingKind::Choice(&["text", "json"]),
+        get: |config| match config.logging.format {
+            LogFormat::Text => "text".to_string(),
+            LogFormat::Json => "json".to_string(),
+        },
+        set: |config, value| {
+            config.logging.format = match value {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => bail!("{} is neither text nor json", value),
+            };
+            Ok(())
+        },
+    },
+    Setting {
+        key: "theme.name",
+        kind: SettingKind::Choice(THEME_NAMES),
+        get: |config| config.theme.name.to_string(),
+        set: |config, value| {
+            config.theme.name = value.to_string();
+            Ok(())
+        },
+    },
+    Setting {
+        key: "theme.color_depth",
+        kind: SettingKind::Choice(ColorDepth::NAMES),
+        get: |config| config.theme.color_depth.name().to_string(),
+        set: |config, value| {
+            config.theme.color_depth = match ColorDepth::parse(value) {
+                Some(depth) => depth,
+                None => bail!("{} is not one of {}", value, ColorDepth::NAMES.join(", ")),
+            };
+            Ok(())
+        },
+    },
+    Setting {
+        key: "notifications.bell",
+        kind: SettingKind::Bool,
+        get: |config| config.notifications.bell.to_string(),
+        set: |config, value| {
+            config.notifications.bell = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "chat.combos",
+        kind: SettingKind::Bool,
+        get: |config| config.chat.combos.to_string(),
+        set: |config, value| {
+            config.chat.combos = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "links.tagged",
+        kind: SettingKind::Choice(TaggedLinks::NAMES),
+        get: |config| config.links.tagged.name().to_string(),
+        set: |config, value| {
+            config.links.tagged = TaggedLinks::parse(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "keys.preset",
+        kind: SettingKind::Choice(Preset::NAMES),
+        get: |config| config.keys.preset.name().to_string(),
+        set: |config, value| {
+            config.keys.preset = Preset::parse(value)?;
+            Ok(())
+        },
+    },
+    Setting {
+        key: "default_profile",
+        kind: SettingKind::Text,
+        get: |config| config.default_profile.to_string(),
+        set: |config, value| {
+            config.default_profile = value.to_string();
+            Ok(())
+        },
+    },
+];
+
+fn parse_number(value: &str) -> Result<usize> {
+    match value.trim().parse::<usize>() {
+        Ok(num) => Ok(num),
+        Err(_) => bail!("{} is not a number", value),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("{} is neither true nor false", value),
+    }
+}
+
+/// The settings screen, it edits a copy of the config until it is saved.
+pub struct Settings {
+    pub config: Config,
+    pub selected: usize,
+    /// The new value while a number or text is edited
+    pub editing: Option<String>,
+    /// Why the last change was refused
+    pub error: Option<String>,
+}
+
+impl Settings {
+    pub fn new(config: Config) -> Settings {
+        Settings {
+            config,
+            selected: 0,
+            editing: None,
+            error: None,
+        }
+    }
+
+    /// Every key with its current value.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        SETTINGS
+            .iter()
+            .map(|setting| (setting.key, (setting.get)(&self.config)))
+            .collect()
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(SETTINGS.len() - 1);
+    }
+
+    /// Toggles bools, cycles choices and starts editing everything else.
+    pub fn activate(&mut self) {
+        let setting = &SETTINGS[self.selected];
+        let current = (setting.get)(&self.config);
+        match setting.kind {
+            SettingKind::Bool => {
+                let toggled = (current != "true").to_string();
+                self.set(&toggled);
+            }
+            SettingKind::Choice(values) => {
+                let index = values.iter().position(|value| *value == current);
+                let next = values[index.map_or(0, |index| (index + 1) % values.len())];
+                self.set(next);
+            }
+            SettingKind::Number | SettingKind::Text => self.editing = Some(current),
+        }
+    }
+
+    /// Applies the edited value, it stays in the editor if it is invalid.
+    pub fn finish_edit(&mut self) {
+        if let Some(value) = self.editing.take() {
+            if !self.set(&value) {
+                self.editing = Some(value);
+            }
+        }
+    }
+
+    // The change is only kept if the whole config is still valid
+    fn set(&mut self, value: &str) -> bool {
+        let setting = &SETTINGS[self.selected];
+        let mut config = self.config.clone();
+        // validate names the key itself
+        let result = (setting.set)(&mut config, value)
+            .map_err(|err| anyhow!("{}: {}", setting.key, err))
+            .and_then(|_| config.validate());
+        match result {
+            Ok(()) => {
+                self.config = config;
+                self.error = None;
+                true
+            }
+            Err(err) => {
+                self.error = Some(err.to_string());
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(settings: &mut Settings, key: &str) {
+        settings.selected = SETTINGS
+            .iter()
+            .position(|setting| setting.key == key)
+            .unwrap();
+    }
+
+    #[test]
+    fn toggle_and_cycle() {
+        let mut settings = Settings::new(Config::default());
+        select(&mut settings, "notifications.bell");
+        settings.activate();
+        assert!(settings.config.notifications.bell);
+
+        select(&mut settings, "logging.format");
+        settings.activate();
+        assert_eq!(settings.config.logging.format, LogFormat::Json);
+        settings.activate();
+        assert_eq!(settings.config.logging.format, LogFormat::Text);
+    }
+
+    #[test]
+    fn invalid_values_are_refused() {
+        let mut settings = Settings::new(Config::default());
+        select(&mut settings, "scrollback.messages");
+        settings.activate();
+        assert_eq!(settings.editing.as_deref(), Some("200"));
+
+        settings.editing = Some("0".to_string());
+        settings.finish_edit();
+        assert_eq!(settings.config.scrollback.messages, 200);
+        assert!(settings
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("scrollback.messages: "));
+        assert!(settings.editing.is_some());
+
+        settings.editing = Some("500".to_string());
+        settings.finish_edit();
+        assert_eq!(settings.config.scrollback.messages, 500);
+        assert_eq!(settings.error, None);
+    }
+
+    #[test]
+    fn every_setting_reads_back() {
+        let config = Config::default();
+        for setting in SETTINGS {
+            let mut changed = config.clone();
+            (setting.set)(&mut changed, &(setting.get)(&config)).unwrap();
+            assert_eq!((setting.get)(&changed), (setting.get)(&config));
+        }
+    }
+}
@@ -1,16 +1,30 @@
-use std::fmt::Display;
+use std::collections::HashMap;
 
-use crate::chat::{command::Command, user::UserList};
+use crate::chat::{
+    command::{ArgKind, Command},
+    store::MessageStore,
+    user::UserList,
+};
 
-use super::emotes::EmoteList;
+use super::{
+    chat_input::ChatInput,
+    emotes::{EmoteList, MODIFIERS},
+    parser::parse_emote,
+};
+
+const MAX_SUGGESTIONS: usize = 10;
+// How many of the newest chat messages count as recent activity
+const RECENT_MESSAGES: usize = 50;
 
-// TODO: Should prob have a &ChatInput
-// then we can order suggestions after usage
 pub struct Suggestor<'a> {
     pub suggestions: Vec<String>,
     pub emote_list: &'a EmoteList,
     pub current_word: String,
-    pub index: usize,
+    pub selected: Option<usize>,
+    /// Also suggests the mod only commands
+    pub moderator: bool,
+    /// The word started with @, which the completion keeps
+    at_prefix: bool,
 }
 
 impl<'a> Suggestor<'a> {
@@ -21,69 +35,364 @@ impl<'a> Suggestor<'a> {
             suggestions,
             emote_list,
             current_word: "".to_string(),
-            index: 0,
+            selected: None,
+            moderator: false,
+            at_prefix: false,
         }
     }
 
-    pub fn get(&mut self) -> String {
-        if let Some(suggestion) = self.suggestions.get(self.index) {
-            self.index += 1;
-            format!("{} ", suggestion)
-        } else {
-            self.index = 0;
-            let suggestion = self.suggestions.get(self.index).unwrap();
-            self.index += 1;
-            format!("{} ", suggestion)
-        }
+    pub fn clear(&mut self) {
+        self.suggestions.clear();
+        self.selected = None;
     }
 
-    pub fn update(&mut self, user_list: &UserList, current_word: String) {
-        if current_word.len() == 0 {
+    /// Selects the next suggestion (Tab) and returns it with a trailing whitespace.
+    pub fn select_next(&mut self) -> String {
+        let index = match self.selected {
+            Some(index) => (index + 1) % self.suggestions.len(),
+            None => 0,
+        };
+        self.select(index)
+    }
+
+    /// Selects the previous suggestion (Shift-Tab) and returns it with a trailing whitespace.
+    pub fn select_prev(&mut self) -> String {
+        let index = match self.selected {
+            Some(0) | None => self.suggestions.len() - 1,
+            Some(index) => index - 1,
+        };
+        self.select(index)
+    }
+
+    fn select(&mut self, index: usize) -> String {
+        self.selected = Some(index);
+        let at = if self.at_prefix { "@" } else { "" };
+        format!("{}{} ", at, self.suggestions[index])
+    }
+
+    pub fn update(
+        &mut self,
+        user_list: &UserList,
+        chat_input: &ChatInput,
+        messages: &MessageStore,
+    ) {
+        let current_word = chat_input.get_current_word();
+        self.selected = None;
+        self.at_prefix = false;
+
+        if current_word.trim().is_empty() {
             self.suggestions.clear();
-        } else {
-            self.current_word = current_word.to_lowercase();
+            return;
+        }
 
-            let mut emote_suggestions: Vec<String> = self
-                .emote_list
-                .emotes
-                .iter()
-                .filter(|emote| emote.name.to_lowercase().starts_with(&self.current_word))
-                .take(5)
-                .map(|emote| emote.name.to_string())
-                .collect();
+        if let Some(modifier_suggestions) = self.suggest_modifiers(&current_word) {
+            self.suggestions = modifier_suggestions;
+            return;
+        }
 
-            let mut username_suggestions: Vec<String> = user_list
-                .users
-                .iter()
-                .filter(|user| user.name.to_lowercase().starts_with(&self.current_word))
-                .take(5)
-                .map(|user| user.name.to_string())
-                .collect();
-
-            // suggest commands
-            if &self.current_word[..1] == "/" {
-                let mut command_suggestions: Vec<String> = Command::vec()
+        self.current_word = current_word.to_lowercase();
+
+        // @ only completes nicks
+        let (query, mut nicks_only) = match current_word.strip_prefix('@') {
+            Some(query) => (query, true),
+            None => (current_word.as_str(), false),
+        };
+        self.at_prefix = nicks_only;
+
+        // complete commands and their arguments
+        if chat_input.current_message.starts_with('/') {
+            let words: Vec<&str> = chat_input.current_message.split_whitespace().collect();
+            if words.len() == 1 {
+                self.suggestions = Command::vec(self.moderator)
                     .into_iter()
-                    .filter(|cmd| cmd.starts_with(&self.current_word.to_lowercase()))
+                    .filter(|cmd| cmd.starts_with(&self.current_word))
                     .collect();
-                emote_suggestions.append(&mut command_suggestions)
+                return;
             }
 
-            // emotes get recommended before names
-            emote_suggestions.append(&mut username_suggestions);
+            // the current word is never empty here, so the last word is the typed argument
+            match Command::find(words[0]).and_then(|cmd| cmd.arg_kind(words.len() - 2)) {
+                Some(ArgKind::Nick) => nicks_only = true,
+                Some(ArgKind::Command) => {
+                    self.suggestions = Command::available(self.moderator)
+                        .map(|cmd| cmd.name.to_string())
+                        .filter(|name| name.starts_with(&self.current_word))
+                        .collect();
+                    return;
+                }
+                Some(ArgKind::Text) => (),
+                Some(ArgKind::Number) | None => {
+                    self.suggestions.clear();
+                    return;
+                }
+            }
+        }
 
-            self.suggestions = emote_suggestions;
-            self.index = 0;
+        let usage = count_words(chat_input.history.iter().map(|msg| msg.as_str()));
+        let activity = count_words(
+            messages
+                .iter()
+                .rev()
+                .take(RECENT_MESSAGES)
+                .flat_map(|msg| [&*msg.name, msg.message.as_str()]),
+        );
+
+        let emotes = self
+            .emote_list
+            .emotes
+            .iter()
+            .filter(|_| !nicks_only)
+            .map(|emote| emote.name);
+        let nicks = user_list.users.iter().map(|user| user.name.as_str());
+
+        let mut ranked: Vec<(i32, &str)> = emotes
+            .chain(nicks)
+            .filter_map(|candidate| {
+                let score = fuzzy_score(query, candidate)?;
+                let used = usage.get(candidate).copied().unwrap_or(0);
+                let seen = activity.get(candidate).copied().unwrap_or(0);
+                Some((score + weight(used, 4, 40) + weight(seen, 2, 20), candidate))
+            })
+            .collect();
+
+        ranked.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(a.1.len().cmp(&b.1.len()))
+                .then(a.1.cmp(b.1))
+        });
+        ranked.dedup_by(|a, b| a.1 == b.1);
+
+        self.suggestions = ranked
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate.to_string())
+            .collect();
+    }
+
+    // After an emote followed by a colon suggest its modifiers, e.g. PEPE:w => PEPE:wide
+    fn suggest_modifiers(&self, current_word: &str) -> Option<Vec<String>> {
+        let (emote, modifier) = current_word.rsplit_once(':')?;
+        parse_emote(emote, self.emote_list)?;
+
+        Some(
+            MODIFIERS
+                .iter()
+                .filter(|m| m.starts_with(modifier))
+                .map(|m| format!("{}:{}", emote, m))
+                .collect(),
+        )
+    }
+}
+
+/// Scores how well query matches candidate as a case insensitive subsequence.
+/// Returns None if the characters of query don't appear in order in candidate.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut last_match: Option<usize> = None;
+
+    for q in query.chars().flat_map(char::to_lowercase) {
+        let found = candidate_chars[position..]
+            .iter()
+            .position(|c| c.to_lowercase().eq(std::iter::once(q)))?;
+        let index = position + found;
+
+        score += 1;
+        // consecutive matches and matches at word starts are worth more
+        if last_match.map_or(index == 0, |last| index == last + 1) {
+            score += 5;
+        } else if candidate_chars[index].is_uppercase() {
+            score += 3;
         }
+        // skipped characters make the match worse
+        score -= found as i32;
+
+        last_match = Some(index);
+        position = index + 1;
+    }
+
+    if candidate.to_lowercase().starts_with(&query.to_lowercase()) {
+        score += 20;
+    }
+    if candidate.eq_ignore_ascii_case(query) {
+        score += 30;
     }
+
+    Some(score)
+}
+
+fn weight(count: usize, per_use: i32, max: i32) -> i32 {
+    (count as i32 * per_use).min(max)
+}
+
+// Counts words in texts, nick mentions and emote modifiers count towards the plain word
+fn count_words<'a>(texts: impl Iterator<Item = &'a str>) -> HashMap<&'a str, usize> {
+    let mut counts = HashMap::new();
+    texts
+        .flat_map(|text| text.split_whitespace())
+        .filter_map(|word| word.trim_start_matches('@').split(':').next())
+        .for_each(|word| *counts.entry(word).or_insert(0) += 1);
+    counts
 }
 
-impl Display for Suggestor<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.suggestions.len() > 0 {
-            write!(f, " {} ", self.suggestions.join(" | "))
-        } else {
-            write!(f, "─")
+#[cfg(test)]
+mod tests {
+    use crate::chat::{message::ChatMessage, user::User};
+
+    use super::*;
+
+    fn user_list(names: &[&str]) -> UserList {
+        let mut user_list = UserList::new();
+        names.iter().for_each(|name| {
+            user_list.add(User {
+                name: name.to_string(),
+                ..Default::default()
+            })
+        });
+        user_list
+    }
+
+    fn input(message: &str) -> ChatInput {
+        ChatInput {
+            current_message: message.to_string(),
+            ..Default::default()
         }
     }
+
+    #[test]
+    fn suggest_modifiers_after_emote() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(&UserList::new(), &input("PEPE:w"), &MessageStore::default());
+        assert_eq!(suggestor.suggestions, vec!["PEPE:wide"]);
+    }
+
+    #[test]
+    fn suggest_chained_modifiers() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(
+            &UserList::new(),
+            &input("PEPE:wide:"),
+            &MessageStore::default(),
+        );
+        assert_eq!(suggestor.suggestions.len(), MODIFIERS.len());
+        assert!(suggestor
+            .suggestions
+            .contains(&"PEPE:wide:flip".to_string()));
+    }
+
+    #[test]
+    fn no_modifiers_after_unknown_emote() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(
+            &UserList::new(),
+            &input("notanemote:"),
+            &MessageStore::default(),
+        );
+        assert!(suggestor.suggestions.is_empty());
+    }
+
+    #[test]
+    fn fuzzy_score_subsequence() {
+        assert!(fuzzy_score("gchd", "GIGACHAD").is_some());
+        assert!(fuzzy_score("xyz", "GIGACHAD").is_none());
+        assert!(fuzzy_score("giga", "GIGACHAD") > fuzzy_score("gchd", "GIGACHAD"));
+    }
+
+    #[test]
+    fn fuzzy_matches_are_suggested() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(&UserList::new(), &input("gchd"), &MessageStore::default());
+        assert_eq!(suggestor.suggestions[0], "GIGACHAD");
+    }
+
+    #[test]
+    fn usage_ranks_higher() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        let mut chat_input = input("mon");
+        chat_input
+            .history
+            .push_front("monkaSMEGA monkaSMEGA".to_string());
+        suggestor.update(&UserList::new(), &chat_input, &MessageStore::default());
+        assert_eq!(suggestor.suggestions[0], "monkaSMEGA");
+        assert!(suggestor.suggestions.contains(&"monkaS".to_string()));
+    }
+
+    #[test]
+    fn recent_chatters_rank_higher() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        let users = user_list(&["bobby", "bobbert"]);
+        let mut messages = MessageStore::default();
+        messages.push(ChatMessage::from_string(
+            "bobbert".to_string(),
+            "hi".to_string(),
+        ));
+        suggestor.update(&users, &input("bob"), &messages);
+        assert_eq!(suggestor.suggestions, vec!["bobbert", "bobby"]);
+    }
+
+    #[test]
+    fn at_only_suggests_nicks() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        let users = user_list(&["Pepega"]);
+        suggestor.update(&users, &input("@pep"), &MessageStore::default());
+        assert_eq!(suggestor.suggestions, vec!["Pepega"]);
+        assert_eq!(suggestor.select_next(), "@Pepega ");
+
+        suggestor.update(&users, &input("pepeg"), &MessageStore::default());
+        assert!(!suggestor.select_next().starts_with('@'));
+    }
+
+    #[test]
+    fn suggest_commands() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(&UserList::new(), &input("/st"), &MessageStore::default());
+        assert_eq!(suggestor.suggestions, vec!["/stalk"]);
+    }
+
+    #[test]
+    fn suggest_nicks_after_stalk() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        let users = user_list(&["Pepega"]);
+        suggestor.update(&users, &input("/stalk pe"), &MessageStore::default());
+        assert_eq!(suggestor.suggestions, vec!["Pepega"]);
+
+        // the second argument is a number
+        suggestor.update(&users, &input("/stalk Pepega 1"), &MessageStore::default());
+        assert!(suggestor.suggestions.is_empty());
+    }
+
+    #[test]
+    fn suggest_commands_after_help() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.update(
+            &UserList::new(),
+            &input("/help em"),
+            &MessageStore::default(),
+        );
+        assert_eq!(suggestor.suggestions, vec!["embeds"]);
+    }
+
+    #[test]
+    fn next_and_prev_cycle() {
+        let emote_list = EmoteList::new();
+        let mut suggestor = Suggestor::new(&emote_list);
+        suggestor.suggestions = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(suggestor.select_next(), "a ");
+        assert_eq!(suggestor.select_next(), "b ");
+        assert_eq!(suggestor.select_next(), "a ");
+        assert_eq!(suggestor.select_prev(), "b ");
+        suggestor.clear();
+        assert_eq!(suggestor.selected, None);
+    }
 }
@@ -0,0 +1,499 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use tui::style::{Color, Modifier, Style};
+
+use crate::{
+    chat::features::{resolve_alias, Flair, FlairInfo},
+    config::ThemeConfig,
+    ui::color::{parse_color, ColorDepth},
+};
+
+pub const THEME_NAMES: &[&str] = &["dark", "light", "high-contrast"];
+
+// Every style a theme file can change
+pub const STYLE_NAMES: &[&str] = &[
+    "window",
+    "selected",
+    "hint",
+    "accent",
+    "error",
+    "message",
+    "greentext",
+    "own_message",
+    "mention",
+    "nsfw",
+    "nsfl",
+    "spoiler",
+    "code",
+    "info",
+    "error_message",
+    "search_match",
+    "emote_modifier",
+    "nick",
+];
+
+/// Changes to one style, everything that isn't set keeps the value of the theme
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+}
+
+/// A theme file, TOML or JSON. The theme section of the config has the same keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeFile {
+    pub styles: BTreeMap<String, StyleConfig>,
+    /// Nick colours by flair, e.g. "tier1": "cyan"
+    pub flairs: BTreeMap<String, String>,
+}
+
+/// The styles of every part of the UI and of every kind of message.
+/// Message styles are patched on top of each other, e.g. a greentext that mentions you.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Text and background of the windows
+    pub window: Style,
+    /// The selected row of a list
+    pub selected: Style,
+    pub hint: Style,
+    /// Key names and the key status
+    pub accent: Style,
+    /// Errors outside of the chat, e.g. in the login screen
+    pub error: Style,
+    pub message: Style,
+    pub greentext: Style,
+    pub own_message: Style,
+    pub mention: Style,
+    pub nsfw: Style,
+    pub nsfl: Style,
+    /// Masked spoilers
+    pub spoiler: Style,
+    pub code: Style,
+    /// Client messages like /stalk results, the background covers the whole line
+    pub info: Style,
+    pub error_message: Style,
+    pub search_match: Style,
+    pub emote_modifier: Style,
+    /// Nicks without a flair colour
+    pub nick: Style,
+    /// Flair colours instead of the ones from the server, by server name
+    flairs: BTreeMap<String, Color>,
+    depth: ColorDepth,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    /// One of THEME_NAMES
+    pub fn builtin(name: &str) -> Result<Theme> {
+        match name {
+            "dark" => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            "high-contrast" => Ok(Theme::high_contrast()),
+            _ => bail!(
+                "theme.name: {} is not one of {}",
+                name,
+                THEME_NAMES.join(", ")
+            ),
+        }
+    }
+
+    /// The built-in theme with the styles of the config, without the theme file.
+    pub fn new(config: &ThemeConfig) -> Result<Theme> {
+        let mut theme = Theme::builtin(&config.name)?;
+        theme.apply(&config.styles, &config.flairs, "theme.")?;
+        Ok(theme)
+    }
+
+    /// The built-in theme, then the theme file, then the styles of the config.
+    /// A relative theme file is looked up next to the config file.
+    pub fn load(config: &ThemeConfig, config_dir: Option<&Path>) -> Result<Theme> {
+        let mut theme = Theme::builtin(&config.name)?;
+        if let Some(file) = &config.file {
+            let path = match config_dir {
+                Some(dir) => dir.join(file),
+                None => PathBuf::from(file),
+            };
+            let styles = read_theme_file(&path)
+                .map_err(|err| anyhow!("theme.file: {}: {}", path.display(), err))?;
+            let prefix = format!("{}: ", path.display());
+            theme.apply(&styles.styles, &styles.flairs, &prefix)?;
+        }
+        theme.apply(&config.styles, &config.flairs, "theme.")?;
+        Ok(theme.with_depth(config.color_depth.detect()))
+    }
+
+    /// The colour of a nick with this flair, the theme's or else the server's
+    pub fn flair(&self, flair: &FlairInfo) -> Option<Color> {
+        match self.flairs.get(&flair.name) {
+            Some(color) => Some(*color),
+            None => parse_color(&flair.color).map(|color| self.depth.convert(color)),
+        }
+    }
+
+    /// The colour of the most important flair that has one
+    pub fn nick_color(&self, flairs: &[Flair]) -> Color {
+        match flairs.iter().find_map(|flair| self.flair(flair)) {
+            Some(color) => color,
+            None => self.nick.fg.unwrap_or(Color::Reset),
+        }
+    }
+
+    fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+        let style = match name {
+            "window" => &mut self.window,
+            "selected" => &mut self.selected,
+            "hint" => &mut self.hint,
+            "accent" => &mut self.accent,
+            "error" => &mut self.error,
+            "message" => &mut self.message,
+            "greentext" => &mut self.greentext,
+            "own_message" => &mut self.own_message,
+            "mention" => &mut self.mention,
+            "nsfw" => &mut self.nsfw,
+            "nsfl" => &mut self.nsfl,
+            "spoiler" => &mut self.spoiler,
+            "code" => &mut self.code,
+            "info" => &mut self.info,
+            "error_message" => &mut self.error_message,
+            "search_match" => &mut self.search_match,
+            "emote_modifier" => &mut self.emote_modifier,
+            "nick" => &mut self.nick,
+            _ => return None,
+        };
+        Some(style)
+    }
+
+    // Errors start with prefix and the key, e.g. "theme.styles.mention.bg: "
+    fn apply(
+        &mut self,
+        styles: &BTreeMap<String, StyleConfig>,
+        flairs: &BTreeMap<String, String>,
+        prefix: &str,
+    ) -> Result<()> {
+        for (name, config) in styles {
+            let key = format!("{}styles.{}", prefix, name);
+            let style = match self.style_mut(name) {
+                Some(style) => style,
+                None => bail!("{}: is not one of {}", key, STYLE_NAMES.join(", ")),
+            };
+            if let Some(fg) = &config.fg {
+                *style = style.fg(color(fg, &key, "fg")?);
+            }
+            if let Some(bg) = &config.bg {
+                *style = style.bg(color(bg, &key, "bg")?);
+            }
+            for (set, modifier) in [
+                (config.bold, Modifier::BOLD),
+                (config.italic, Modifier::ITALIC),
+                (config.underlined, Modifier::UNDERLINED),
+            ] {
+                *style = match set {
+                    Some(true) => style.add_modifier(modifier),
+                    Some(false) => style.remove_modifier(modifier),
+                    None => *style,
+                };
+            }
+        }
+
+        for (name, value) in flairs {
+            let key = format!("{}flairs.{}", prefix, name);
+            // flairs the client doesn't know yet may come with the next flairs.json
+            let flair = resolve_alias(name).to_string();
+            self.flairs.insert(flair, color(value, &key, "")?);
+        }
+        Ok(())
+    }
+
+    /// Converts every colour to one the terminal can show.
+    pub fn with_depth(mut self, depth: ColorDepth) -> Theme {
+        let convert = |style: &mut Style| {
+            style.fg = style.fg.map(|color| depth.convert(color));
+            style.bg = style.bg.map(|color| depth.convert(color));
+        };
+        for name in STYLE_NAMES {
+            if let Some(style) = self.style_mut(name) {
+                convert(style);
+            }
+        }
+        for color in self.flairs.values_mut() {
+            *color = depth.convert(*color);
+        }
+        self.depth = depth;
+        self
+    }
+
+    pub fn dark() -> Theme {
+        Theme {
+            window: Style::default().fg(Color::White).bg(Color::Black),
+            selected: Style::default().fg(Color::Black).bg(Color::White),
+            hint: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::LightYellow),
+            error: Style::default().fg(Color::LightRed),
+            message: Style::default().fg(Color::White).bg(Color::Black),
+            greentext: Style::default().fg(Color::Green),
+            own_message: Style::default().bg(Color::Rgb(50, 50, 50)),
+            mention: Style::default().bg(Color::Rgb(10, 40, 60)),
+            nsfw: Style::default().bg(Color::Rgb(130, 100, 150)),
+            nsfl: Style::default().bg(Color::Rgb(130, 100, 150)),
+            spoiler: Style::default().fg(Color::DarkGray).bg(Color::DarkGray),
+            code: Style::default().fg(Color::Gray).bg(Color::Rgb(40, 40, 40)),
+            info: Style::default()
+                .fg(Color::Rgb(250, 0, 140))
+                .bg(Color::Rgb(50, 50, 50)),
+            error_message: Style::default()
+                .fg(Color::LightRed)
+                .bg(Color::Rgb(50, 50, 50)),
+            search_match: Style::default().fg(Color::Black).bg(Color::Yellow),
+            emote_modifier: Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+            nick: Style::default().fg(Color::White),
+            // the colours of flairs.json are made for a dark background
+            flairs: BTreeMap::new(),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            window: Style::default().fg(Color::Black).bg(Color::White),
+            selected: Style::default().fg(Color::White).bg(Color::Black),
+            hint: Style::default().fg(Color::Rgb(120, 120, 120)),
+            accent: Style::default().fg(Color::Blue),
+            error: Style::default().fg(Color::Red),
+            message: Style::default().fg(Color::Black).bg(Color::White),
+            greentext: Style::default().fg(Color::Rgb(0, 128, 0)),
+            own_message: Style::default().bg(Color::Rgb(230, 230, 230)),
+            mention: Style::default().bg(Color::Rgb(200, 225, 245)),
+            nsfw: Style::default().bg(Color::Rgb(230, 210, 240)),
+            nsfl: Style::default().bg(Color::Rgb(230, 210, 240)),
+            spoiler: Style::default()
+                .fg(Color::Rgb(120, 120, 120))
+                .bg(Color::Rgb(120, 120, 120)),
+            code: Style::default()
+                .fg(Color::Rgb(80, 80, 80))
+                .bg(Color::Rgb(235, 235, 235)),
+            info: Style::default()
+                .fg(Color::Rgb(200, 0, 110))
+                .bg(Color::Rgb(235, 235, 235)),
+            error_message: Style::default()
+                .fg(Color::Red)
+                .bg(Color::Rgb(235, 235, 235)),
+            search_match: Style::default().fg(Color::Black).bg(Color::LightYellow),
+            emote_modifier: Style::default()
+                .fg(Color::Rgb(120, 120, 120))
+                .add_modifier(Modifier::ITALIC),
+            nick: Style::default().fg(Color::Black),
+            flairs: BTreeMap::from([
+                ("flair13".to_string(), Color::Rgb(0, 140, 160)),
+                ("flair1".to_string(), Color::Rgb(0, 110, 190)),
+                ("flair3".to_string(), Color::Rgb(0, 140, 60)),
+                ("flair8".to_string(), Color::Rgb(160, 0, 160)),
+                ("vip".to_string(), Color::Rgb(200, 60, 20)),
+                ("flair17".to_string(), Color::Rgb(160, 120, 0)),
+                ("moderator".to_string(), Color::Rgb(160, 120, 0)),
+                ("flair12".to_string(), Color::Rgb(200, 110, 0)),
+                ("flair2".to_string(), Color::Rgb(200, 110, 0)),
+                ("admin".to_string(), Color::Red),
+            ]),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+
+    /// Only the 16 basic colours, so it looks the same everywhere
+    pub fn high_contrast() -> Theme {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Theme {
+            window: Style::default().fg(Color::White).bg(Color::Black),
+            selected: bold.fg(Color::Black).bg(Color::Yellow),
+            hint: Style::default().fg(Color::Gray),
+            accent: bold.fg(Color::LightYellow),
+            error: bold.fg(Color::LightRed),
+            message: Style::default().fg(Color::White).bg(Color::Black),
+            greentext: Style::default().fg(Color::LightGreen),
+            own_message: Style::default().fg(Color::White).bg(Color::DarkGray),
+            mention: bold.fg(Color::White).bg(Color::Blue),
+            nsfw: Style::default().fg(Color::White).bg(Color::Magenta),
+            nsfl: Style::default().fg(Color::White).bg(Color::Magenta),
+            spoiler: Style::default().fg(Color::Gray).bg(Color::Gray),
+            code: Style::default().fg(Color::LightCyan),
+            info: bold.fg(Color::LightMagenta),
+            error_message: bold.fg(Color::LightRed),
+            search_match: bold.fg(Color::Black).bg(Color::Yellow),
+            emote_modifier: Style::default()
+                .fg(Color::Gray)
+                .add_modifier(Modifier::ITALIC),
+            nick: Style::default().fg(Color::White),
+            flairs: BTreeMap::from([
+                ("flair13".to_string(), Color::LightCyan),
+                ("flair1".to_string(), Color::LightBlue),
+                ("flair3".to_string(), Color::LightGreen),
+                ("flair8".to_string(), Color::LightMagenta),
+                ("vip".to_string(), Color::LightRed),
+                ("flair17".to_string(), Color::Yellow),
+                ("moderator".to_string(), Color::Yellow),
+                ("flair12".to_string(), Color::LightYellow),
+                ("flair2".to_string(), Color::LightYellow),
+                ("admin".to_string(), Color::LightRed),
+            ]),
+            depth: ColorDepth::TrueColor,
+        }
+    }
+}
+
+fn color(value: &str, key: &str, field: &str) -> Result<Color> {
+    match parse_color(value) {
+        Some(color) => Ok(color),
+        None if field.is_empty() => bail!("{}: {} is not a colour name or #rrggbb", key, value),
+        None => bail!(
+            "{}.{}: {} is not a colour name or #rrggbb",
+            key,
+            field,
+            value
+        ),
+    }
+}
+
+fn read_theme_file(path: &Path) -> Result<ThemeFile> {
+    let text = fs::read_to_string(path)?;
+    let toml = path
+        .extension()
+        .is_some_and(|extension| extension == "toml");
+    let styles = match toml {
+        true => toml::from_str(&text)?,
+        false => serde_json::from_str(&text)?,
+    };
+    Ok(styles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chat::features::Flairs, test_util::temp_dir};
+
+    fn nick_color(theme: &Theme, features: &[&str]) -> Color {
+        let features: Vec<String> = features.iter().map(|name| name.to_string()).collect();
+        theme.nick_color(&Flairs::default().parse(&features))
+    }
+
+    #[test]
+    fn builtin_themes() {
+        for name in THEME_NAMES {
+            let theme = Theme::builtin(name).unwrap();
+            assert!(theme.window.bg.is_some());
+            assert_ne!(nick_color(&theme, &["flair13"]), nick_color(&theme, &[]));
+        }
+        assert!(Theme::builtin("neon").is_err());
+    }
+
+    #[test]
+    fn config_changes_styles() {
+        let config = ThemeConfig {
+            styles: BTreeMap::from([(
+                "mention".to_string(),
+                StyleConfig {
+                    bg: Some("red".to_string()),
+                    bold: Some(true),
+                    ..StyleConfig::default()
+                },
+            )]),
+            flairs: BTreeMap::from([("tier1".to_string(), "#ff0000".to_string())]),
+            ..ThemeConfig::default()
+        };
+        let theme = Theme::new(&config).unwrap();
+        assert_eq!(theme.mention.bg, Some(Color::Red));
+        assert!(theme.mention.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(nick_color(&theme, &["flair13"]), Color::Rgb(255, 0, 0));
+        assert_eq!(nick_color(&theme, &["subscriber"]), Color::White);
+        // the server's colour when the theme has none
+        assert_eq!(nick_color(&theme, &["flair12"]), Color::Rgb(230, 144, 20));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let mut theme = Theme::dark();
+        let styles = BTreeMap::from([(
+            "mention".to_string(),
+            StyleConfig {
+                fg: Some("nope".to_string()),
+                ..StyleConfig::default()
+            },
+        )]);
+        let err = theme
+            .apply(&styles, &BTreeMap::new(), "theme.")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("theme.styles.mention.fg: "));
+
+        let styles = BTreeMap::from([("chat".to_string(), StyleConfig::default())]);
+        let err = theme
+            .apply(&styles, &BTreeMap::new(), "theme.")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("theme.styles.chat: "));
+
+        let flairs = BTreeMap::from([("tier1".to_string(), "reddish".to_string())]);
+        let err = theme
+            .apply(&BTreeMap::new(), &flairs, "theme.")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("theme.flairs.tier1: "));
+
+        // a flair newer than the client is fine
+        let flairs = BTreeMap::from([("flair99".to_string(), "red".to_string())]);
+        theme.apply(&BTreeMap::new(), &flairs, "theme.").unwrap();
+    }
+
+    #[test]
+    fn theme_file() {
+        let dir = temp_dir("theme-file");
+        fs::write(
+            dir.join("theme.toml"),
+            "[styles.message]\nfg = \"#102030\"\n\n[flairs]\ntier2 = \"blue\"\n",
+        )
+        .unwrap();
+        let config = ThemeConfig {
+            name: "light".to_string(),
+            file: Some(PathBuf::from("theme.toml")),
+            color_depth: ColorDepth::TrueColor,
+            ..ThemeConfig::default()
+        };
+        let theme = Theme::load(&config, Some(&dir)).unwrap();
+        assert_eq!(theme.message.fg, Some(Color::Rgb(16, 32, 48)));
+        assert_eq!(theme.message.bg, Some(Color::White));
+        assert_eq!(nick_color(&theme, &["flair1"]), Color::Blue);
+
+        let config = ThemeConfig {
+            color_depth: ColorDepth::Ansi16,
+            ..config
+        };
+        let theme = Theme::load(&config, Some(&dir)).unwrap();
+        assert_eq!(theme.message.fg, Some(Color::Black));
+
+        let config = ThemeConfig {
+            file: Some(PathBuf::from("missing.toml")),
+            ..config
+        };
+        let err = Theme::load(&config, Some(&dir)).unwrap_err();
+        assert!(err.to_string().starts_with("theme.file: "));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
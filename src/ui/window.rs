@@ -1,32 +1,37 @@
 use std::ops::Range;
 
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// The scroll state of a pane, whether it's shown is up to the layout
 #[derive(PartialEq, Eq, Debug)]
 pub struct Window {
     pub window_type: WindowType,
-    pub active: bool,
     pub auto_scroll: bool,
     pub scroll: i16,
+    pub height: usize,
 }
 
 impl Window {
-    pub fn new(window_type: WindowType, active: bool) -> Self {
+    pub fn new(window_type: WindowType) -> Self {
         Window {
             window_type,
-            active,
             auto_scroll: true,
             scroll: 0,
+            height: 0,
         }
     }
 
     pub fn compute_viewport(&mut self, height: usize, list_len: usize) -> Range<usize> {
         let end = list_len;
+        self.height = height;
 
         if list_len > height {
             if self.auto_scroll {
                 self.scroll = (list_len - height) as i16;
             }
         } else {
-            return 0 as usize..end as usize;
+            return 0_usize..end;
         }
 
         // Make sure user can't scroll too far down and enable auto_scroll
@@ -49,18 +54,65 @@ impl Window {
         }
     }
 
-    /// Flips the window.active state e.g. true => false and vice versa
-    pub fn flip(&mut self) {
-        self.active = !self.active;
+    /// Scrolls so that the item at index is in the middle of the window.
+    pub fn scroll_to(&mut self, index: usize) {
+        self.auto_scroll = false;
+        self.scroll = index.saturating_sub(self.height / 2) as i16;
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WindowType {
     Chat,
     ChatInput,
     Debug,
     UserList,
+    Whispers,
+    Mentions,
+    /// The embeds of /embeds
+    Embeds,
+    /// Messages of the client like INFO and ERROR lines
+    Log,
+}
+
+impl WindowType {
+    /// The panes a layout can have, chat_input is part of the chat
+    pub const NAMES: &'static [&'static str] = &[
+        "chat",
+        "debug",
+        "user_list",
+        "whispers",
+        "mentions",
+        "embeds",
+        "log",
+    ];
+
+    pub fn parse(name: &str) -> Result<WindowType> {
+        match name {
+            "chat" => Ok(WindowType::Chat),
+            "debug" => Ok(WindowType::Debug),
+            "user_list" | "users" => Ok(WindowType::UserList),
+            "whispers" => Ok(WindowType::Whispers),
+            "mentions" => Ok(WindowType::Mentions),
+            "embeds" => Ok(WindowType::Embeds),
+            "log" => Ok(WindowType::Log),
+            _ => bail!("{} is not one of {}", name, WindowType::NAMES.join(", ")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowType::Chat => "chat",
+            WindowType::ChatInput => "chat_input",
+            WindowType::Debug => "debug",
+            WindowType::UserList => "user_list",
+            WindowType::Whispers => "whispers",
+            WindowType::Mentions => "mentions",
+            WindowType::Embeds => "embeds",
+            WindowType::Log => "log",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -68,14 +120,24 @@ pub struct WindowList {
     pub windows: Vec<Window>,
 }
 
+impl Default for WindowList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WindowList {
     pub fn new() -> WindowList {
         WindowList {
             windows: vec![
-                Window::new(WindowType::Chat, true),
-                Window::new(WindowType::ChatInput, true),
-                Window::new(WindowType::Debug, false),
-                Window::new(WindowType::UserList, false),
+                Window::new(WindowType::Chat),
+                Window::new(WindowType::ChatInput),
+                Window::new(WindowType::Debug),
+                Window::new(WindowType::UserList),
+                Window::new(WindowType::Whispers),
+                Window::new(WindowType::Mentions),
+                Window::new(WindowType::Embeds),
+                Window::new(WindowType::Log),
             ],
         }
     }
@@ -84,6 +146,10 @@ impl WindowList {
         self.windows.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
     pub fn get(&self, window_type: WindowType) -> &Window {
         self.windows
             .iter()
@@ -114,10 +180,20 @@ mod tests {
         assert_eq!(chat_input.window_type, WindowType::ChatInput);
     }
 
+    #[test]
+    fn window_scroll_to() {
+        let mut chat = Window::new(WindowType::Chat);
+        chat.compute_viewport(10, 100);
+        chat.scroll_to(50);
+        assert_eq!(chat.compute_viewport(10, 100), 45..100);
+        chat.scroll_to(98);
+        assert_eq!(chat.compute_viewport(10, 100), 90..100);
+    }
+
     #[test]
     fn window_get_mut() {
         let mut windows = WindowList::new();
         let debug = windows.get_mut(WindowType::Debug);
-        assert_eq!(debug, &mut Window::new(WindowType::Debug, false));
+        assert_eq!(debug, &mut Window::new(WindowType::Debug));
     }
 }
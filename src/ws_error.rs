@@ -1,5 +1,6 @@
 use std::{fmt::Display, time::Duration};
 
+use anyhow::{bail, Result};
 use serde_json::{Number, Value};
 
 // Taken from https://github.com/destinygg/chat/blob/df26c113dce83af6a6d902d229d7e9f8823e45ea/connection.go
@@ -20,14 +21,17 @@ pub enum WsError {
 }
 
 impl WsError {
-    pub fn from_json(json: &str) -> WsError {
-        let v: Value = serde_json::from_str(json).unwrap();
+    pub fn from_json(json: &str) -> Result<WsError> {
+        let v: Value = serde_json::from_str(json)?;
 
         if let Value::Number(mute_time_left) = &v["muteTimeLeft"] {
-            return Self::from_muted_str(mute_time_left);
+            return Ok(Self::from_muted_str(mute_time_left));
         }
 
-        Self::from_error_str(v["description"].as_str().unwrap())
+        match v["description"].as_str() {
+            Some(description) => Ok(Self::from_error_str(description)),
+            None => bail!("the error has no description"),
+        }
     }
 
     pub fn from_error_str(err: &str) -> WsError {
@@ -47,7 +51,7 @@ impl WsError {
     }
 
     pub fn from_muted_str(num: &Number) -> WsError {
-        WsError::Muted(Duration::from_secs(num.as_u64().unwrap()))
+        WsError::Muted(Duration::from_secs(num.as_u64().unwrap_or_default()))
     }
 }
 
@@ -84,7 +88,7 @@ impl Display for WsError {
             ),
             WsError::NeedLogin => write!(
                 f,
-                "You are not logged in. Check your config.json file if you should be!"
+                "You are not logged in. Anonymous mode (--anonymous or the profile's anonymous setting) can't chat."
             ),
             WsError::NoPermission => {
                 write!(f, "You do not have the required permissions for that.")